@@ -2,14 +2,14 @@
 extern crate nom;
 
 use nom::digit;
-use std::str::{self,FromStr};
+use std::str;
 
 fn main(){
     println!("hello");
-    let input = "(1+2)*(3-4)";
+    let _input = "(1+2)*(3-4)";
 }
 
-named!(unit<&str>,
+named!(unit<&'a str>,
    map_res!(digit, str::from_utf8)
 );
 
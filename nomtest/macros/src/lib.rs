@@ -0,0 +1,515 @@
+//! `query!`, a query string checked by the parser when the code is compiled
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate nomtest_macros;
+//!
+//! let query: nomtest::Query = query!("age=lt.20&order_by=name.desc");
+//! ```
+//!
+//! The string is parsed by `nomtest::parse_query` at compile time and the macro
+//! expands to the `Query` it parses into, so nothing is parsed when the code
+//! runs. A query which does not parse is a compile error at the character where
+//! the parser stopped.
+//!
+//! `#[derive(Filterable)]` makes a struct with named fields the rows of a table:
+//! it implements `nomtest::row::Row` and `nomtest::field::Filterable`, and adds
+//! a typed `nomtest::field::Field` constant for each field, `User::NAME` for
+//! `name`. The attributes are:
+//!
+//! - `#[filterable(table = "users")]` on the struct, the table is the struct
+//!   name in snake case otherwise
+//! - `#[filterable(rename = "full_name")]` on a field, the name of its column
+//! - `#[filterable(skip_filter)]` and `#[filterable(skip_sort)]` on a field, its
+//!   column can not be filtered on or sorted by, which its constant and the role
+//!   of `Filterable` follow
+
+extern crate nomtest;
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::{quote, quote_spanned};
+use syn::{Data, DeriveInput, Fields, Ident, LitStr};
+
+use nomtest::{Condition, Direction, Equality, Equation, Filter, Join, JoinType, Modifier, NullsWhere, Operand,
+              Order, ParseError, Query, Range, Seek};
+use nomtest::expr::Expr;
+use nomtest::relative::{Anchor, RelativeTime, Unit};
+
+#[proc_macro]
+pub fn query(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let literal = match syn::parse::<LitStr>(input) {
+        Ok(literal) => literal,
+        Err(e) => return compile_error(e.span(), &e.to_string()).into(),
+    };
+    let value = literal.value();
+    match nomtest::parse_query(&value) {
+        Ok(query) => match non_finite(&query) {
+            Some(n) => compile_error(literal.span(), &format!("invalid query: {} is not a finite number", n)).into(),
+            None => query_tokens(&query).into(),
+        },
+        Err(e) => {
+            let span = match e {
+                ParseError::Syntax(position) => character_span(&literal, &value, position),
+                _ => literal.span(),
+            };
+            compile_error(span, &message(&value, &e)).into()
+        }
+    }
+}
+
+#[proc_macro_derive(Filterable, attributes(filterable))]
+pub fn derive_filterable(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = match syn::parse::<DeriveInput>(input) {
+        Ok(input) => input,
+        Err(e) => return compile_error(e.span(), &e.to_string()).into(),
+    };
+    match filterable(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => compile_error(e.span(), &e.to_string()).into(),
+    }
+}
+
+/// a field of the struct and the column it is
+struct FieldColumn {
+    ident: Ident,
+    ty: syn::Type,
+    column: String,
+    filter: bool,
+    sort: bool,
+}
+
+fn filterable(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let mut table = snake_case(&input.ident.to_string());
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("filterable")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table") {
+                table = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `table = \"...\"`"))
+            }
+        })?;
+    }
+    let named = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new(input.ident.span(), "Filterable needs a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new(input.ident.span(), "Filterable needs a struct with named fields")),
+    };
+    let mut fields = vec![];
+    for field in named {
+        let ident = field.ident.clone().expect("a named field");
+        let mut column = FieldColumn {
+            column: ident.to_string().trim_start_matches("r#").to_string(),
+            ident,
+            ty: field.ty.clone(),
+            filter: true,
+            sort: true,
+        };
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("filterable")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    column.column = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("skip_filter") {
+                    column.filter = false;
+                } else if meta.path.is_ident("skip_sort") {
+                    column.sort = false;
+                } else {
+                    return Err(meta.error("expected `rename = \"...\"`, `skip_filter` or `skip_sort`"));
+                }
+                Ok(())
+            })?;
+        }
+        fields.push(column);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let constants = fields.iter().filter(|field| field.filter || field.sort).map(|field| {
+        let constant = Ident::new(&field.ident.to_string().trim_start_matches("r#").to_uppercase(), field.ident.span());
+        let ty = &field.ty;
+        let column = &field.column;
+        let kind = if field.filter {
+            quote!(<#ty as ::nomtest::field::FieldType>::Kind)
+        } else {
+            quote!(::nomtest::field::Opaque)
+        };
+        let sorted = if field.sort { quote!(::nomtest::field::Sorted) } else { quote!(::nomtest::field::Unsorted) };
+        let doc = format!("the `{}` column", column);
+        quote! {
+            #[doc = #doc]
+            pub const #constant: ::nomtest::field::Field<#kind, #sorted> = ::nomtest::field::Field::new(#column);
+        }
+    });
+    let getters = fields.iter().map(|field| {
+        let (ident, column) = (&field.ident, &field.column);
+        quote!(#column => ::std::option::Option::Some(::nomtest::field::FieldType::to_value(&self.#ident)))
+    });
+    let column_names = fields.iter().map(|field| string_tokens(&field.column));
+    let columns = fields.iter().map(|field| {
+        let name = string_tokens(&field.column);
+        let ty = &field.ty;
+        quote!(::nomtest::schema::Column {
+            name: #name,
+            column_type: <#ty as ::nomtest::field::FieldType>::column_type(),
+            nullable: <#ty as ::nomtest::field::FieldType>::nullable(),
+        })
+    });
+    let table = string_tokens(&table);
+    let rule = |columns: Vec<&String>| {
+        if columns.is_empty() {
+            quote!(::nomtest::policy::ColumnRule::All)
+        } else {
+            let columns = columns.into_iter().map(|column| string_tokens(column));
+            quote!(::nomtest::policy::ColumnRule::Deny(vec![#(#columns),*]))
+        }
+    };
+    let filter_rule = rule(fields.iter().filter(|field| !field.filter).map(|field| &field.column).collect());
+    let order_rule = rule(fields.iter().filter(|field| !field.sort).map(|field| &field.column).collect());
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#constants)*
+        }
+
+        impl #impl_generics ::nomtest::row::Row for #name #ty_generics #where_clause {
+            fn get(&self, column: &str) -> ::std::option::Option<::nomtest::row::Value> {
+                match column {
+                    #(#getters,)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            fn columns(&self) -> ::std::vec::Vec<::std::string::String> {
+                vec![#(#column_names),*]
+            }
+        }
+
+        impl #impl_generics ::nomtest::field::Filterable for #name #ty_generics #where_clause {
+            fn table() -> ::nomtest::schema::Table {
+                ::nomtest::schema::Table {
+                    name: #table,
+                    columns: vec![#(#columns),*],
+                    foreign_keys: vec![],
+                }
+            }
+
+            fn role() -> ::nomtest::policy::Role {
+                ::nomtest::policy::Role {
+                    filter: #filter_rule,
+                    order: #order_rule,
+                    ..::std::default::Default::default()
+                }
+            }
+        }
+    })
+}
+
+/// `UserAccount` is `user_account`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// `compile_error!` without a path, syn puts `::core::` in front of it which
+/// crates of the 2015 edition do not have
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    quote_spanned!(span=> compile_error!(#message))
+}
+
+/// the error, with the query and a caret under the character when there is one
+fn message(value: &str, e: &ParseError) -> String {
+    match *e {
+        ParseError::Syntax(position) => {
+            let column = value.get(..position).map_or(position, |before| before.chars().count());
+            format!("invalid query: {}\n  {}\n  {}^", e, value, " ".repeat(column))
+        }
+        _ => format!("invalid query: {}", e),
+    }
+}
+
+/// the span of the character at the byte offset of the value, the whole literal
+/// when the compiler can not point inside of it or the literal has escapes in it
+fn character_span(literal: &LitStr, value: &str, position: usize) -> Span {
+    let token = literal.token();
+    if token.to_string() != format!("\"{}\"", value) {
+        return literal.span();
+    }
+    let end = value[position..].chars().next().map_or(position, |c| position + c.len_utf8());
+    // the opening quote comes first
+    token.subspan(position + 1..end.max(position + 1) + 1).unwrap_or_else(|| literal.span())
+}
+
+/// the first number of the query which is infinite or not a number, a literal
+/// can not be made of it
+fn non_finite(query: &Query) -> Option<f64> {
+    fn find(operand: &Operand) -> Option<f64> {
+        match *operand {
+            Operand::Number(n) if !n.is_finite() => Some(n),
+            Operand::Function(ref function) => function.params.iter().filter_map(find).next(),
+            Operand::List(ref items) => items.iter().filter_map(find).next(),
+            _ => None,
+        }
+    }
+    let filters = Expr::from_filters(&query.filters);
+    let having = Expr::from_filters(&query.having);
+    let conditions = filters.iter().chain(having.iter()).flat_map(Expr::conditions);
+    let mut operands: Vec<&Operand> = conditions.flat_map(|condition| vec![&condition.left, &condition.right]).collect();
+    operands.extend(query.group_by.iter().chain(query.order_by.iter().map(|order| &order.operand)));
+    operands.extend(query.equations.iter().map(|equation| &equation.right));
+    operands.into_iter().filter_map(find).next()
+}
+
+fn query_tokens(query: &Query) -> TokenStream {
+    let from = query.from.iter().map(operand_tokens);
+    let join = query.join.iter().map(join_tokens);
+    let filters = query.filters.iter().map(filter_tokens);
+    let group_by = query.group_by.iter().map(operand_tokens);
+    let having = query.having.iter().map(filter_tokens);
+    let order_by = query.order_by.iter().map(order_tokens);
+    let range = option_tokens(query.range.as_ref().map(range_tokens));
+    let equations = query.equations.iter().map(equation_tokens);
+    quote! {
+        ::nomtest::Query {
+            from: vec![#(#from),*],
+            join: vec![#(#join),*],
+            filters: vec![#(#filters),*],
+            group_by: vec![#(#group_by),*],
+            having: vec![#(#having),*],
+            order_by: vec![#(#order_by),*],
+            range: #range,
+            equations: vec![#(#equations),*],
+        }
+    }
+}
+
+fn option_tokens(option: Option<TokenStream>) -> TokenStream {
+    match option {
+        Some(tokens) => quote!(::std::option::Option::Some(#tokens)),
+        None => quote!(::std::option::Option::None),
+    }
+}
+
+fn string_tokens(s: &str) -> TokenStream {
+    quote!(::std::string::String::from(#s))
+}
+
+fn operand_tokens(operand: &Operand) -> TokenStream {
+    match *operand {
+        Operand::Column(ref column) => {
+            let column = string_tokens(column);
+            quote!(::nomtest::Operand::Column(#column))
+        }
+        Operand::Function(ref function) => {
+            let name = string_tokens(&function.function);
+            let params = function.params.iter().map(operand_tokens);
+            quote!(::nomtest::Operand::Function(::nomtest::Function {
+                function: #name,
+                params: vec![#(#params),*],
+            }))
+        }
+        Operand::Number(n) => {
+            let n = Literal::f64_suffixed(n);
+            quote!(::nomtest::Operand::Number(#n))
+        }
+        Operand::Boolean(b) => quote!(::nomtest::Operand::Boolean(#b)),
+        Operand::Value(ref value) => {
+            let value = string_tokens(value);
+            quote!(::nomtest::Operand::Value(#value))
+        }
+        Operand::Relative(ref time) => relative_tokens(time),
+        Operand::List(ref items) => {
+            let items = items.iter().map(operand_tokens);
+            quote!(::nomtest::Operand::List(vec![#(#items),*]))
+        }
+    }
+}
+
+fn relative_tokens(time: &RelativeTime) -> TokenStream {
+    let anchor = match time.anchor {
+        Anchor::Now => quote!(Now),
+        Anchor::Today => quote!(Today),
+        Anchor::StartOfMonth => quote!(StartOfMonth),
+    };
+    let shifts = time.shifts.iter().map(|shift| {
+        let amount = shift.amount;
+        let unit = match shift.unit {
+            Unit::Second => quote!(Second),
+            Unit::Minute => quote!(Minute),
+            Unit::Hour => quote!(Hour),
+            Unit::Day => quote!(Day),
+            Unit::Week => quote!(Week),
+            Unit::Month => quote!(Month),
+            Unit::Year => quote!(Year),
+        };
+        quote!(::nomtest::relative::Shift { amount: #amount, unit: ::nomtest::relative::Unit::#unit })
+    });
+    quote!(::nomtest::Operand::Relative(::nomtest::relative::RelativeTime {
+        anchor: ::nomtest::relative::Anchor::#anchor,
+        shifts: vec![#(#shifts),*],
+    }))
+}
+
+fn equality_tokens(equality: &Equality) -> TokenStream {
+    let variant = match *equality {
+        Equality::EQ => quote!(EQ),
+        Equality::NEQ => quote!(NEQ),
+        Equality::LT => quote!(LT),
+        Equality::LTE => quote!(LTE),
+        Equality::GT => quote!(GT),
+        Equality::GTE => quote!(GTE),
+        Equality::IN => quote!(IN),
+        Equality::NOT_IN => quote!(NOT_IN),
+        Equality::IS => quote!(IS),
+        Equality::IS_NOT => quote!(IS_NOT),
+        Equality::LIKE => quote!(LIKE),
+        Equality::ILIKE => quote!(ILIKE),
+        Equality::ST => quote!(ST),
+        Equality::FTS(_) => quote!(FTS),
+        Equality::PLFTS(_) => quote!(PLFTS),
+        Equality::PHFTS(_) => quote!(PHFTS),
+        Equality::WFTS(_) => quote!(WFTS),
+        Equality::MATCH => quote!(MATCH),
+        Equality::IMATCH => quote!(IMATCH),
+    };
+    if equality.is_full_text() {
+        let language = option_tokens(equality.language().map(string_tokens));
+        return quote!(::nomtest::Equality::#variant(#language));
+    }
+    quote!(::nomtest::Equality::#variant)
+}
+
+fn condition_tokens(condition: &Condition) -> TokenStream {
+    let left = operand_tokens(&condition.left);
+    let equality = equality_tokens(&condition.equality);
+    let right = operand_tokens(&condition.right);
+    quote!(::nomtest::Condition {
+        left: #left,
+        equality: #equality,
+        right: #right,
+    })
+}
+
+fn filter_tokens(filter: &Filter) -> TokenStream {
+    let condition = condition_tokens(&filter.condition);
+    let connector = option_tokens(filter.connector.as_ref().map(|connector| match *connector {
+        nomtest::Connector::AND => quote!(::nomtest::Connector::AND),
+        nomtest::Connector::OR => quote!(::nomtest::Connector::OR),
+    }));
+    let sub_filters = filter.sub_filters.iter().map(filter_tokens);
+    quote!(::nomtest::Filter {
+        condition: #condition,
+        connector: #connector,
+        sub_filters: vec![#(#sub_filters),*],
+    })
+}
+
+fn order_tokens(order: &Order) -> TokenStream {
+    let operand = operand_tokens(&order.operand);
+    let direction = option_tokens(order.direction.as_ref().map(|direction| match *direction {
+        Direction::ASC => quote!(::nomtest::Direction::ASC),
+        Direction::DESC => quote!(::nomtest::Direction::DESC),
+    }));
+    let nulls_where = option_tokens(order.nulls_where.as_ref().map(|nulls_where| match *nulls_where {
+        NullsWhere::FIRST => quote!(::nomtest::NullsWhere::FIRST),
+        NullsWhere::LAST => quote!(::nomtest::NullsWhere::LAST),
+    }));
+    quote!(::nomtest::Order {
+        operand: #operand,
+        direction: #direction,
+        nulls_where: #nulls_where,
+    })
+}
+
+fn range_tokens(range: &Range) -> TokenStream {
+    match *range {
+        Range::Page(ref page) => {
+            let (number, size) = (page.page, page.page_size);
+            quote!(::nomtest::Range::Page(::nomtest::Page { page: #number, page_size: #size }))
+        }
+        Range::Limit(ref limit) => {
+            let number = limit.limit;
+            let offset = option_tokens(limit.offset.map(|offset| quote!(#offset)));
+            quote!(::nomtest::Range::Limit(::nomtest::Limit { limit: #number, offset: #offset }))
+        }
+        Range::Keyset(ref keyset) => {
+            let seek = match keyset.seek {
+                Seek::After => quote!(::nomtest::Seek::After),
+                Seek::Before => quote!(::nomtest::Seek::Before),
+            };
+            let cursor = &keyset.cursor;
+            let limit = option_tokens(keyset.limit.map(|limit| quote!(#limit)));
+            quote!(::nomtest::Range::Keyset(::nomtest::Keyset {
+                seek: #seek,
+                cursor: #cursor.to_string(),
+                limit: #limit,
+            }))
+        }
+    }
+}
+
+fn equation_tokens(equation: &Equation) -> TokenStream {
+    let left = operand_tokens(&equation.left);
+    let right = operand_tokens(&equation.right);
+    quote!(::nomtest::Equation { left: #left, right: #right })
+}
+
+fn join_tokens(join: &Join) -> TokenStream {
+    let modifier = option_tokens(join.modifier.as_ref().map(|modifier| match *modifier {
+        Modifier::LEFT => quote!(::nomtest::Modifier::LEFT),
+        Modifier::RIGHT => quote!(::nomtest::Modifier::RIGHT),
+        Modifier::FULL => quote!(::nomtest::Modifier::FULL),
+    }));
+    let join_type = option_tokens(join.join_type.as_ref().map(|join_type| match *join_type {
+        JoinType::CROSS => quote!(::nomtest::JoinType::CROSS),
+        JoinType::INNER => quote!(::nomtest::JoinType::INNER),
+        JoinType::OUTER => quote!(::nomtest::JoinType::OUTER),
+        JoinType::NATURAL => quote!(::nomtest::JoinType::NATURAL),
+    }));
+    let table = operand_tokens(&join.table);
+    let column1 = join.column1.iter().map(|column| string_tokens(column));
+    let column2 = join.column2.iter().map(|column| string_tokens(column));
+    quote!(::nomtest::Join {
+        modifier: #modifier,
+        join_type: #join_type,
+        table: #table,
+        column1: vec![#(#column1),*],
+        column2: vec![#(#column2),*],
+    })
+}
+
+#[test]
+fn test_message() {
+    let e = nomtest::parse_query("age=lt.20&&").unwrap_err();
+    assert_eq!(message("age=lt.20&&", &e),
+               "invalid query: syntax error at position 10\n  age=lt.20&&\n            ^");
+    let e = nomtest::parse_query("offset=10").unwrap_err();
+    assert_eq!(message("offset=10", &e), "invalid query: invalid range: offset needs a limit");
+}
+
+#[test]
+fn test_non_finite() {
+    let mut query = nomtest::parse_query("age=lt.20&order_by=sum(x)").unwrap();
+    assert_eq!(non_finite(&query), None);
+    query.filters[0].condition.right = Operand::Number(f64::INFINITY);
+    assert_eq!(non_finite(&query), Some(f64::INFINITY));
+    // the numerals too long for a f64 are texts
+    assert_eq!(non_finite(&nomtest::parse_query(&format!("age=lt.{}", "9".repeat(400))).unwrap()), None);
+}
+
+#[test]
+fn test_snake_case() {
+    assert_eq!(snake_case("User"), "user");
+    assert_eq!(snake_case("UserAccount"), "user_account");
+}
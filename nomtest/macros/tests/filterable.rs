@@ -0,0 +1,88 @@
+#[macro_use]
+extern crate nomtest_macros;
+extern crate chrono;
+extern crate nomtest;
+
+use chrono::NaiveDate;
+
+use nomtest::field::Filterable;
+use nomtest::policy::{Policy, Usage};
+use nomtest::row::{Row, Value};
+use nomtest::schema::{self, Schema, Type};
+use nomtest::Query;
+
+#[derive(Filterable)]
+#[filterable(table = "users")]
+struct User {
+    id: i64,
+    #[filterable(rename = "full_name")]
+    name: String,
+    active: bool,
+    #[filterable(skip_sort)]
+    deleted_at: Option<NaiveDate>,
+    #[filterable(skip_filter)]
+    score: f64,
+}
+
+#[derive(Filterable)]
+struct AuditEntry {
+    message: String,
+}
+
+fn user() -> User {
+    User {
+        id: 1,
+        name: "John".to_string(),
+        active: true,
+        deleted_at: None,
+        score: 4.5,
+    }
+}
+
+#[test]
+fn test_fields() {
+    let query = Query::from("users")
+        .filter(User::NAME.starts_with("Jo").and(User::DELETED_AT.is_null()).and(User::ID.gt(0)))
+        .order_by(User::SCORE.desc());
+    assert_eq!(query,
+               nomtest::parse_query("full_name=like.Jo*&deleted_at=is.null&id=gt.0&from=users&order_by=score.desc")
+                   .unwrap());
+    assert_eq!(User::ACTIVE.is(true).to_string(), "active=is.true");
+    assert_eq!(User::DELETED_AT.lt(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()).to_string(),
+               "deleted_at=lt.2024-01-01");
+    assert_eq!(User::NAME.name(), "full_name");
+}
+
+#[test]
+fn test_row() {
+    let user = user();
+    assert_eq!(user.get("full_name"), Some(Value::Text("John".to_string())));
+    assert_eq!(user.get("deleted_at"), Some(Value::Null));
+    assert_eq!(user.get("name"), None);
+    assert_eq!(user.columns(), vec!["id", "full_name", "active", "deleted_at", "score"]);
+    let query = Query::default().filter(User::NAME.eq("John").and(User::ACTIVE.is(true)));
+    assert!(nomtest::eval::matches_all(&query.filters, &user));
+    let query = Query::default().filter(User::DELETED_AT.is_not_null());
+    assert!(!nomtest::eval::matches_all(&query.filters, &user));
+}
+
+#[test]
+fn test_schema() {
+    let table = User::table();
+    assert_eq!(table.name, "users");
+    assert_eq!(table.columns.iter().map(|column| (column.column_type, column.nullable)).collect::<Vec<_>>(),
+               vec![(Type::Integer, false), (Type::Text, false), (Type::Bool, false), (Type::Text, true),
+                    (Type::Number, false)]);
+    assert_eq!(AuditEntry::table().name, "audit_entry");
+    let schema = Schema { tables: vec![table] };
+    assert!(schema::validate(&nomtest::parse_query("full_name=like.J*&from=users").unwrap(), &schema).is_ok());
+    assert!(schema::validate(&nomtest::parse_query("id=eq.x&from=users").unwrap(), &schema).is_err());
+
+    let mut policy = Policy::default();
+    policy.roles.insert("client".to_string(), User::role());
+    let apply = |input: &str| policy.apply("client", &nomtest::parse_query(input).unwrap(), &Default::default());
+    assert!(apply("id=eq.1&order_by=full_name").is_ok());
+    assert_eq!(apply("order_by=deleted_at"),
+               Err(nomtest::policy::Error::Denied("deleted_at".to_string(), Usage::Order)));
+    assert_eq!(apply("score=gt.1"), Err(nomtest::policy::Error::Denied("score".to_string(), Usage::Filter)));
+}
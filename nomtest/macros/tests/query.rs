@@ -0,0 +1,27 @@
+#[macro_use]
+extern crate nomtest_macros;
+extern crate nomtest;
+
+use nomtest::{parse_query, Query};
+
+#[test]
+fn test_query() {
+    let query: Query = query!("age=lt.20&order_by=name.desc");
+    assert_eq!(query, parse_query("age=lt.20&order_by=name.desc").unwrap());
+    let input = "age=lt.20&(product=eq.134|price=lt.100.5)&from=users&group_by=dept&having=count(*)=gt.1\
+                 &order_by=name.desc.nulls_last,age&limit=10&offset=20&format=csv";
+    assert_eq!(query!("age=lt.20&(product=eq.134|price=lt.100.5)&from=users&group_by=dept&having=count(*)=gt.1\
+                       &order_by=name.desc.nulls_last,age&limit=10&offset=20&format=csv"),
+               parse_query(input).unwrap());
+    assert_eq!(query!("name=eq.技術通報|active=is.true&page=2&page_size=50"),
+               parse_query("name=eq.技術通報|active=is.true&page=2&page_size=50").unwrap());
+    assert_eq!(query!("body=fts(english).fat&cat&title=wfts.\"fat cat\""),
+               parse_query("body=fts(english).fat&cat&title=wfts.\"fat cat\"").unwrap());
+    assert_eq!(query!("name=imatch.^jo(hn|e)$&nick=match.[&|]"),
+               parse_query("name=imatch.^jo(hn|e)$&nick=match.[&|]").unwrap());
+    assert_eq!(query!("created_at=gt.now-7d&due=in.@today+1w,@start_of_month+1mo-1s"),
+               parse_query("created_at=gt.now-7d&due=in.@today+1w,@start_of_month+1mo-1s").unwrap());
+    assert_eq!(query!("order_by=id&before=eyJhIjoxfQ.c2ln&limit=5"),
+               parse_query("order_by=id&before=eyJhIjoxfQ.c2ln&limit=5").unwrap());
+    assert_eq!(query!(""), Query::default());
+}
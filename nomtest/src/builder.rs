@@ -0,0 +1,331 @@
+//! Building a query in rust, without writing out the filters by hand
+//!
+//! ```
+//! use nomtest::Query;
+//! use nomtest::builder::col;
+//!
+//! let query = Query::from("users")
+//!     .filter(col("age").lt(20).and(col("name").starts_with("Jo")))
+//!     .order_by(col("name").desc().nulls_last())
+//!     .page(2, 50);
+//! assert_eq!(query, nomtest::parse_query("age=lt.20&name=like.Jo*&from=users\
+//!                                         &order_by=name.desc.nulls_last&page=2&page_size=50").unwrap());
+//! ```
+//!
+//! The query built is the one the parser makes of the same query string, but for
+//! the texts: the parser types a literal by the way it is written, the builder by
+//! the rust value. `col("a").eq("5")` is the text `5` where `a=eq.5` is the number
+//! 5, both write out as `a=eq.5`; use `col("a").eq(5)` for the number. A filter
+//! chain can not start with a parenthesized group, `(a|b)&c` is built as `c&(a|b)`.
+
+use expr::Expr;
+use {Condition, Direction, Equality, Equation, Function, Limit, NullsWhere, Operand, Order, Page, Query, Range};
+
+/// a column, or a function of columns, to make conditions and orders with
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Column {
+    operand: Operand,
+}
+
+pub fn col(name: &str) -> Column {
+    Column { operand: Operand::Column(name.to_string()) }
+}
+
+/// a function of columns, `call("count", &["*"])` is `count(*)`
+pub fn call(function: &str, params: &[&str]) -> Column {
+    let params = params.iter()
+        .map(|param| match *param {
+            "*" => Operand::Value("*".to_string()),
+            column => Operand::Column(column.to_string()),
+        })
+        .collect();
+    Column {
+        operand: Operand::Function(Function {
+            function: function.to_string(),
+            params,
+        }),
+    }
+}
+
+impl From<Column> for Operand {
+    fn from(column: Column) -> Self {
+        column.operand
+    }
+}
+
+impl Column {
+    fn condition<O: Into<Operand>>(self, equality: Equality, right: O) -> Expr {
+        Expr::Condition(Condition {
+            left: self.operand,
+            equality,
+            right: right.into(),
+        })
+    }
+
+    pub fn eq<O: Into<Operand>>(self, value: O) -> Expr {
+        self.condition(Equality::EQ, value)
+    }
+
+    pub fn neq<O: Into<Operand>>(self, value: O) -> Expr {
+        self.condition(Equality::NEQ, value)
+    }
+
+    pub fn lt<O: Into<Operand>>(self, value: O) -> Expr {
+        self.condition(Equality::LT, value)
+    }
+
+    pub fn lte<O: Into<Operand>>(self, value: O) -> Expr {
+        self.condition(Equality::LTE, value)
+    }
+
+    pub fn gt<O: Into<Operand>>(self, value: O) -> Expr {
+        self.condition(Equality::GT, value)
+    }
+
+    pub fn gte<O: Into<Operand>>(self, value: O) -> Expr {
+        self.condition(Equality::GTE, value)
+    }
+
+    /// a `Vec` or a slice is the list, `id=in.1,2,3`
+    pub fn is_in<O: Into<Operand>>(self, values: O) -> Expr {
+        self.condition(Equality::IN, Operand::List(values.into().list_items()))
+    }
+
+    /// a `Vec` or a slice is the list, `id=not_in.1,2,3`
+    pub fn not_in<O: Into<Operand>>(self, values: O) -> Expr {
+        self.condition(Equality::NOT_IN, Operand::List(values.into().list_items()))
+    }
+
+    pub fn is_null(self) -> Expr {
+        self.condition(Equality::IS, "null")
+    }
+
+    pub fn is_not_null(self) -> Expr {
+        self.condition(Equality::IS_NOT, "null")
+    }
+
+    /// `is.true` or `is.false`
+    pub fn is(self, value: bool) -> Expr {
+        self.condition(Equality::IS, value)
+    }
+
+    /// `*` in the pattern is the wildcard
+    pub fn like(self, pattern: &str) -> Expr {
+        self.condition(Equality::LIKE, Operand::Value(pattern.to_string()))
+    }
+
+    pub fn ilike(self, pattern: &str) -> Expr {
+        self.condition(Equality::ILIKE, Operand::Value(pattern.to_string()))
+    }
+
+    /// `like.Jo*`, a `*` in the prefix is still a wildcard
+    pub fn starts_with(self, prefix: &str) -> Expr {
+        self.like(&format!("{}*", prefix))
+    }
+
+    /// `st.jo`, case insensitive
+    pub fn istarts_with(self, prefix: &str) -> Expr {
+        self.condition(Equality::ST, Operand::Value(prefix.to_string()))
+    }
+
+    pub fn asc(self) -> Order {
+        self.order(Some(Direction::ASC))
+    }
+
+    pub fn desc(self) -> Order {
+        self.order(Some(Direction::DESC))
+    }
+
+    fn order(self, direction: Option<Direction>) -> Order {
+        Order {
+            operand: self.operand,
+            direction,
+            nulls_where: None,
+        }
+    }
+}
+
+/// the column in the order of the database
+impl From<Column> for Order {
+    fn from(column: Column) -> Self {
+        column.order(None)
+    }
+}
+
+impl Order {
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls_where = Some(NullsWhere::FIRST);
+        self
+    }
+
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls_where = Some(NullsWhere::LAST);
+        self
+    }
+}
+
+impl Expr {
+    pub fn and(self, other: Expr) -> Expr {
+        join(self, other, true)
+    }
+
+    pub fn or(self, other: Expr) -> Expr {
+        join(self, other, false)
+    }
+}
+
+/// the two expressions in one AND or OR, those of the same kind are flattened
+/// so `a.and(b).and(c)` is `a&b&c` like the parser reads it
+fn join(left: Expr, right: Expr, and: bool) -> Expr {
+    let mut exprs = vec![];
+    for expr in [left, right] {
+        match expr {
+            Expr::And(inner) if and => exprs.extend(inner),
+            Expr::Or(inner) if !and => exprs.extend(inner),
+            expr => exprs.push(expr),
+        }
+    }
+    if and { Expr::And(exprs) } else { Expr::Or(exprs) }
+}
+
+/// a query of the table
+impl<'a> From<&'a str> for Query {
+    fn from(table: &'a str) -> Self {
+        Query {
+            from: vec![Operand::Column(table.to_string())],
+            ..Query::default()
+        }
+    }
+}
+
+impl Query {
+    /// the filter AND-ed to the filters, in the same chain like `a=eq.1&b=eq.2` is
+    pub fn filter(mut self, expr: Expr) -> Self {
+        self.filters = and_filters(&self.filters, expr);
+        self
+    }
+
+    pub fn group_by(mut self, column: Column) -> Self {
+        self.group_by.push(column.operand);
+        self
+    }
+
+    pub fn having(mut self, expr: Expr) -> Self {
+        self.having = and_filters(&self.having, expr);
+        self
+    }
+
+    pub fn order_by<O: Into<Order>>(mut self, order: O) -> Self {
+        self.order_by.push(order.into());
+        self
+    }
+
+    /// pages start at 1
+    pub fn page(mut self, page: i64, page_size: i64) -> Self {
+        self.range = Some(Range::Page(Page { page, page_size }));
+        self
+    }
+
+    pub fn limit(mut self, limit: i64) -> Self {
+        let offset = match self.range {
+            Some(Range::Limit(ref limit)) => limit.offset,
+            _ => None,
+        };
+        self.range = Some(Range::Limit(Limit { limit, offset }));
+        self
+    }
+
+    /// the rows skipped, a page is replaced by a limit of its size. Until a
+    /// limit is set there is none, `limit` can come before or after
+    pub fn offset(mut self, offset: i64) -> Self {
+        let limit = match self.range {
+            Some(Range::Limit(ref limit)) => limit.limit,
+            Some(Range::Page(ref page)) => page.page_size,
+            _ => i64::MAX,
+        };
+        self.range = Some(Range::Limit(Limit { limit, offset: Some(offset) }));
+        self
+    }
+
+    /// an equation, `format=csv`
+    pub fn equation<O: Into<Operand>>(mut self, name: &str, value: O) -> Self {
+        self.equations.push(Equation {
+            left: Operand::Column(name.to_string()),
+            right: value.into(),
+        });
+        self
+    }
+}
+
+/// the filters with the expression AND-ed to the last one, or apart when the
+/// two can not be a single chain
+fn and_filters(filters: &[::Filter], expr: Expr) -> Vec<::Filter> {
+    let mut filters = filters.to_vec();
+    let expr = match filters.pop() {
+        Some(last) => Expr::from(&last).and(expr),
+        None => expr,
+    };
+    filters.extend(expr.to_filters());
+    filters
+}
+
+#[cfg(test)]
+fn parsed(input: &str) -> Query {
+    ::parse_query(input).unwrap()
+}
+
+#[test]
+fn test_builder() {
+    let query = Query::from("users")
+        .filter(col("age").lt(20).and(col("name").starts_with("Jo")))
+        .order_by(col("name").desc().nulls_last())
+        .page(2, 50);
+    assert_eq!(query, parsed("age=lt.20&name=like.Jo*&from=users&order_by=name.desc.nulls_last&page=2&page_size=50"));
+    let query = Query::default()
+        .filter(col("a").eq(1).or(col("b").eq("x").and(col("c").is_in(vec![1, 2, 3]))))
+        .filter(col("d").is_null())
+        .filter(col("e").gte(1.5).or(col("f").is(true)))
+        .limit(10)
+        .offset(20)
+        .equation("format", "csv");
+    assert_eq!(query, parsed("d=is.null&(a=eq.1|b=eq.x&c=in.1,2,3)&(e=gte.1.5|f=is.true)\
+                            &limit=10&offset=20&format=csv"));
+    let query = Query::default()
+        .group_by(col("dept"))
+        .having(call("count", &["*"]).gt(1))
+        .order_by(col("dept"))
+        .order_by(col("age").asc().nulls_first());
+    assert_eq!(query, parsed("group_by=dept&having=count(*)=gt.1&order_by=dept,age.asc.nulls_first"));
+    assert_eq!(Query::default().filter(col("id").not_in(vec!["a", "b"])).filter(col("n").istarts_with("jo")),
+               parsed("id=not_in.a,b&n=st.jo"));
+    assert_eq!(Query::default().filter(col("id").is_in(7)), parsed("id=in.7"));
+    // a text stays a text, where the parser reads a number
+    let text = Query::default().filter(col("a").eq("5"));
+    assert_eq!(text.filters[0].condition.right, Operand::Value("5".to_string()));
+    assert_eq!(parsed("a=eq.5").filters[0].condition.right, Operand::Number(5.0));
+    assert_eq!(text.to_string(), "a=eq.5");
+    assert_eq!(Query::default().filter(col("a").eq(5)), parsed("a=eq.5"));
+}
+
+#[test]
+fn test_builder_groups() {
+    // a chain starts with a condition, the group goes after it
+    let query = Query::default().filter(col("a").eq(1).or(col("b").eq(2)).and(col("c").eq(3)));
+    assert_eq!(query, parsed("c=eq.3&(a=eq.1|b=eq.2)"));
+    let or = || col("a").eq(1).or(col("b").eq(2));
+    let query = Query::default().filter(or()).filter(or());
+    assert_eq!(query.filters.len(), 2);
+    assert_eq!(Expr::from_filters(&query.filters), Some(or().and(or())));
+    let query = Query::default()
+        .filter(col("a").eq(1).or(col("b").eq(2)).and(col("c").eq(3).or(col("d").eq(4))).or(col("e").eq(5)));
+    assert_eq!(query, parsed("a=eq.1&(c=eq.3|d=eq.4)|b=eq.2&(c=eq.3|d=eq.4)|e=eq.5"));
+}
+
+#[test]
+fn test_builder_offset() {
+    assert_eq!(Query::default().offset(20).limit(10), parsed("limit=10&offset=20"));
+    assert_eq!(Query::default().limit(10).offset(20), parsed("limit=10&offset=20"));
+    assert_eq!(Query::default().page(3, 10).offset(5), parsed("limit=10&offset=5"));
+}
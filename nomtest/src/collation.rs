@@ -0,0 +1,261 @@
+//! How the in-memory evaluator compares and orders text
+//!
+//! By default texts are compared by their code points, as they are. A
+//! collation can put them in a unicode normal form first, fold their case,
+//! ignore their accents and order them like a language does. It applies to
+//! the comparisons, `in`, `like`, `ilike`, `st` and the full text search,
+//! to the order of the rows and to `min` and `max`. A regular expression
+//! only sees the normal form of the text.
+//!
+//! A collation is chosen for the whole query, and a column can have one of
+//! its own:
+//!
+//! ```
+//! use nomtest::collation::{Collation, Collations, Normalization};
+//!
+//! let mut collations = Collations::new(Collation {
+//!     normalization: Some(Normalization::Nfkc),
+//!     ..Default::default()
+//! });
+//! collations.columns.insert("name".to_string(), Collation::insensitive());
+//! ```
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use caseless;
+use icu_collator::{CaseLevel, Collator, CollatorOptions, Strength};
+use icu_locid::Locale;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use row::Value;
+use Operand;
+
+/// a unicode normal form
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Normalization {
+    /// canonical composition, `e` and a combining acute accent are `é`
+    Nfc,
+    /// compatibility composition, `ﬁ` is also `fi` and `①` is `1`
+    Nfkc,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Collation {
+    /// the normal form the texts are put in, None leaves them as they are
+    pub normalization: Option<Normalization>,
+    /// full unicode case folding, `Straße` is `STRASSE`
+    pub case_insensitive: bool,
+    /// the accents and the other combining marks are ignored, `café` is `cafe`
+    pub accent_insensitive: bool,
+    /// the order of a language, `ä` is after `a` in german and after `z` in
+    /// swedish. None is the order of the code points
+    pub locale: Option<Locale>,
+}
+
+impl Collation {
+    /// case and accent insensitive, in the normal form NFKC
+    pub fn insensitive() -> Self {
+        Collation {
+            normalization: Some(Normalization::Nfkc),
+            case_insensitive: true,
+            accent_insensitive: true,
+            locale: None,
+        }
+    }
+
+    /// the order of a language, `de`, `sv` or `fr-CA`
+    pub fn locale(locale: &str) -> Result<Self, CollationError> {
+        let locale = Locale::from_str(locale).map_err(|_| CollationError::Locale(locale.to_string()))?;
+        Ok(Collation {
+            locale: Some(locale),
+            ..Default::default()
+        })
+    }
+
+    /// the text in the normal form of the collation
+    pub fn normalize(&self, text: &str) -> String {
+        match self.normalization {
+            None => text.to_string(),
+            Some(Normalization::Nfc) => text.nfc().collect(),
+            Some(Normalization::Nfkc) => text.nfkc().collect(),
+        }
+    }
+
+    /// the text which is the same for all the texts the collation does not
+    /// tell apart: `Café` and `cafe` when it is case and accent insensitive
+    pub fn fold(&self, text: &str) -> String {
+        let mut text = self.normalize(text);
+        if self.case_insensitive {
+            text = caseless::default_case_fold_str(&text);
+        }
+        if self.accent_insensitive {
+            text = text.nfd().filter(|&c| !is_combining_mark(c)).collect();
+        }
+        // folding can undo the normal form, `ǰ` is folded into `j` and a caron
+        if self.case_insensitive || self.accent_insensitive {
+            text = self.normalize(&text);
+        }
+        text
+    }
+
+    /// the order of two texts, by the language of the collation when it has one
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        let ordering = self.locale
+            .as_ref()
+            .and_then(|locale| with_collator(locale, self.options(), |collator| {
+                collator.compare(&self.normalize(a), &self.normalize(b))
+            }));
+        ordering.unwrap_or_else(|| self.fold(a).cmp(&self.fold(b)))
+    }
+
+    /// the order of two values, the texts are ordered by the collation and the
+    /// other values as `Value::compare` does
+    pub fn compare_values(&self, a: &Value, b: &Value) -> Option<Ordering> {
+        match (a, b) {
+            (Value::Text(a), Value::Text(b)) => Some(self.compare(a, b)),
+            _ => a.compare(b),
+        }
+    }
+
+    /// the strength of the collator, a primary strength ignores the accents
+    /// and the case, the case level brings the case back
+    fn options(&self) -> CollatorOptions {
+        let mut options = CollatorOptions::new();
+        options.strength = Some(match (self.case_insensitive, self.accent_insensitive) {
+            (true, true) | (false, true) => Strength::Primary,
+            (true, false) => Strength::Secondary,
+            (false, false) => Strength::Tertiary,
+        });
+        if self.accent_insensitive && !self.case_insensitive {
+            options.case_level = Some(CaseLevel::On);
+        }
+        options
+    }
+}
+
+thread_local! {
+    /// the collators of the locales, a collator loads the data of its language once
+    static COLLATORS: RefCell<HashMap<String, Collator>> = RefCell::new(HashMap::new());
+}
+
+/// the collator of the locale, None when there is no data for it
+fn with_collator<T, F>(locale: &Locale, options: CollatorOptions, f: F) -> Option<T>
+    where F: FnOnce(&Collator) -> T
+{
+    COLLATORS.with(|collators| {
+        let mut collators = collators.borrow_mut();
+        let key = format!("{}/{:?}/{:?}", locale, options.strength, options.case_level);
+        if !collators.contains_key(&key) {
+            let collator = Collator::try_new(&locale.into(), options).ok()?;
+            collators.insert(key.clone(), collator);
+        }
+        collators.get(&key).map(f)
+    })
+}
+
+/// the collation of a query, and those of the columns which have their own
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Collations {
+    pub default: Collation,
+    pub columns: HashMap<String, Collation>,
+}
+
+impl Collations {
+    pub fn new(default: Collation) -> Self {
+        Collations {
+            default,
+            columns: HashMap::new(),
+        }
+    }
+
+    /// the collation of a column, of the column of an aggregate, `min(name)`,
+    /// or the default one
+    pub fn of(&self, operand: &Operand) -> &Collation {
+        let column = match *operand {
+            Operand::Column(ref column) => Some(column),
+            Operand::Function(ref function) => match function.params.first() {
+                Some(Operand::Column(column)) => Some(column),
+                _ => None,
+            },
+            _ => None,
+        };
+        column.and_then(|column| self.columns.get(column)).unwrap_or(&self.default)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum CollationError {
+    /// not a locale, `de`, `sv` or `fr-CA`
+    Locale(String),
+}
+
+impl fmt::Display for CollationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CollationError::Locale(ref locale) => write!(f, "invalid locale: {}", locale),
+        }
+    }
+}
+
+impl ::std::error::Error for CollationError {}
+
+#[test]
+fn test_fold() {
+    let binary = Collation::default();
+    assert_eq!(binary.fold("Cafe\u{301}"), "Cafe\u{301}");
+    let nfc = Collation { normalization: Some(Normalization::Nfc), ..Default::default() };
+    assert_eq!(nfc.fold("Cafe\u{301}"), "Café");
+    assert_eq!(nfc.fold("ﬁ①"), "ﬁ①");
+    let nfkc = Collation { normalization: Some(Normalization::Nfkc), ..Default::default() };
+    assert_eq!(nfkc.fold("ﬁ①"), "fi1");
+    let case = Collation { case_insensitive: true, ..Default::default() };
+    assert_eq!(case.fold("Straße ΣΑΣ"), "strasse σασ");
+    let accents = Collation { accent_insensitive: true, ..Default::default() };
+    assert_eq!(accents.fold("Crème Brûlée"), "Creme Brulee");
+    assert_eq!(Collation::insensitive().fold("° ͜ʖ ͡°"), "° ʖ °");
+    assert_eq!(Collation::insensitive().fold("技術通報"), "技術通報");
+}
+
+#[test]
+fn test_compare() {
+    let binary = Collation::default();
+    assert_eq!(binary.compare("a", "B"), Ordering::Greater);
+    assert_eq!(binary.compare("é", "e\u{301}"), Ordering::Greater);
+    assert_eq!(Collation::insensitive().compare("É", "e\u{301}"), Ordering::Equal);
+    let german = Collation::locale("de").unwrap();
+    assert_eq!(german.compare("a", "B"), Ordering::Less);
+    assert_eq!(german.compare("ä", "z"), Ordering::Less);
+    assert_eq!(Collation::locale("sv").unwrap().compare("ä", "z"), Ordering::Greater);
+    let insensitive = Collation { case_insensitive: true, accent_insensitive: true, ..german.clone() };
+    assert_eq!(insensitive.compare("Äpfel", "apfel"), Ordering::Equal);
+    let accents = Collation { accent_insensitive: true, ..german };
+    assert_eq!(accents.compare("äpfel", "apfel"), Ordering::Equal);
+    assert_ne!(accents.compare("Äpfel", "apfel"), Ordering::Equal);
+    assert_eq!(Collation::locale("not a locale"), Err(CollationError::Locale("not a locale".to_string())));
+}
+
+#[test]
+fn test_collations() {
+    let mut collations = Collations::default();
+    collations.columns.insert("name".to_string(), Collation::insensitive());
+    assert_eq!(collations.of(&Operand::Column("name".to_string())), &Collation::insensitive());
+    let max = ::parse_query("order_by=max(name)").unwrap().order_by[0].operand.clone();
+    assert_eq!(collations.of(&max), &Collation::insensitive());
+    assert_eq!(collations.of(&Operand::Column("title".to_string())), &Collation::default());
+}
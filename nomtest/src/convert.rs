@@ -0,0 +1,470 @@
+//! Conversions of rust values into operands, so the builder takes `impl Into<Operand>`
+//!
+//! A text is always a text, `"01234"`, `"true"` and `"a,b"` are not read as a
+//! number, a boolean or a list, use the rust value for those: `col("age").eq(20)`
+//! is `age=eq.20`. `None` is NULL, and a `Vec` or a slice is the list of `in`
+//! and `not_in`, `col("zip").is_in(vec!["01234", "02134"])` is a list of texts.
+//! Numbers are `f64` like in the parser, integers beyond 2^53 lose precision.
+//!
+//! With the `chrono` feature dates and times are texts in the ISO 8601 form,
+//! with the `uuid` feature a uuid is its hyphenated form.
+//!
+//! The other way, `TryFrom<&Operand>` takes the value of a literal out of an
+//! operand, and fails with a `ConversionError` when it is not of the type asked
+//! for: a fraction or a number out of range for an integer, a column for a text.
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+
+use relative::RelativeTime;
+use {Operand, Query};
+
+impl<'a> From<&'a str> for Operand {
+    fn from(s: &'a str) -> Self {
+        Operand::Value(s.to_string())
+    }
+}
+
+impl<'a> From<&'a String> for Operand {
+    fn from(s: &'a String) -> Self {
+        Operand::Value(s.to_string())
+    }
+}
+
+impl From<String> for Operand {
+    fn from(s: String) -> Self {
+        Operand::Value(s)
+    }
+}
+
+impl From<bool> for Operand {
+    fn from(b: bool) -> Self {
+        Operand::Boolean(b)
+    }
+}
+
+impl From<RelativeTime> for Operand {
+    fn from(time: RelativeTime) -> Self {
+        Operand::Relative(time)
+    }
+}
+
+macro_rules! number_from {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Operand {
+                fn from(n: $t) -> Self {
+                    Operand::Number(n as f64)
+                }
+            }
+        )*
+    };
+}
+
+number_from!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+/// `None` is NULL, `x=is.null`
+impl<T: Into<Operand>> From<Option<T>> for Operand {
+    fn from(option: Option<T>) -> Self {
+        match option {
+            Some(value) => value.into(),
+            None => Operand::Value("null".to_string()),
+        }
+    }
+}
+
+/// the list of `in` and `not_in`, each item keeps its type
+impl<T: Into<Operand>> From<Vec<T>> for Operand {
+    fn from(items: Vec<T>) -> Self {
+        Operand::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<'a, T: Clone + Into<Operand>> From<&'a [T]> for Operand {
+    fn from(items: &'a [T]) -> Self {
+        Operand::from(items.to_vec())
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod dates {
+    use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeZone};
+    use std::borrow::Cow;
+    use std::convert::TryFrom;
+    use std::fmt::Display;
+
+    use super::ConversionError;
+    use relative::SystemClock;
+    use Operand;
+
+    /// `2024-01-31`
+    impl From<NaiveDate> for Operand {
+        fn from(date: NaiveDate) -> Self {
+            Operand::Value(date.format("%Y-%m-%d").to_string())
+        }
+    }
+
+    /// `13:45:00`, with the fraction of a second when there is one
+    impl From<NaiveTime> for Operand {
+        fn from(time: NaiveTime) -> Self {
+            Operand::Value(time.format("%H:%M:%S%.f").to_string())
+        }
+    }
+
+    /// `2024-01-31T13:45:00`
+    impl From<NaiveDateTime> for Operand {
+        fn from(datetime: NaiveDateTime) -> Self {
+            Operand::Value(datetime.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+        }
+    }
+
+    /// `2024-01-31T13:45:00+01:00`, `Z` for utc
+    impl<Tz: TimeZone> From<DateTime<Tz>> for Operand
+        where Tz::Offset: Display
+    {
+        fn from(datetime: DateTime<Tz>) -> Self {
+            Operand::Value(datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        }
+    }
+
+    /// the text of a date, the parser does not know about dates, a relative time
+    /// which `relative::resolve_operand` has not resolved is resolved against the
+    /// clock of the system
+    fn text<'a>(operand: &'a Operand, expected: &'static str) -> Result<Cow<'a, str>, ConversionError> {
+        match *operand {
+            Operand::Value(ref v) => Ok(Cow::Borrowed(v)),
+            Operand::Relative(ref time) => Ok(Cow::Owned(time.resolve(&SystemClock))),
+            _ => Err(ConversionError::new(expected, operand)),
+        }
+    }
+
+    impl<'a> TryFrom<&'a Operand> for NaiveDate {
+        type Error = ConversionError;
+
+        fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+            NaiveDate::parse_from_str(&text(operand, "NaiveDate")?, "%Y-%m-%d")
+                .map_err(|_| ConversionError::new("NaiveDate", operand))
+        }
+    }
+
+    impl<'a> TryFrom<&'a Operand> for NaiveTime {
+        type Error = ConversionError;
+
+        fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+            NaiveTime::parse_from_str(&text(operand, "NaiveTime")?, "%H:%M:%S%.f")
+                .map_err(|_| ConversionError::new("NaiveTime", operand))
+        }
+    }
+
+    /// `2024-01-31T13:45:00`, or with a space instead of the `T`
+    impl<'a> TryFrom<&'a Operand> for NaiveDateTime {
+        type Error = ConversionError;
+
+        fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+            let text = text(operand, "NaiveDateTime")?;
+            NaiveDateTime::parse_from_str(&text, "%Y-%m-%dT%H:%M:%S%.f")
+                .or_else(|_| NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f"))
+                .map_err(|_| ConversionError::new("NaiveDateTime", operand))
+        }
+    }
+
+    /// rfc 3339, with its offset
+    impl<'a> TryFrom<&'a Operand> for DateTime<FixedOffset> {
+        type Error = ConversionError;
+
+        fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+            DateTime::parse_from_rfc3339(&text(operand, "DateTime")?)
+                .map_err(|_| ConversionError::new("DateTime", operand))
+        }
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<::uuid::Uuid> for Operand {
+    fn from(uuid: ::uuid::Uuid) -> Self {
+        Operand::Value(uuid.hyphenated().to_string())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl<'a> TryFrom<&'a Operand> for ::uuid::Uuid {
+    type Error = ConversionError;
+
+    fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+        match *operand {
+            Operand::Value(ref v) => v.parse().map_err(|_| ConversionError::new("Uuid", operand)),
+            _ => Err(ConversionError::new("Uuid", operand)),
+        }
+    }
+}
+
+/// what an operand holds, for the errors
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Kind {
+    Column,
+    Function,
+    Integer,
+    Number,
+    Boolean,
+    Null,
+    Text,
+    List,
+    Relative,
+}
+
+impl Kind {
+    pub fn of(operand: &Operand) -> Kind {
+        match *operand {
+            Operand::Column(_) => Kind::Column,
+            Operand::Function(_) => Kind::Function,
+            Operand::Number(n) if n.fract() == 0.0 => Kind::Integer,
+            Operand::Number(_) => Kind::Number,
+            Operand::Boolean(_) => Kind::Boolean,
+            Operand::Value(ref v) if v.eq_ignore_ascii_case("null") => Kind::Null,
+            Operand::Value(_) => Kind::Text,
+            Operand::Relative(_) => Kind::Relative,
+            Operand::List(_) => Kind::List,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match *self {
+            Kind::Column => "a column",
+            Kind::Function => "a function",
+            Kind::Integer => "an integer",
+            Kind::Number => "a number",
+            Kind::Boolean => "a boolean",
+            Kind::Null => "null",
+            Kind::Text => "a text",
+            Kind::List => "a list",
+            Kind::Relative => "a relative time",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// the operand is not of the rust type it was converted to
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct ConversionError {
+    /// the rust type, `u32`, `bool`, `NaiveDate`
+    pub expected: &'static str,
+    pub actual: Kind,
+    /// the operand as it is written in the query
+    pub value: String,
+}
+
+impl ConversionError {
+    fn new(expected: &'static str, operand: &Operand) -> Self {
+        ConversionError {
+            expected,
+            actual: Kind::of(operand),
+            value: operand.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}, found {} `{}`", self.expected, self.actual, self.value)
+    }
+}
+
+impl error::Error for ConversionError {}
+
+/// a whole number within the range of the type, `2` but not `2.5` or `-1` for a `u32`
+macro_rules! integer_try_from {
+    ($($t:ident),*) => {
+        $(
+            impl<'a> TryFrom<&'a Operand> for $t {
+                type Error = ConversionError;
+
+                fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+                    match *operand {
+                        // `MAX as f64` rounds up to a power of 2 for the wide types, which is out of range
+                        Operand::Number(n) if n.fract() == 0.0 && n >= $t::MIN as f64 && n < $t::MAX as f64 + 1.0 => {
+                            Ok(n as $t)
+                        }
+                        _ => Err(ConversionError::new(stringify!($t), operand)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_try_from!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl<'a> TryFrom<&'a Operand> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+        match *operand {
+            Operand::Number(n) => Ok(n),
+            _ => Err(ConversionError::new("f64", operand)),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Operand> for f32 {
+    type Error = ConversionError;
+
+    fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+        match *operand {
+            Operand::Number(n) if (n as f32).is_finite() || !n.is_finite() => Ok(n as f32),
+            _ => Err(ConversionError::new("f32", operand)),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Operand> for bool {
+    type Error = ConversionError;
+
+    fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+        match *operand {
+            Operand::Boolean(b) => Ok(b),
+            _ => Err(ConversionError::new("bool", operand)),
+        }
+    }
+}
+
+/// any literal as it is written, `20` is the text `20`, but not a column or a function
+impl<'a> TryFrom<&'a Operand> for String {
+    type Error = ConversionError;
+
+    fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+        match *operand {
+            Operand::Column(_) | Operand::Function(_) => Err(ConversionError::new("String", operand)),
+            _ => Ok(operand.to_string()),
+        }
+    }
+}
+
+/// the items of a list, or of the text of a list as it is written in a url,
+/// `count=1,2,3`, a single value is a list of one
+impl<'a, T> TryFrom<&'a Operand> for Vec<T>
+    where T: for<'b> TryFrom<&'b Operand, Error = ConversionError>
+{
+    type Error = ConversionError;
+
+    fn try_from(operand: &'a Operand) -> Result<Self, Self::Error> {
+        match *operand {
+            Operand::Column(_) | Operand::Function(_) => Err(ConversionError::new("Vec", operand)),
+            Operand::Value(ref v) => v.split(',').map(|item| T::try_from(&::value_operand(item))).collect(),
+            _ => operand.list_items().iter().map(T::try_from).collect(),
+        }
+    }
+}
+
+impl Query {
+    /// the value of the equation `name`, `limit=10`, None when the query has none
+    pub fn equation_value<T>(&self, name: &str) -> Result<Option<T>, ConversionError>
+        where T: for<'a> TryFrom<&'a Operand, Error = ConversionError>
+    {
+        let equation = self.equations.iter().find(|equation| match equation.left {
+            Operand::Column(ref column) => column == name,
+            _ => false,
+        });
+        equation.map(|equation| T::try_from(&equation.right)).transpose()
+    }
+}
+
+#[test]
+fn test_into_operand() {
+    assert_eq!(Operand::from(20u8), Operand::Number(20.0));
+    assert_eq!(Operand::from(-3i64), Operand::Number(-3.0));
+    assert_eq!(Operand::from(1.5f32), Operand::Number(1.5));
+    assert_eq!(Operand::from("20"), Operand::Value("20".to_string()));
+    assert_eq!(Operand::from("01234"), Operand::Value("01234".to_string()));
+    assert_eq!(Operand::from("true"), Operand::Value("true".to_string()));
+    assert_eq!(Operand::from("now"), Operand::Value("now".to_string()));
+    assert_eq!(Operand::from("John".to_string()), Operand::Value("John".to_string()));
+    assert_eq!(Operand::from(Some(true)), Operand::Boolean(true));
+    assert_eq!(Operand::from(None::<i32>), Operand::Value("null".to_string()));
+    assert_eq!(Operand::from(vec![1, 2, 3]),
+               Operand::List(vec![Operand::Number(1.0), Operand::Number(2.0), Operand::Number(3.0)]));
+    assert_eq!(Operand::from(&["a", "b"][..]),
+               Operand::List(vec![Operand::Value("a".to_string()), Operand::Value("b".to_string())]));
+    assert_eq!(Operand::from(vec![7]), Operand::List(vec![Operand::Number(7.0)]));
+    // the items keep their type, a zip code is not read back as a number
+    let zips = ::Query::default().filter(::builder::col("zip").is_in(vec!["01234", "02134"]));
+    assert_eq!(zips.filters[0].condition.right.list_items(),
+               vec![Operand::Value("01234".to_string()), Operand::Value("02134".to_string())]);
+    let week_ago = RelativeTime::new(::relative::Anchor::Now).shift(-7, ::relative::Unit::Day);
+    assert_eq!(Operand::from("@now-7d"), Operand::Value("@now-7d".to_string()));
+    assert_eq!(Operand::from(week_ago).to_string(), "@now-7d");
+    let query = ::Query::default().filter(::builder::col("deleted_at").eq(None::<String>));
+    assert_eq!(query.filters[0].condition.right, ::parse_filter("deleted_at=eq.null").condition.right);
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_dates() {
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use std::convert::TryFrom;
+    let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+    assert_eq!(Operand::from(date), Operand::Value("2024-01-31".to_string()));
+    assert_eq!(Operand::from(date.and_hms_opt(13, 45, 0).unwrap()),
+               Operand::Value("2024-01-31T13:45:00".to_string()));
+    assert_eq!(Operand::from(Utc.with_ymd_and_hms(2024, 1, 31, 13, 45, 0).unwrap()),
+               Operand::Value("2024-01-31T13:45:00Z".to_string()));
+    assert_eq!(NaiveDate::try_from(&Operand::from("2024-01-31")), Ok(date));
+    assert_eq!(NaiveDate::try_from(&Operand::from("2024-02-31")).map_err(|e| e.expected), Err("NaiveDate"));
+    assert_eq!(::chrono::NaiveDateTime::try_from(&Operand::from("2024-01-31 13:45:00")),
+               Ok(date.and_hms_opt(13, 45, 0).unwrap()));
+    let datetime = ::chrono::DateTime::parse_from_rfc3339("2024-01-31T13:45:00+01:00").unwrap();
+    assert_eq!(::chrono::DateTime::try_from(&Operand::from(datetime)), Ok(datetime));
+    // resolved against a clock, or the clock of the system when it is not
+    let clock = ::relative::FixedClock(1706708700);
+    let today = Operand::from("today".parse::<RelativeTime>().unwrap());
+    assert_eq!(NaiveDate::try_from(&::relative::resolve_operand(&today, &clock)), Ok(date));
+    let hour_ago = Operand::from("now-1h".parse::<RelativeTime>().unwrap());
+    assert_eq!(::chrono::DateTime::try_from(&::relative::resolve_operand(&hour_ago, &clock)),
+               Ok(Utc.with_ymd_and_hms(2024, 1, 31, 12, 45, 0).unwrap().fixed_offset()));
+    assert!(NaiveDate::try_from(&today).is_ok());
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn test_uuid() {
+    let uuid = ::uuid::Uuid::from_u128(0x67e5504410b1426f9247bb680e5fe0c8);
+    assert_eq!(Operand::from(uuid), Operand::Value("67e55044-10b1-426f-9247-bb680e5fe0c8".to_string()));
+    assert_eq!(::uuid::Uuid::try_from(&Operand::from(uuid)), Ok(uuid));
+}
+
+#[test]
+fn test_try_from_operand() {
+    // the operands the parser makes of the values
+    let operand = ::value_operand;
+    assert_eq!(u8::try_from(&operand("200")), Ok(200));
+    assert_eq!(u8::try_from(&operand("300")),
+               Err(ConversionError { expected: "u8", actual: Kind::Integer, value: "300".to_string() }));
+    assert_eq!(u32::try_from(&operand("-1")).map_err(|e| e.to_string()),
+               Err("expected u32, found an integer `-1`".to_string()));
+    assert_eq!(i64::try_from(&operand("2.5")).map_err(|e| e.actual), Err(Kind::Number));
+    assert_eq!(u64::try_from(&Operand::Number(u64::MAX as f64)).map_err(|e| e.expected), Err("u64"));
+    assert_eq!(f64::try_from(&operand("2.5")), Ok(2.5));
+    assert_eq!(bool::try_from(&operand("true")), Ok(true));
+    assert_eq!(bool::try_from(&operand("yes")).map_err(|e| e.actual), Err(Kind::Text));
+    assert_eq!(String::try_from(&operand("John")), Ok("John".to_string()));
+    assert_eq!(String::try_from(&Operand::Column("name".to_string())).map_err(|e| e.actual), Err(Kind::Column));
+    assert_eq!(Vec::<i32>::try_from(&operand("1,2,3")), Ok(vec![1, 2, 3]));
+    assert_eq!(Vec::<i32>::try_from(&operand("7")), Ok(vec![7]));
+    assert_eq!(Vec::<i32>::try_from(&operand("1,x")).map_err(|e| e.value), Err("x".to_string()));
+}
+
+#[test]
+fn test_equation_value() {
+    let query = ::parse_query("count=2&format=csv&size=1.5").unwrap();
+    assert_eq!(query.equation_value::<u32>("count"), Ok(Some(2)));
+    assert_eq!(query.equation_value::<String>("format"), Ok(Some("csv".to_string())));
+    assert_eq!(query.equation_value::<u32>("size").map_err(|e| e.actual), Err(Kind::Number));
+    assert_eq!(query.equation_value::<u32>("missing"), Ok(None));
+}
@@ -0,0 +1,369 @@
+//! Translate a `Query` into an Elasticsearch/OpenSearch search request body
+//!
+//! ```json
+//! {"query": {"bool": {"must": [{"range": {"age": {"lt": 20}}}]}},
+//!  "sort": [{"name": {"order": "desc"}}], "from": 10, "size": 10}
+//! ```
+//! Conditions keep their sql meaning, `a=neq.1` does not match the documents
+//! where `a` is missing, the same way `a != 1` is not true when `a` is NULL.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use expr::Expr;
+use fts::TsQuery;
+use json;
+use like::LikePattern;
+use {Condition, Direction, Equality, Filter, NullsWhere, Operand, Order, Query, Range};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Error {
+    /// the part of the query which has no equivalent in the query dsl, ie: joins
+    Unsupported(String),
+    /// an operand which can not be used at this position, ie: a function
+    InvalidOperand(Operand),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Unsupported(ref what) => write!(f, "{} is not supported in elasticsearch", what),
+            Error::InvalidOperand(ref op) => write!(f, "invalid operand: {:?}", op),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// the search request body: `query`, `sort`, `from` and `size`
+pub fn to_search(query: &Query) -> Result<Value, Error> {
+    if !query.join.is_empty() {
+        return Err(Error::Unsupported("join".to_string()));
+    }
+    if !query.group_by.is_empty() || !query.having.is_empty() {
+        return Err(Error::Unsupported("group_by".to_string()));
+    }
+    let mut body = Map::new();
+    body.insert("query".to_string(), query_dsl(&query.filters)?);
+    if !query.order_by.is_empty() {
+        body.insert("sort".to_string(), sort_dsl(&query.order_by)?);
+    }
+    match query.range {
+        Some(Range::Page(ref page)) => {
+            let from = page.offset().ok_or_else(|| Error::Unsupported("an offset past i64".to_string()))?;
+            body.insert("from".to_string(), Value::from(from));
+            body.insert("size".to_string(), Value::from(page.page_size));
+        }
+        Some(Range::Limit(ref limit)) => {
+            if let Some(offset) = limit.offset {
+                body.insert("from".to_string(), Value::from(offset));
+            }
+            body.insert("size".to_string(), Value::from(limit.limit));
+        }
+        Some(Range::Keyset(_)) => return Err(Error::Unsupported("an unresolved cursor".to_string())),
+        None => (),
+    }
+    Ok(Value::Object(body))
+}
+
+/// the query of the filters, `match_all` when there is none
+pub fn query_dsl(filters: &[Filter]) -> Result<Value, Error> {
+    match Expr::from_filters(filters) {
+        Some(expr) => expr_query(&expr),
+        None => Ok(single("match_all", Value::Object(Map::new()))),
+    }
+}
+
+/// the sort clauses, the position of nulls is set with `missing`
+pub fn sort_dsl(order_by: &[Order]) -> Result<Value, Error> {
+    let mut sort = vec![];
+    for order in order_by {
+        let field = field_name(&order.operand)?;
+        let mut clause = Map::new();
+        let direction = match order.direction {
+            Some(Direction::DESC) => "desc",
+            Some(Direction::ASC) | None => "asc",
+        };
+        clause.insert("order".to_string(), Value::from(direction));
+        match order.nulls_where {
+            Some(NullsWhere::FIRST) => {
+                clause.insert("missing".to_string(), Value::from("_first"));
+            }
+            Some(NullsWhere::LAST) => {
+                clause.insert("missing".to_string(), Value::from("_last"));
+            }
+            None => (),
+        }
+        sort.push(single(&field, Value::Object(clause)));
+    }
+    Ok(Value::Array(sort))
+}
+
+fn expr_query(expr: &Expr) -> Result<Value, Error> {
+    match *expr {
+        Expr::Condition(ref condition) => condition_query(condition),
+        Expr::And(ref exprs) => Ok(boolean(vec![("must", expr_queries(exprs)?)])),
+        Expr::Or(ref exprs) => {
+            let mut query = boolean(vec![("should", expr_queries(exprs)?)]);
+            if let Some(bool_query) = query["bool"].as_object_mut() {
+                bool_query.insert("minimum_should_match".to_string(), Value::from(1));
+            }
+            Ok(query)
+        }
+    }
+}
+
+fn expr_queries(exprs: &[Expr]) -> Result<Vec<Value>, Error> {
+    let mut queries = vec![];
+    for expr in exprs {
+        queries.push(expr_query(expr)?);
+    }
+    Ok(queries)
+}
+
+fn condition_query(condition: &Condition) -> Result<Value, Error> {
+    let field = field_name(&condition.left)?;
+    let right = &condition.right;
+    let query = match condition.equality {
+        Equality::EQ => single("term", single(&field, json_value(right)?)),
+        Equality::NEQ => {
+            boolean(vec![("must", vec![exists(&field)]),
+                         ("must_not", vec![single("term", single(&field, json_value(right)?))])])
+        }
+        Equality::LT => range(&field, "lt", right)?,
+        Equality::LTE => range(&field, "lte", right)?,
+        Equality::GT => range(&field, "gt", right)?,
+        Equality::GTE => range(&field, "gte", right)?,
+        Equality::IN => single("terms", single(&field, json_list(right)?)),
+        Equality::NOT_IN => {
+            boolean(vec![("must", vec![exists(&field)]),
+                         ("must_not", vec![single("terms", single(&field, json_list(right)?))])])
+        }
+        Equality::IS => {
+            match is_value(right)? {
+                Value::Null => boolean(vec![("must_not", vec![exists(&field)])]),
+                value => single("term", single(&field, value)),
+            }
+        }
+        Equality::IS_NOT => {
+            match is_value(right)? {
+                Value::Null => exists(&field),
+                value => boolean(vec![("must_not", vec![single("term", single(&field, value))])]),
+            }
+        }
+        Equality::LIKE | Equality::ILIKE | Equality::ST => {
+            let pattern = LikePattern::from_condition(&condition.equality, right)
+                .ok_or_else(|| Error::InvalidOperand(right.clone()))?;
+            let mut wildcard = Map::new();
+            wildcard.insert("value".to_string(), Value::from(pattern.to_wildcard()));
+            if pattern.case_insensitive {
+                wildcard.insert("case_insensitive".to_string(), Value::Bool(true));
+            }
+            single("wildcard", single(&field, Value::Object(wildcard)))
+        }
+        // a lucene regexp is anchored and has another syntax
+        Equality::MATCH | Equality::IMATCH => return Err(Error::Unsupported("regular expressions".to_string())),
+        Equality::FTS(_) | Equality::PLFTS(_) | Equality::PHFTS(_) | Equality::WFTS(_) => {
+            let text = match json_value(right)? {
+                Value::String(text) => text,
+                value => value.to_string(),
+            };
+            match TsQuery::parse(&condition.equality, &text) {
+                Some(search) => search_query(&field, &search, condition.equality.language())?,
+                // a search without words matches nothing, like in postgresql
+                None => boolean(vec![("must_not", vec![single("match_all", Value::Object(Map::new()))])]),
+            }
+        }
+    };
+    Ok(query)
+}
+
+/// a full text search as `match` queries of the words, with the language as the analyzer.
+/// A phrase is a `match_phrase` of its words, which have to be next to each other
+fn search_query(field: &str, search: &TsQuery, language: Option<&str>) -> Result<Value, Error> {
+    let text_query = |kind: &str, text: String| {
+        let mut options = Map::new();
+        options.insert("query".to_string(), Value::from(text));
+        if let Some(language) = language {
+            options.insert("analyzer".to_string(), Value::from(language));
+        }
+        single(kind, single(field, Value::Object(options)))
+    };
+    let searches = |searches: &[TsQuery]| -> Result<Vec<Value>, Error> {
+        searches.iter().map(|search| search_query(field, search, language)).collect()
+    };
+    let query = match *search {
+        TsQuery::Word { ref word, prefix: false } => text_query("match", word.to_string()),
+        TsQuery::Word { ref word, prefix: true } => text_query("match_phrase_prefix", word.to_string()),
+        TsQuery::Not(ref search) => boolean(vec![("must_not", vec![search_query(field, search, language)?])]),
+        TsQuery::And(ref all) => boolean(vec![("must", searches(all)?)]),
+        TsQuery::Or(ref any) => {
+            let mut query = boolean(vec![("should", searches(any)?)]);
+            if let Some(bool_query) = query["bool"].as_object_mut() {
+                bool_query.insert("minimum_should_match".to_string(), Value::from(1));
+            }
+            query
+        }
+        TsQuery::Followed(..) => match search.phrase_words() {
+            Some(words) => text_query("match_phrase", words.join(" ")),
+            None => return Err(Error::Unsupported("a phrase of more than single words".to_string())),
+        },
+    };
+    Ok(query)
+}
+
+fn boolean(clauses: Vec<(&str, Vec<Value>)>) -> Value {
+    let mut bool_query = Map::new();
+    for (occur, queries) in clauses {
+        bool_query.insert(occur.to_string(), Value::Array(queries));
+    }
+    single("bool", Value::Object(bool_query))
+}
+
+fn range(field: &str, operator: &str, operand: &Operand) -> Result<Value, Error> {
+    Ok(single("range", single(field, single(operator, json_value(operand)?))))
+}
+
+fn exists(field: &str) -> Value {
+    single("exists", single("field", Value::from(field)))
+}
+
+fn single(key: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(key.to_string(), value);
+    Value::Object(map)
+}
+
+fn field_name(operand: &Operand) -> Result<String, Error> {
+    match *operand {
+        Operand::Column(ref column) => Ok(column.to_string()),
+        _ => Err(Error::InvalidOperand(operand.clone())),
+    }
+}
+
+fn json_value(operand: &Operand) -> Result<Value, Error> {
+    json::from_operand(operand).ok_or_else(|| Error::InvalidOperand(operand.clone()))
+}
+
+fn json_list(operand: &Operand) -> Result<Value, Error> {
+    let mut values = vec![];
+    for item in operand.list_items() {
+        values.push(json_value(&item)?);
+    }
+    Ok(Value::Array(values))
+}
+
+fn is_value(operand: &Operand) -> Result<Value, Error> {
+    json::is_operand(operand).ok_or_else(|| Error::InvalidOperand(operand.clone()))
+}
+
+#[test]
+fn test_elastic_bool() {
+    let query = Query {
+        filters: vec![::parse_filter("age=lt.20&(product=eq.134|price=gte.100.5)")],
+        ..Default::default()
+    };
+    ::assert_golden("elastic/bool", &to_search(&query).unwrap());
+}
+
+#[test]
+fn test_elastic_negations() {
+    let query = Query {
+        filters: vec![::parse_filter("status=neq.closed&tag=not_in.red,green"),
+                      ::parse_filter("deleted_at=is.null|owner=is_not.null|active=is_not.true")],
+        ..Default::default()
+    };
+    ::assert_golden("elastic/negations", &to_search(&query).unwrap());
+}
+
+#[test]
+fn test_elastic_patterns() {
+    let query = Query {
+        filters: vec![::parse_filter("name=st.John&title=like.why?*&nick=ilike.*° ͜ʖ ͡°*")],
+        ..Default::default()
+    };
+    ::assert_golden("elastic/patterns", &to_search(&query).unwrap());
+}
+
+#[test]
+fn test_elastic_full_text() {
+    let query = Query {
+        filters: vec![::parse_filter("body=fts(english).fat:* & !(cat | dog)"),
+                      ::parse_filter("title=wfts.\"fat cat\" or dog")],
+        ..Default::default()
+    };
+    ::assert_golden("elastic/full_text", &to_search(&query).unwrap());
+    let query = Query {
+        filters: vec![::parse_filter("body=fts.(fat|cat)<->dog")],
+        ..Default::default()
+    };
+    assert_eq!(to_search(&query), Err(Error::Unsupported("a phrase of more than single words".to_string())));
+}
+
+#[test]
+fn test_elastic_relative_times() {
+    use relative::{self, FixedClock};
+    let query = ::parse_query("created_at=gt.now-7d&due=in.@today,@today+1w").unwrap();
+    // 2024-01-31T13:45:00Z
+    ::assert_golden("elastic/relative_times", &to_search(&relative::resolve(&query, &FixedClock(1706708700))).unwrap());
+}
+
+#[test]
+fn test_elastic_sort_and_range() {
+    use Page;
+    let query = Query {
+        order_by: vec![Order {
+                           operand: Operand::Column("name".to_string()),
+                           direction: Some(Direction::DESC),
+                           nulls_where: Some(NullsWhere::LAST),
+                       },
+                       Order {
+                           operand: Operand::Column("age".to_string()),
+                           direction: None,
+                           nulls_where: None,
+                       }],
+        range: Some(Range::Page(Page {
+            page: 2,
+            page_size: 50,
+        })),
+        ..Default::default()
+    };
+    ::assert_golden("elastic/sort_page", &to_search(&query).unwrap());
+}
+
+#[test]
+fn test_elastic_unsupported() {
+    use {Join, JoinType};
+    let query = Query {
+        join: vec![Join {
+                       modifier: None,
+                       join_type: Some(JoinType::INNER),
+                       table: Operand::Column("orders".to_string()),
+                       column1: vec!["id".to_string()],
+                       column2: vec!["user_id".to_string()],
+                   }],
+        ..Default::default()
+    };
+    assert_eq!(to_search(&query), Err(Error::Unsupported("join".to_string())));
+    let query = ::parse_query("order_by=id&before=abc.def").unwrap();
+    assert_eq!(to_search(&query), Err(Error::Unsupported("an unresolved cursor".to_string())));
+    let query = Query::default().page(i64::MAX, 10);
+    assert_eq!(to_search(&query), Err(Error::Unsupported("an offset past i64".to_string())));
+    let query = ::parse_query("name=match.^Jo").unwrap();
+    assert_eq!(to_search(&query), Err(Error::Unsupported("regular expressions".to_string())));
+
+    let query = Query {
+        filters: vec![::parse_filter("name=like.John")],
+        order_by: vec![Order {
+                           operand: Operand::Value("name".to_string()),
+                           direction: None,
+                           nulls_where: None,
+                       }],
+        ..Default::default()
+    };
+    assert_eq!(to_search(&query),
+               Err(Error::InvalidOperand(Operand::Value("name".to_string()))));
+}
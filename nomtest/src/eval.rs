@@ -0,0 +1,320 @@
+//! Evaluate filters against in-memory rows
+//!
+//! The evaluation follows the sql three-valued logic, a comparison with NULL
+//! is neither true nor false but unknown, and a row only matches when the
+//! whole filter is true. Texts are compared by their code points unless the
+//! filter is evaluated with the `Collations` of the query.
+
+use std::cmp::Ordering;
+use std::ops::Not;
+
+use collation::{Collation, Collations};
+use expr::Expr;
+use fts::TsQuery;
+use like::LikePattern;
+use regexp;
+use row::{Row, Value};
+use {Condition, Equality, Filter, Operand};
+
+/// the outcome of a condition in sql
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Truth {
+    True,
+    False,
+    Unknown,
+}
+
+impl Truth {
+    pub fn and(self, other: Truth) -> Truth {
+        match (self, other) {
+            (Truth::False, _) | (_, Truth::False) => Truth::False,
+            (Truth::True, Truth::True) => Truth::True,
+            _ => Truth::Unknown,
+        }
+    }
+
+    pub fn or(self, other: Truth) -> Truth {
+        match (self, other) {
+            (Truth::True, _) | (_, Truth::True) => Truth::True,
+            (Truth::False, Truth::False) => Truth::False,
+            _ => Truth::Unknown,
+        }
+    }
+
+    pub fn is_true(self) -> bool {
+        self == Truth::True
+    }
+}
+
+impl Not for Truth {
+    type Output = Truth;
+
+    fn not(self) -> Truth {
+        match self {
+            Truth::True => Truth::False,
+            Truth::False => Truth::True,
+            Truth::Unknown => Truth::Unknown,
+        }
+    }
+}
+
+impl From<bool> for Truth {
+    fn from(b: bool) -> Self {
+        if b { Truth::True } else { Truth::False }
+    }
+}
+
+impl Filter {
+    /// whether the row satisfies this filter, unknown is not a match
+    pub fn matches<R: Row>(&self, row: &R) -> bool {
+        self.eval(row).is_true()
+    }
+
+    pub fn eval<R: Row>(&self, row: &R) -> Truth {
+        self.eval_with(row, &Collations::default())
+    }
+
+    pub fn matches_with<R: Row>(&self, row: &R, collations: &Collations) -> bool {
+        self.eval_with(row, collations).is_true()
+    }
+
+    pub fn eval_with<R: Row>(&self, row: &R, collations: &Collations) -> Truth {
+        Expr::from(self).eval_with(row, collations)
+    }
+}
+
+/// whether the row satisfies all the filters
+pub fn matches_all<R: Row>(filters: &[Filter], row: &R) -> bool {
+    filters.iter().all(|filter| filter.matches(row))
+}
+
+/// whether the row satisfies all the filters, with the collations of the query
+pub fn matches_all_with<R: Row>(filters: &[Filter], row: &R, collations: &Collations) -> bool {
+    filters.iter().all(|filter| filter.matches_with(row, collations))
+}
+
+impl Expr {
+    pub fn eval<R: Row>(&self, row: &R) -> Truth {
+        self.eval_with(row, &Collations::default())
+    }
+
+    pub fn eval_with<R: Row>(&self, row: &R, collations: &Collations) -> Truth {
+        match *self {
+            Expr::Condition(ref condition) => condition.eval_with(row, collations),
+            Expr::And(ref exprs) => {
+                exprs.iter().fold(Truth::True, |truth, expr| truth.and(expr.eval_with(row, collations)))
+            }
+            Expr::Or(ref exprs) => {
+                exprs.iter().fold(Truth::False, |truth, expr| truth.or(expr.eval_with(row, collations)))
+            }
+        }
+    }
+}
+
+impl Condition {
+    pub fn eval<R: Row>(&self, row: &R) -> Truth {
+        self.eval_with(row, &Collations::default())
+    }
+
+    /// the texts are compared with the collation of the left column
+    pub fn eval_with<R: Row>(&self, row: &R, collations: &Collations) -> Truth {
+        let collation = collations.of(&self.left);
+        let left = operand_value(&self.left, row);
+        let right = || operand_value(&self.right, row);
+        match self.equality {
+            Equality::EQ => compare(collation, &left, &right(), |o| o == Ordering::Equal),
+            Equality::NEQ => compare(collation, &left, &right(), |o| o != Ordering::Equal),
+            Equality::LT => compare(collation, &left, &right(), |o| o == Ordering::Less),
+            Equality::LTE => compare(collation, &left, &right(), |o| o != Ordering::Greater),
+            Equality::GT => compare(collation, &left, &right(), |o| o == Ordering::Greater),
+            Equality::GTE => compare(collation, &left, &right(), |o| o != Ordering::Less),
+            Equality::IN => in_list(collation, &left, &self.right, row),
+            Equality::NOT_IN => !in_list(collation, &left, &self.right, row),
+            Equality::IS => is(&left, &self.right),
+            Equality::IS_NOT => !is(&left, &self.right),
+            Equality::LIKE | Equality::ILIKE | Equality::ST => {
+                match (left.to_text(), LikePattern::from_condition(&self.equality, &self.right)) {
+                    (Some(text), Some(pattern)) => Truth::from(pattern.matches_with(&text, collation)),
+                    _ => Truth::Unknown,
+                }
+            }
+            Equality::MATCH | Equality::IMATCH => {
+                let pattern = Value::from_operand(&self.right).and_then(|pattern| pattern.to_text());
+                match (left.to_text(), pattern) {
+                    (Some(text), Some(pattern)) => {
+                        let case_insensitive = self.equality == Equality::IMATCH;
+                        let text = collation.normalize(&text);
+                        regexp::is_match(&collation.normalize(&pattern), case_insensitive, &text)
+                            .map_or(Truth::Unknown, Truth::from)
+                    }
+                    _ => Truth::Unknown,
+                }
+            }
+            Equality::FTS(_) | Equality::PLFTS(_) | Equality::PHFTS(_) | Equality::WFTS(_) => {
+                let search = Value::from_operand(&self.right).and_then(|search| search.to_text());
+                match (left.to_text(), search) {
+                    (Some(text), Some(search)) => {
+                        // a search without words matches nothing, like in postgresql
+                        let query = TsQuery::parse(&self.equality, &collation.fold(&search));
+                        Truth::from(query.is_some_and(|query| query.matches(&collation.fold(&text))))
+                    }
+                    _ => Truth::Unknown,
+                }
+            }
+        }
+    }
+}
+
+/// columns are looked up in the row, a function is looked up by its column name,
+/// which is where the aggregates of a group are. Missing columns are NULL
+pub fn operand_value<R: Row>(operand: &Operand, row: &R) -> Value {
+    match *operand {
+        Operand::Column(ref column) => row.get(column).unwrap_or(Value::Null),
+        Operand::Function(ref function) => row.get(&function.column_name()).unwrap_or(Value::Null),
+        _ => Value::from_operand(operand).unwrap_or(Value::Null),
+    }
+}
+
+fn compare<F>(collation: &Collation, left: &Value, right: &Value, test: F) -> Truth
+    where F: Fn(Ordering) -> bool
+{
+    match collation.compare_values(left, right) {
+        Some(ordering) => Truth::from(test(ordering)),
+        None => Truth::Unknown,
+    }
+}
+
+/// `x IN (a, b)` is `x = a OR x = b`
+fn in_list<R: Row>(collation: &Collation, left: &Value, list: &Operand, row: &R) -> Truth {
+    list.list_items().iter().fold(Truth::False, |truth, item| {
+        truth.or(compare(collation, left, &operand_value(item, row), |o| o == Ordering::Equal))
+    })
+}
+
+/// `is` is never unknown: `x IS NULL`, `x IS TRUE`, `x IS FALSE`
+fn is(left: &Value, right: &Operand) -> Truth {
+    match *right {
+        Operand::Value(ref v) if v.eq_ignore_ascii_case("null") => Truth::from(left.is_null()),
+        Operand::Boolean(b) => Truth::from(left.compare(&Value::Bool(b)) == Some(Ordering::Equal)),
+        _ => Truth::Unknown,
+    }
+}
+
+#[cfg(test)]
+fn row(values: Vec<(&str, Value)>) -> ::std::collections::HashMap<String, Value> {
+    values.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+#[test]
+fn test_matches() {
+    let john = row(vec![("age", Value::Number(18.0)),
+                        ("name", Value::Text("John Cena".to_string())),
+                        ("active", Value::Bool(true))]);
+    assert!(::parse_filter("age=lt.20&name=st.john").matches(&john));
+    assert!(::parse_filter("age=gte.20|name=like.*Cena").matches(&john));
+    assert!(::parse_filter("age=in.17,18,19&active=is.true").matches(&john));
+    assert!(!::parse_filter("age=not_in.17,18,19").matches(&john));
+    assert!(!::parse_filter("name=like.john*").matches(&john));
+    assert!(::parse_filter("name=ilike.john*&age=lte.18&age=neq.20").matches(&john));
+    assert!(::parse_filter("product=is.null&age=is_not.null").matches(&john));
+}
+
+#[test]
+fn test_null_is_unknown() {
+    let nobody = row(vec![("age", Value::Null)]);
+    assert_eq!(::parse_filter("age=lt.20").eval(&nobody), Truth::Unknown);
+    assert_eq!(::parse_filter("age=neq.20").eval(&nobody), Truth::Unknown);
+    assert_eq!(::parse_filter("age=not_in.1,2").eval(&nobody), Truth::Unknown);
+    assert_eq!(::parse_filter("name=st.john").eval(&nobody), Truth::Unknown);
+    // unknown AND false is false, unknown OR true is true
+    assert_eq!(::parse_filter("age=lt.20&age=is_not.null").eval(&nobody), Truth::False);
+    assert_eq!(::parse_filter("age=lt.20|age=is.null").eval(&nobody), Truth::True);
+    assert_eq!(::parse_filter("age=lt.20|age=gt.20").eval(&nobody), Truth::Unknown);
+    assert!(!::parse_filter("age=lt.20|age=gt.20").matches(&nobody));
+}
+
+#[test]
+fn test_json_rows() {
+    let rows: Vec<::serde_json::Value> =
+        ::serde_json::from_str(r#"[{"name": "技術通報", "price": 100},
+                                   {"name": "° ͜ʖ ͡°", "price": null},
+                                   {"name": "50% off", "price": 20.5}]"#)
+            .unwrap();
+    let names = |input: &str| -> Vec<String> {
+        let filter = ::parse_filter(input);
+        rows.iter()
+            .filter(|row| filter.matches(row))
+            .map(|row| row["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(names("price=lt.100.0"), vec!["50% off"]);
+    assert_eq!(names("price=is.null|name=st.技術"), vec!["技術通報", "° ͜ʖ ͡°"]);
+    assert_eq!(names("name=ilike.*° ͜ʖ ͡°*"), vec!["° ͜ʖ ͡°"]);
+    assert_eq!(names("name=like.*%*"), vec!["50% off"]);
+}
+
+#[test]
+fn test_full_text() {
+    let post = row(vec![("body", Value::Text("The fat cat sat".to_string())), ("title", Value::Null)]);
+    assert!(::parse_filter("body=fts(english).fat&cat").matches(&post));
+    assert!(::parse_filter("body=fts.fat<->cat&!dog").matches(&post));
+    assert!(!::parse_filter("body=phfts.cat fat").matches(&post));
+    assert!(::parse_filter("body=plfts.cat fat").matches(&post));
+    assert!(::parse_filter("body=wfts.dog or \"cat sat\"").matches(&post));
+    assert_eq!(::parse_filter("title=fts.cat").eval(&post), Truth::Unknown);
+    // no words, no match
+    assert_eq!(::parse_filter("body=plfts.!!").eval(&post), Truth::False);
+}
+
+#[test]
+fn test_relative_times() {
+    use relative::{resolve_filter, FixedClock};
+    // 2024-01-31T13:45:00Z
+    let clock = FixedClock(1706708700);
+    let task = row(vec![("created_at", Value::Text("2024-01-20T08:00:00Z".to_string())),
+                        ("due", Value::Text("2025-02-01".to_string()))]);
+    let matches = |filter: &str| resolve_filter(&::parse_filter(filter), &clock).matches(&task);
+    assert!(matches("created_at=lt.now-7d&due=gt.today+1y"));
+    assert!(matches("created_at=gte.start_of_month"));
+    assert!(!matches("created_at=lt.now-14d"));
+    assert!(!matches("due=in.@today,@today+1y"));
+    assert!(matches("due=in.@today,@today+1y+1d"));
+}
+
+#[test]
+fn test_collations() {
+    let cafe = row(vec![("name", Value::Text("Cafe\u{301} Noir".to_string())),
+                        ("city", Value::Text("Straße".to_string()))]);
+    let mut collations = Collations::new(Collation {
+        normalization: Some(::collation::Normalization::Nfc),
+        ..Default::default()
+    });
+    assert!(!::parse_filter("name=eq.Café Noir").matches(&cafe));
+    assert!(::parse_filter("name=eq.Café Noir").matches_with(&cafe, &collations));
+    assert!(!::parse_filter("name=in.cafe noir,tea").matches_with(&cafe, &collations));
+    collations.columns.insert("name".to_string(), Collation::insensitive());
+    assert!(::parse_filter("name=in.cafe noir,tea").matches_with(&cafe, &collations));
+    assert!(::parse_filter("name=st.cafe&name=lt.cafe p").matches_with(&cafe, &collations));
+    assert!(::parse_filter("name=plfts.noir cafe").matches_with(&cafe, &collations));
+    assert!(::parse_filter("name=match.^Café").matches_with(&cafe, &collations));
+    assert!(!::parse_filter("name=match.^Café").matches(&cafe));
+    // the city keeps the collation of the query
+    assert!(!::parse_filter("city=eq.STRASSE").matches_with(&cafe, &collations));
+    assert!(::parse_filter("city=ilike.strasse").matches_with(&cafe, &collations));
+    let german = Collations::new(Collation::locale("de").unwrap());
+    assert!(::parse_filter("city=gt.Strasse&city=lt.Strasst").matches_with(&cafe, &german));
+}
+
+#[test]
+fn test_regular_expressions() {
+    let user = row(vec![("name", Value::Text("John".to_string())), ("age", Value::Number(30.0)), ("nick", Value::Null)]);
+    assert!(::parse_filter("name=match.^Jo(hn|e)$").matches(&user));
+    assert!(!::parse_filter("name=match.^jo").matches(&user));
+    assert!(::parse_filter("name=imatch.^jo").matches(&user));
+    assert_eq!(::parse_filter("nick=match.b").eval(&user), Truth::Unknown);
+    // a number is matched as text, as sqlite does
+    assert!(::parse_filter("age=match.^3").matches(&user));
+}
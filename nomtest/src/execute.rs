@@ -0,0 +1,566 @@
+//! Execute a whole `Query` over an in-memory collection
+//!
+//! The steps are the same as in sql: the filters, then the grouping and the
+//! having filters, then the order and at last the range. A group is a new row
+//! made of the group_by columns, `count(*)` and every aggregate used in the
+//! having filters or the order. The texts are compared by their code points,
+//! or by the `Collations` of the query with `execute_with`.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use collation::{Collation, Collations};
+use eval::{self, matches_all, matches_all_with};
+use expr::Expr;
+use row::{Row, Value};
+use {Direction, Function, NullsWhere, Operand, Order, Query};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Error {
+    /// a function which is not one of the aggregates: count, sum, avg, min, max
+    UnknownAggregate(String),
+    /// group_by is a column or a function
+    InvalidGroupBy(Operand),
+    /// the cursor of a keyset has to be turned into a filter by `keyset::resolve` first
+    UnresolvedCursor,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownAggregate(ref name) => write!(f, "unknown aggregate function: {}", name),
+            Error::InvalidGroupBy(ref op) => write!(f, "invalid group_by: {:?}", op),
+            Error::UnresolvedCursor => write!(f, "the cursor has not been resolved"),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// a row of the result, either one of the input rows or a group of them
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Record<R> {
+    Row(R),
+    Group(Group),
+}
+
+/// the group_by columns and the aggregates of a group, in that order
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Group {
+    pub values: Vec<(String, Value)>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct ResultSet<R> {
+    pub rows: Vec<Record<R>>,
+    /// the number of rows, or groups, before the range is applied
+    pub total: usize,
+}
+
+impl Row for Group {
+    fn get(&self, column: &str) -> Option<Value> {
+        self.values.iter().find(|&(name, _)| name == column).map(|(_, value)| value.clone())
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.values.iter().map(|(name, _)| name.to_string()).collect()
+    }
+}
+
+impl<R: Row> Row for Record<R> {
+    fn get(&self, column: &str) -> Option<Value> {
+        match *self {
+            Record::Row(ref row) => row.get(column),
+            Record::Group(ref group) => group.get(column),
+        }
+    }
+
+    fn columns(&self) -> Vec<String> {
+        match *self {
+            Record::Row(ref row) => row.columns(),
+            Record::Group(ref group) => group.columns(),
+        }
+    }
+
+    fn get_json(&self, column: &str) -> ::serde_json::Value {
+        match *self {
+            Record::Row(ref row) => row.get_json(column),
+            Record::Group(ref group) => group.get_json(column),
+        }
+    }
+}
+
+pub fn execute<R, I>(query: &Query, rows: I) -> Result<ResultSet<R>, Error>
+    where R: Row,
+          I: IntoIterator<Item = R>
+{
+    execute_with(query, rows, &Collations::default())
+}
+
+/// the query, with the texts compared and ordered by the collations
+pub fn execute_with<R, I>(query: &Query, rows: I, collations: &Collations) -> Result<ResultSet<R>, Error>
+    where R: Row,
+          I: IntoIterator<Item = R>
+{
+    let bounds = match query.range {
+        Some(ref range) => Some(range.bounds().ok_or(Error::UnresolvedCursor)?),
+        None => None,
+    };
+    let matching: Vec<R> = rows.into_iter().filter(|row| matches_all_with(&query.filters, row, collations)).collect();
+    let mut records: Vec<Record<R>> = if query.group_by.is_empty() {
+        matching.into_iter().map(Record::Row).collect()
+    } else {
+        group(query, matching, collations)?
+            .into_iter()
+            .filter(|group| matches_all_with(&query.having, group, collations))
+            .map(Record::Group)
+            .collect()
+    };
+    sort(&mut records, &query.order_by, collations);
+    let total = records.len();
+    let rows = match bounds {
+        Some((offset, limit)) => records.into_iter().skip(offset).take(limit).collect(),
+        None => records,
+    };
+    Ok(ResultSet { rows, total })
+}
+
+/// whether the rows can be filtered one at a time, the query has no order and no groups
+pub fn is_streamable(query: &Query) -> bool {
+    query.order_by.is_empty() && query.group_by.is_empty()
+}
+
+/// the rows matching a streamable query, lazily. The range is applied as the
+/// rows go by and no row is read once it is full, the cursor of a keyset has
+/// to be resolved first
+pub fn stream<'a, R, I>(query: &'a Query, rows: I) -> Result<impl Iterator<Item = R> + 'a, Error>
+    where R: Row + 'a,
+          I: IntoIterator<Item = R>,
+          I::IntoIter: 'a
+{
+    let (offset, limit) = match query.range {
+        Some(ref range) => range.bounds().ok_or(Error::UnresolvedCursor)?,
+        None => (0, usize::MAX),
+    };
+    Ok(rows.into_iter()
+        .filter(move |row| matches_all(&query.filters, row))
+        .skip(offset)
+        .take(limit))
+}
+
+/// the columns of the result, the row columns unless the query groups the rows
+pub fn result_columns(query: &Query, row_columns: &[String]) -> Vec<String> {
+    if query.group_by.is_empty() {
+        return row_columns.to_vec();
+    }
+    let mut columns: Vec<String> = query.group_by
+        .iter()
+        .map(|operand| match *operand {
+            Operand::Function(ref function) => function.column_name(),
+            Operand::Column(ref column) | Operand::Value(ref column) => column.to_string(),
+            _ => String::new(),
+        })
+        .collect();
+    columns.push("count(*)".to_string());
+    columns.extend(aggregates(query).iter().map(Function::column_name));
+    columns
+}
+
+fn group<R: Row>(query: &Query, rows: Vec<R>, collations: &Collations) -> Result<Vec<Group>, Error> {
+    let mut names = vec![];
+    for operand in &query.group_by {
+        match *operand {
+            Operand::Column(ref column) => names.push(column.to_string()),
+            Operand::Function(ref function) => names.push(function.column_name()),
+            _ => return Err(Error::InvalidGroupBy(operand.clone())),
+        }
+    }
+    let aggregates = aggregates(query);
+    for function in &aggregates {
+        aggregate::<R>(function, &[], collations)?;
+    }
+
+    // the groups are kept in the order they are first seen
+    let mut keys: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<(Vec<Value>, Vec<R>)> = vec![];
+    for row in rows {
+        let key: Vec<Value> = query.group_by.iter().map(|op| eval::operand_value(op, &row)).collect();
+        let index = *keys.entry(group_key(&key, &query.group_by, collations)).or_insert(groups.len());
+        if index == groups.len() {
+            groups.push((key, vec![]));
+        }
+        groups[index].1.push(row);
+    }
+
+    let mut result = vec![];
+    for (key, rows) in groups {
+        let mut values: Vec<(String, Value)> = names.iter().cloned().zip(key).collect();
+        values.push(("count(*)".to_string(), Value::Number(rows.len() as f64)));
+        for function in &aggregates {
+            values.push((function.column_name(), aggregate(function, &rows, collations)?));
+        }
+        result.push(Group { values });
+    }
+    Ok(result)
+}
+
+/// the aggregates used in the having filters and in the order, `count(*)` is always there
+pub fn aggregates(query: &Query) -> Vec<Function> {
+    let mut functions: Vec<Function> = vec![];
+    {
+        let mut add = |operand: &Operand| {
+            if let Operand::Function(ref function) = *operand {
+                if function.column_name() != "count(*)" && !functions.contains(function) {
+                    functions.push(function.clone());
+                }
+            }
+        };
+        if let Some(expr) = Expr::from_filters(&query.having) {
+            for condition in expr.conditions() {
+                add(&condition.left);
+                add(&condition.right);
+            }
+        }
+        for order in &query.order_by {
+            add(&order.operand);
+        }
+    }
+    functions
+}
+
+fn aggregate<R: Row>(function: &Function, rows: &[R], collations: &Collations) -> Result<Value, Error> {
+    let values: Vec<Value> = match function.params.first() {
+        Some(param) => {
+            rows.iter()
+                .map(|row| eval::operand_value(param, row))
+                .filter(|value| !value.is_null())
+                .collect()
+        }
+        None => vec![],
+    };
+    let numbers = || values.iter().filter_map(|value| match *value {
+        Value::Number(n) => Some(n),
+        Value::Text(ref s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    });
+    let value = match function.function.to_lowercase().as_str() {
+        "count" => {
+            match function.params.first() {
+                None => Value::Number(rows.len() as f64),
+                Some(&Operand::Value(ref v)) | Some(&Operand::Column(ref v)) if v == "*" => {
+                    Value::Number(rows.len() as f64)
+                }
+                Some(_) => Value::Number(values.len() as f64),
+            }
+        }
+        "sum" if values.is_empty() => Value::Null,
+        "sum" => Value::Number(numbers().sum()),
+        "avg" => {
+            let count = numbers().count();
+            if count == 0 {
+                Value::Null
+            } else {
+                Value::Number(numbers().sum::<f64>() / count as f64)
+            }
+        }
+        "min" => extreme(&values, Ordering::Less, collations.of(&Operand::Function(function.clone()))),
+        "max" => extreme(&values, Ordering::Greater, collations.of(&Operand::Function(function.clone()))),
+        _ => return Err(Error::UnknownAggregate(function.function.to_string())),
+    };
+    Ok(value)
+}
+
+fn extreme(values: &[Value], wanted: Ordering, collation: &Collation) -> Value {
+    let mut result = Value::Null;
+    for value in values {
+        if result.is_null() || cmp_values(value, &result, collation) == wanted {
+            result = value.clone();
+        }
+    }
+    result
+}
+
+/// a total order of the non null values: booleans, numbers then texts, the
+/// texts by the collation and NaN after the other numbers
+fn cmp_values(a: &Value, b: &Value, collation: &Collation) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match *value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::Text(_) => 3,
+        }
+    }
+    let is_nan = |value: &Value| matches!(*value, Value::Number(n) if n.is_nan());
+    rank(a).cmp(&rank(b))
+        .then_with(|| collation.compare_values(a, b).unwrap_or_else(|| is_nan(a).cmp(&is_nan(b))))
+}
+
+/// the key of the group of the values, the texts the collation does not tell
+/// apart are in one group
+fn group_key(values: &[Value], group_by: &[Operand], collations: &Collations) -> String {
+    let mut key = String::new();
+    for (value, operand) in values.iter().zip(group_by) {
+        match *value {
+            Value::Text(ref text) => key.push_str(&format!("{:?};", Value::Text(collations.of(operand).fold(text)))),
+            ref value => key.push_str(&format!("{:?};", value)),
+        }
+    }
+    key
+}
+
+/// a stable sort, the nulls are last in ascending and first in descending order
+/// unless the order says otherwise, the same as in postgresql
+fn sort<R: Row>(records: &mut [Record<R>], order_by: &[Order], collations: &Collations) {
+    if order_by.is_empty() {
+        return;
+    }
+    records.sort_by(|a, b| {
+        for order in order_by {
+            let descending = order.direction == Some(Direction::DESC);
+            let nulls_first = match order.nulls_where {
+                Some(NullsWhere::FIRST) => true,
+                Some(NullsWhere::LAST) => false,
+                None => descending,
+            };
+            let collation = collations.of(&order.operand);
+            let left = eval::operand_value(&order.operand, a);
+            let right = eval::operand_value(&order.operand, b);
+            let ordering = match (left.is_null(), right.is_null()) {
+                (true, true) => Ordering::Equal,
+                (true, false) if nulls_first => Ordering::Less,
+                (true, false) => Ordering::Greater,
+                (false, true) if nulls_first => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) if descending => cmp_values(&right, &left, collation),
+                (false, false) => cmp_values(&left, &right, collation),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+}
+
+#[cfg(test)]
+fn people() -> Vec<::serde_json::Value> {
+    ::serde_json::from_str(r#"[
+        {"name": "John", "dept": "sales", "age": 30, "salary": 100},
+        {"name": "Jane", "dept": "tech", "age": 25, "salary": 300},
+        {"name": "Joe", "dept": "sales", "age": null, "salary": 250},
+        {"name": "技術通報", "dept": "tech", "age": 41, "salary": null},
+        {"name": "Ann", "dept": "hr", "age": 25, "salary": 150}
+    ]"#)
+        .unwrap()
+}
+
+#[cfg(test)]
+fn names<R: Row>(result: &ResultSet<R>) -> Vec<String> {
+    result.rows
+        .iter()
+        .map(|record| match record.get("name") {
+            Some(Value::Text(name)) => name,
+            other => panic!("{:?}", other),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn order(column: &str, direction: Option<Direction>, nulls_where: Option<NullsWhere>) -> Order {
+    Order {
+        operand: Operand::Column(column.to_string()),
+        direction,
+        nulls_where,
+    }
+}
+
+#[test]
+fn test_filter_order_range() {
+    use {Limit, Range};
+    let query = Query {
+        filters: vec![::parse_filter("salary=gte.150|salary=is.null")],
+        order_by: vec![order("age", Some(Direction::DESC), None), order("name", None, None)],
+        range: Some(Range::Limit(Limit {
+            limit: 2,
+            offset: Some(1),
+        })),
+        ..Default::default()
+    };
+    let result = execute(&query, people()).unwrap();
+    assert_eq!(result.total, 4);
+    // Joe has no age so comes first in descending order
+    assert_eq!(names(&result), vec!["技術通報", "Ann"]);
+}
+
+#[test]
+fn test_nulls_where() {
+    use {Page, Range};
+    let query = Query {
+        order_by: vec![order("age", Some(Direction::ASC), Some(NullsWhere::FIRST)),
+                       order("name", Some(Direction::DESC), None)],
+        ..Default::default()
+    };
+    let result = execute(&query, people()).unwrap();
+    assert_eq!(names(&result), vec!["Joe", "Jane", "Ann", "John", "技術通報"]);
+
+    let query = Query {
+        order_by: vec![order("age", None, None)],
+        range: Some(Range::Page(Page {
+            page: 2,
+            page_size: 2,
+        })),
+        ..Default::default()
+    };
+    let result = execute(&query, people()).unwrap();
+    assert_eq!(result.total, 5);
+    assert_eq!(names(&result), vec!["John", "技術通報"]);
+}
+
+#[test]
+fn test_group_by() {
+    use {Condition, Equality, Filter};
+    let sum = Function {
+        function: "sum".to_string(),
+        params: vec![Operand::Column("salary".to_string())],
+    };
+    let query = Query {
+        group_by: vec![Operand::Column("dept".to_string())],
+        having: vec![Filter {
+                         condition: Condition {
+                             left: Operand::Function(Function {
+                                 function: "count".to_string(),
+                                 params: vec![],
+                             }),
+                             equality: Equality::GT,
+                             right: Operand::Number(1.0),
+                         },
+                         connector: None,
+                         sub_filters: vec![],
+                     }],
+        order_by: vec![Order {
+                           operand: Operand::Function(sum.clone()),
+                           direction: Some(Direction::DESC),
+                           nulls_where: None,
+                       }],
+        ..Default::default()
+    };
+    let result = execute(&query, people()).unwrap();
+    assert_eq!(result.total, 2);
+    let groups: Vec<Group> = result.rows
+        .into_iter()
+        .map(|record| match record {
+            Record::Group(group) => group,
+            Record::Row(row) => panic!("{:?}", row),
+        })
+        .collect();
+    assert_eq!(groups,
+               vec![Group {
+                        values: vec![("dept".to_string(), Value::Text("sales".to_string())),
+                                     ("count(*)".to_string(), Value::Number(2.0)),
+                                     ("sum(salary)".to_string(), Value::Number(350.0))],
+                    },
+                    Group {
+                        values: vec![("dept".to_string(), Value::Text("tech".to_string())),
+                                     ("count(*)".to_string(), Value::Number(2.0)),
+                                     ("sum(salary)".to_string(), Value::Number(300.0))],
+                    }]);
+}
+
+#[test]
+fn test_aggregates() {
+    let rows = people();
+    let call = |name: &str, column: &str| {
+        aggregate(&Function {
+                       function: name.to_string(),
+                       params: vec![Operand::Column(column.to_string())],
+                   },
+                   &rows,
+                   &Collations::default())
+    };
+    assert_eq!(call("count", "age"), Ok(Value::Number(4.0)));
+    assert_eq!(call("avg", "salary"), Ok(Value::Number(200.0)));
+    assert_eq!(call("min", "name"), Ok(Value::Text("Ann".to_string())));
+    assert_eq!(call("max", "age"), Ok(Value::Number(41.0)));
+    assert_eq!(call("sum", "missing"), Ok(Value::Null));
+    assert_eq!(call("median", "age"), Err(Error::UnknownAggregate("median".to_string())));
+}
+
+#[test]
+fn test_collations() {
+    let rows: Vec<::serde_json::Value> = ::serde_json::from_str(r#"[
+        {"name": "Zebra", "dept": "shop"},
+        {"name": "Äpfel", "dept": "shop"},
+        {"name": "apfel", "dept": "zoo"},
+        {"name": "Eclair", "dept": "zoo"},
+        {"name": "éclair", "dept": "zoo"}
+    ]"#).unwrap();
+    let query = ::parse_query("order_by=name").unwrap();
+    assert_eq!(names(&execute(&query, rows.clone()).unwrap()), vec!["Eclair", "Zebra", "apfel", "Äpfel", "éclair"]);
+    let mut collations = Collations::new(Collation::locale("de").unwrap());
+    assert_eq!(names(&execute_with(&query, rows.clone(), &collations).unwrap()),
+               vec!["apfel", "Äpfel", "Eclair", "éclair", "Zebra"]);
+    // the order is stable for the names which are the same
+    collations.columns.insert("name".to_string(), Collation::insensitive());
+    let query = ::parse_query("name=lt.f&order_by=name.desc").unwrap();
+    assert_eq!(names(&execute_with(&query, rows.clone(), &collations).unwrap()),
+               vec!["Eclair", "éclair", "Äpfel", "apfel"]);
+    let query = ::parse_query("group_by=dept&order_by=max(name)").unwrap();
+    let maximum = |result: ResultSet<::serde_json::Value>| -> Vec<Option<Value>> {
+        result.rows.iter().map(|record| record.get("max(name)")).collect()
+    };
+    assert_eq!(maximum(execute(&query, rows.clone()).unwrap()),
+               vec![Some(Value::Text("Äpfel".to_string())), Some(Value::Text("éclair".to_string()))]);
+    assert_eq!(maximum(execute_with(&query, rows.clone(), &collations).unwrap()),
+               vec![Some(Value::Text("Eclair".to_string())), Some(Value::Text("Zebra".to_string()))]);
+    // the names the collation does not tell apart are one group
+    let query = ::parse_query("group_by=name").unwrap();
+    assert_eq!(execute(&query, rows.clone()).unwrap().rows.len(), 5);
+    let counts: Vec<Option<Value>> = execute_with(&query, rows, &collations).unwrap().rows.iter()
+        .map(|record| record.get("count(*)"))
+        .collect();
+    assert_eq!(counts, vec![Some(Value::Number(1.0)), Some(Value::Number(2.0)), Some(Value::Number(2.0))]);
+}
+
+#[test]
+fn test_cmp_values() {
+    let mut values = vec![Value::Text("b".to_string()), Value::Number(f64::NAN), Value::Text("5".to_string()),
+                          Value::Number(7.0), Value::Bool(true), Value::Text("a".to_string()), Value::Number(5.0)];
+    values.sort_by(|a, b| cmp_values(a, b, &Collation::default()));
+    assert_eq!(format!("{:?}", values),
+               r#"[Bool(true), Number(5.0), Number(7.0), Number(NaN), Text("5"), Text("a"), Text("b")]"#);
+    // by rank first, a text which reads as a number is still after the numbers
+    assert_eq!(cmp_values(&Value::Text("10".to_string()), &Value::Number(9.0), &Collation::default()), Ordering::Greater);
+    assert_eq!(cmp_values(&Value::Text("1".to_string()), &Value::Number(9.0), &Collation::default()), Ordering::Greater);
+}
+
+#[test]
+fn test_stream() {
+    let query = ::parse_query("salary=gte.150&limit=2&offset=1").unwrap();
+    assert!(is_streamable(&query));
+    assert!(!is_streamable(&::parse_query("order_by=name").unwrap()));
+    let mut read = 0;
+    let names: Vec<String> = stream(&query, people().into_iter().inspect(|_| read += 1))
+        .unwrap()
+        .map(|row| row["name"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(names, vec!["Joe", "Ann"]);
+    assert_eq!(read, 5);
+    // the rows after the last one of the range are not read
+    let query = ::parse_query("dept=eq.sales&limit=1").unwrap();
+    let mut read = 0;
+    assert_eq!(stream(&query, people().into_iter().inspect(|_| read += 1)).unwrap().count(), 1);
+    assert_eq!(read, 1);
+    let query = ::parse_query("after=abc.def&limit=1").unwrap();
+    assert!(matches!(stream(&query, people()), Err(Error::UnresolvedCursor)));
+}
@@ -0,0 +1,298 @@
+//! The boolean expression a filter stands for
+//!
+//! A `Filter` is a chain: its condition followed by the sub_filters, each
+//! sub_filter is connected to what comes before it with its own connector.
+//! A sub_filter which has sub_filters of its own is a parenthesized group, it
+//! is AND-ed to what comes before it and its connector connects its condition
+//! to its own sub_filters: `a&(b|c)` is `a` with the group `b`, `OR`, `[c]`.
+//! Like in sql, AND binds tighter than OR, so `a|b&c` is `a|(b&c)`.
+//! The filters of a query are all AND-ed together.
+//!
+//! Output backends translate an `Expr` instead of walking the chain themselves.
+
+use std::fmt;
+
+use {Condition, Connector, Filter};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Expr {
+    Condition(Condition),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    /// the expression of all the filters of a query, None when there is no filter
+    pub fn from_filters(filters: &[Filter]) -> Option<Expr> {
+        let mut exprs: Vec<Expr> = filters.iter().map(Expr::from).collect();
+        match exprs.len() {
+            0 => None,
+            1 => exprs.pop(),
+            _ => Some(Expr::And(exprs)),
+        }
+    }
+}
+
+impl Expr {
+    /// all the conditions of the expression, from left to right
+    pub fn conditions(&self) -> Vec<&Condition> {
+        match *self {
+            Expr::Condition(ref condition) => vec![condition],
+            Expr::And(ref exprs) | Expr::Or(ref exprs) => {
+                exprs.iter().flat_map(|expr| expr.conditions()).collect()
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// the filter chain of the expression, the inverse of `Expr::from(&Filter)`. The
+    /// conditions of an AND may be reordered so the chain starts with a condition,
+    /// None when an AND has only ORs in it, which a chain can not start with
+    pub fn to_filter(&self) -> Option<Filter> {
+        let mut items = self.chain_items()?;
+        let head = items.remove(0);
+        Some(Filter {
+            condition: head.condition,
+            connector: None,
+            sub_filters: items,
+        })
+    }
+
+    /// the filters which AND-ed together are the expression, an AND which can not be
+    /// a single chain is split in one filter per term: `(a|b)&(c|d)` is two filters.
+    /// When such an AND is inside an OR, its first OR is distributed over the rest:
+    /// `(a|b)&(c|d)|e` is `a&(c|d)|b&(c|d)|e`
+    pub fn to_filters(&self) -> Vec<Filter> {
+        if let Some(filter) = self.to_filter() {
+            return vec![filter];
+        }
+        match *self {
+            Expr::And(ref exprs) => exprs.iter().flat_map(Expr::to_filters).collect(),
+            _ => self.distributed().to_filter().into_iter().collect(),
+        }
+    }
+
+    /// the same expression, where every AND has a term which is not an OR
+    fn distributed(&self) -> Expr {
+        match *self {
+            Expr::Condition(_) => self.clone(),
+            Expr::Or(ref exprs) => Expr::Or(exprs.iter().map(Expr::distributed).collect()),
+            Expr::And(ref exprs) => {
+                let exprs: Vec<Expr> = exprs.iter().map(Expr::distributed).collect();
+                match exprs.split_first() {
+                    Some((Expr::Or(first), rest)) if rest.iter().all(|expr| matches!(*expr, Expr::Or(_))) => {
+                        Expr::Or(first.iter()
+                            .map(|expr| {
+                                let mut terms = vec![expr.clone()];
+                                terms.extend(rest.iter().cloned());
+                                Expr::And(terms).distributed()
+                            })
+                            .collect())
+                    }
+                    _ => Expr::And(exprs),
+                }
+            }
+        }
+    }
+
+    /// the filters of the chain, the first has no connector and no sub_filters
+    fn chain_items(&self) -> Option<Vec<Filter>> {
+        let plain = |condition: &Condition, connector| Filter {
+            condition: condition.clone(),
+            connector,
+            sub_filters: vec![],
+        };
+        match *self {
+            Expr::Condition(ref condition) => Some(vec![plain(condition, None)]),
+            // AND binds tighter, the ANDs in an OR need no parentheses
+            Expr::Or(ref exprs) => {
+                let mut items = vec![];
+                for expr in exprs {
+                    let mut chain = expr.chain_items()?;
+                    if !items.is_empty() {
+                        chain[0].connector = Some(Connector::OR);
+                    }
+                    items.extend(chain);
+                }
+                Some(items)
+            }
+            Expr::And(ref exprs) => {
+                let head = exprs.iter().position(|expr| !matches!(*expr, Expr::Or(_)))?;
+                let mut items = exprs[head].chain_items()?;
+                for expr in exprs.iter().enumerate().filter(|&(i, _)| i != head).map(|(_, expr)| expr) {
+                    match *expr {
+                        Expr::Or(_) => {
+                            let mut group = expr.chain_items()?;
+                            let first = group.remove(0);
+                            // the group holds the connector to its first sub_filter
+                            let connector = link(&group[0]);
+                            if group[0].sub_filters.is_empty() {
+                                group[0].connector = None;
+                            }
+                            items.push(Filter {
+                                condition: first.condition,
+                                connector,
+                                sub_filters: group,
+                            });
+                        }
+                        _ => {
+                            let mut chain = expr.chain_items()?;
+                            chain[0].connector = Some(Connector::AND);
+                            items.extend(chain);
+                        }
+                    }
+                }
+                Some(items)
+            }
+        }
+    }
+}
+
+/// the connector of a sub_filter to what comes before it, a group is AND-ed
+fn link(sub_filter: &Filter) -> Option<Connector> {
+    if sub_filter.sub_filters.is_empty() {
+        sub_filter.connector.clone()
+    } else {
+        Some(Connector::AND)
+    }
+}
+
+/// in the query syntax, an OR inside an AND is put in parentheses
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Condition(ref condition) => write!(f, "{}", condition),
+            Expr::And(ref exprs) => {
+                let terms: Vec<String> = exprs.iter()
+                    .map(|expr| match *expr {
+                        Expr::Or(_) => format!("({})", expr),
+                        _ => expr.to_string(),
+                    })
+                    .collect();
+                write!(f, "{}", terms.join("&"))
+            }
+            Expr::Or(ref exprs) => {
+                let terms: Vec<String> = exprs.iter().map(Expr::to_string).collect();
+                write!(f, "{}", terms.join("|"))
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a Filter> for Expr {
+    fn from(filter: &'a Filter) -> Self {
+        chain(&filter.condition, &filter.connector, &filter.sub_filters)
+    }
+}
+
+/// the connector of a filter, when it has one, connects its condition to its first sub_filter
+fn chain(condition: &Condition, connector: &Option<Connector>, sub_filters: &[Filter]) -> Expr {
+    let mut disjuncts = vec![];
+    let mut conjuncts = vec![Expr::Condition(condition.clone())];
+    for (i, sub_filter) in sub_filters.iter().enumerate() {
+        let term = if sub_filter.sub_filters.is_empty() {
+            Expr::Condition(sub_filter.condition.clone())
+        } else {
+            chain(&sub_filter.condition, &sub_filter.connector, &sub_filter.sub_filters)
+        };
+        let connector = if i == 0 { connector.clone().or_else(|| link(sub_filter)) } else { link(sub_filter) };
+        if let Some(Connector::OR) = connector {
+            disjuncts.push(collapse(conjuncts, Expr::And));
+            conjuncts = vec![];
+        }
+        conjuncts.push(term);
+    }
+    disjuncts.push(collapse(conjuncts, Expr::And));
+    collapse(disjuncts, Expr::Or)
+}
+
+fn collapse<F>(mut exprs: Vec<Expr>, wrap: F) -> Expr
+    where F: Fn(Vec<Expr>) -> Expr
+{
+    if exprs.len() == 1 {
+        exprs.remove(0)
+    } else {
+        wrap(exprs)
+    }
+}
+
+#[cfg(test)]
+fn cond(column: &str, equality: ::Equality, value: f64) -> Condition {
+    Condition {
+        left: ::Operand::Column(column.to_string()),
+        equality,
+        right: ::Operand::Number(value),
+    }
+}
+
+#[test]
+fn test_precedence() {
+    use nom::IResult;
+    use Equality::*;
+    let filter = match ::filter(&b"a=eq.1|b=eq.2&c=eq.3"[..]) {
+        IResult::Done(_, filter) => filter,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(Expr::from(&filter),
+               Expr::Or(vec![Expr::Condition(cond("a", EQ, 1.0)),
+                             Expr::And(vec![Expr::Condition(cond("b", EQ, 2.0)),
+                                            Expr::Condition(cond("c", EQ, 3.0))])]));
+}
+
+#[test]
+fn test_group() {
+    use Equality::*;
+    // a=lt.20&(b=eq.1|c=eq.2)
+    let filter = Filter {
+        condition: cond("a", LT, 20.0),
+        connector: None,
+        sub_filters: vec![Filter {
+                              condition: cond("b", EQ, 1.0),
+                              connector: Some(Connector::OR),
+                              sub_filters: vec![Filter {
+                                                    condition: cond("c", EQ, 2.0),
+                                                    connector: None,
+                                                    sub_filters: vec![],
+                                                }],
+                          }],
+    };
+    assert_eq!(Expr::from(&filter),
+               Expr::And(vec![Expr::Condition(cond("a", LT, 20.0)),
+                              Expr::Or(vec![Expr::Condition(cond("b", EQ, 1.0)),
+                                            Expr::Condition(cond("c", EQ, 2.0))])]));
+    assert_eq!(Expr::from_filters(&[]), None);
+    assert_eq!(Expr::from_filters(&[filter.clone(), filter.clone()]),
+               Some(Expr::And(vec![Expr::from(&filter), Expr::from(&filter)])));
+}
+
+#[test]
+fn test_display() {
+    for input in &["a=lt.20&(b=eq.1|c=is.null)", "a=eq.1|b=in.1,2&c=like.*x*", "count(*)=gt.1"] {
+        assert_eq!(Expr::from(&::parse_filter(input)).to_string(), *input);
+    }
+    let input = "age=lt.20&(product=eq.134|price=lt.100.5)&from=users&group_by=dept&having=count(*)=gt.1\
+                 &order_by=name.desc.nulls_last,age&limit=10&offset=20&format=csv";
+    let query = ::parse_query(input).unwrap();
+    assert_eq!(query.to_string(), input);
+    assert_eq!(::parse_query(&query.to_string()), Ok(query));
+}
+
+#[test]
+fn test_to_filter() {
+    for input in &["a=eq.1", "a=lt.20&(b=eq.1|c=eq.2)&d=eq.4", "a=eq.1|b=eq.2&(c=eq.3|d=eq.4)|e=eq.5"] {
+        let filter = ::parse_filter(input);
+        assert_eq!(Expr::from(&filter).to_filter(), Some(filter.clone()));
+    }
+    let expr = Expr::from(&::parse_filter("(a=eq.1|b=eq.2)&c=eq.3"));
+    assert_eq!(Expr::from(&expr.to_filter().unwrap()), expr);
+    let or = Expr::from(&::parse_filter("a=eq.1|b=eq.2"));
+    let and = Expr::And(vec![or.clone(), or.clone()]);
+    assert_eq!(and.to_filter(), None);
+    assert_eq!(and.to_filters(), vec![or.to_filter().unwrap(), or.to_filter().unwrap()]);
+    let e = Expr::from(&::parse_filter("e=eq.5"));
+    assert_eq!(Expr::Or(vec![and, e]).to_filters(),
+               vec![::parse_filter("a=eq.1&(a=eq.1|b=eq.2)|b=eq.2&(a=eq.1|b=eq.2)|e=eq.5")]);
+}
@@ -0,0 +1,307 @@
+//! Typed handles on the columns of a rust struct, for `#[derive(Filterable)]`
+//!
+//! A `Field` only has the operators which make sense for the type of its column:
+//! `like` is for texts, `lt` for numbers, texts and dates, `is_null` for the
+//! columns of an `Option`. `User::NAME.lt(true)` still compiles, the value is
+//! checked by `schema::validate` against the table of the struct.
+//!
+//! ```ignore
+//! #[derive(Filterable)]
+//! #[filterable(table = "users")]
+//! struct User {
+//!     id: i64,
+//!     #[filterable(rename = "full_name")]
+//!     name: String,
+//!     #[filterable(skip_sort)]
+//!     deleted_at: Option<String>,
+//! }
+//!
+//! let query = Query::from("users").filter(User::NAME.starts_with("Jo").and(User::DELETED_AT.is_null()));
+//! ```
+
+use std::marker::PhantomData;
+
+use builder::{col, Column};
+use expr::Expr;
+use policy::Role;
+use row::{Row, Value};
+use schema::{self, Type};
+use {Operand, Order};
+
+/// a struct whose fields are the columns of a table
+pub trait Filterable: Row {
+    /// the table, for `schema::validate`
+    fn table() -> schema::Table;
+
+    /// the columns which can not be filtered on or sorted by, as a policy role
+    fn role() -> Role;
+}
+
+/// the kinds of columns, which decide the operators of a field
+pub struct Text;
+pub struct Number;
+pub struct Bool;
+/// a date or a time, compared in its ISO 8601 form
+pub struct Date;
+/// a column of an `Option`, NULL when it is `None`
+pub struct Nullable<K>(PhantomData<K>);
+/// a column which can not be filtered on
+pub struct Opaque;
+
+/// the kinds with an order: `lt`, `lte`, `gt`, `gte`
+pub trait Ordered {}
+impl Ordered for Text {}
+impl Ordered for Number {}
+impl Ordered for Date {}
+impl<K: Ordered> Ordered for Nullable<K> {}
+
+/// the kinds with patterns: `like`, `ilike`, `starts_with`
+pub trait Textual {}
+impl Textual for Text {}
+impl<K: Textual> Textual for Nullable<K> {}
+
+/// the kinds which are true or false: `is`
+pub trait Boolean {}
+impl Boolean for Bool {}
+impl<K: Boolean> Boolean for Nullable<K> {}
+
+/// the kinds which can be filtered on: `eq`, `neq`, `is_in`, `not_in`
+pub trait Comparable {}
+impl Comparable for Text {}
+impl Comparable for Number {}
+impl Comparable for Bool {}
+impl Comparable for Date {}
+impl<K: Comparable> Comparable for Nullable<K> {}
+
+/// whether the column of a field can be sorted by
+pub struct Sorted;
+pub struct Unsorted;
+
+/// the column of a struct field
+pub struct Field<K, S = Sorted> {
+    name: &'static str,
+    kind: PhantomData<(K, S)>,
+}
+
+impl<K, S> Field<K, S> {
+    pub const fn new(name: &'static str) -> Self {
+        Field {
+            name,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn column(&self) -> Column {
+        col(self.name)
+    }
+}
+
+impl<K: Comparable, S> Field<K, S> {
+    pub fn eq<O: Into<Operand>>(&self, value: O) -> Expr {
+        self.column().eq(value)
+    }
+
+    pub fn neq<O: Into<Operand>>(&self, value: O) -> Expr {
+        self.column().neq(value)
+    }
+
+    pub fn is_in<O: Into<Operand>>(&self, values: O) -> Expr {
+        self.column().is_in(values)
+    }
+
+    pub fn not_in<O: Into<Operand>>(&self, values: O) -> Expr {
+        self.column().not_in(values)
+    }
+}
+
+impl<K: Ordered, S> Field<K, S> {
+    pub fn lt<O: Into<Operand>>(&self, value: O) -> Expr {
+        self.column().lt(value)
+    }
+
+    pub fn lte<O: Into<Operand>>(&self, value: O) -> Expr {
+        self.column().lte(value)
+    }
+
+    pub fn gt<O: Into<Operand>>(&self, value: O) -> Expr {
+        self.column().gt(value)
+    }
+
+    pub fn gte<O: Into<Operand>>(&self, value: O) -> Expr {
+        self.column().gte(value)
+    }
+}
+
+impl<K: Textual, S> Field<K, S> {
+    pub fn like(&self, pattern: &str) -> Expr {
+        self.column().like(pattern)
+    }
+
+    pub fn ilike(&self, pattern: &str) -> Expr {
+        self.column().ilike(pattern)
+    }
+
+    pub fn starts_with(&self, prefix: &str) -> Expr {
+        self.column().starts_with(prefix)
+    }
+
+    pub fn istarts_with(&self, prefix: &str) -> Expr {
+        self.column().istarts_with(prefix)
+    }
+}
+
+impl<K: Boolean, S> Field<K, S> {
+    pub fn is(&self, value: bool) -> Expr {
+        self.column().is(value)
+    }
+}
+
+impl<K, S> Field<Nullable<K>, S> {
+    pub fn is_null(&self) -> Expr {
+        self.column().is_null()
+    }
+
+    pub fn is_not_null(&self) -> Expr {
+        self.column().is_not_null()
+    }
+}
+
+impl<K> Field<K, Sorted> {
+    pub fn asc(&self) -> Order {
+        self.column().asc()
+    }
+
+    pub fn desc(&self) -> Order {
+        self.column().desc()
+    }
+}
+
+/// the field in the order of the database
+impl<'a, K> From<&'a Field<K, Sorted>> for Order {
+    fn from(field: &'a Field<K, Sorted>) -> Self {
+        Order::from(field.column())
+    }
+}
+
+/// the rust types of struct fields, with the kind of their column and their type in a schema
+pub trait FieldType {
+    type Kind;
+
+    fn column_type() -> Type;
+
+    fn nullable() -> bool {
+        false
+    }
+
+    fn to_value(&self) -> Value;
+}
+
+macro_rules! field_type {
+    ($kind:ident, $column_type:ident, $($t:ty),*) => {
+        $(
+            impl FieldType for $t {
+                type Kind = $kind;
+
+                fn column_type() -> Type {
+                    Type::$column_type
+                }
+
+                fn to_value(&self) -> Value {
+                    Value::Number(*self as f64)
+                }
+            }
+        )*
+    };
+}
+
+field_type!(Number, Integer, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+field_type!(Number, Number, f32, f64);
+
+impl FieldType for bool {
+    type Kind = Bool;
+
+    fn column_type() -> Type {
+        Type::Bool
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl FieldType for String {
+    type Kind = Text;
+
+    fn column_type() -> Type {
+        Type::Text
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl<T: FieldType> FieldType for Option<T> {
+    type Kind = Nullable<T::Kind>;
+
+    fn column_type() -> Type {
+        T::column_type()
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+
+    fn to_value(&self) -> Value {
+        self.as_ref().map_or(Value::Null, FieldType::to_value)
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod dates {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+    use std::fmt::Display;
+
+    use super::{Date, FieldType};
+    use row::Value;
+    use schema::Type;
+    use Operand;
+
+    macro_rules! date_type {
+        ($($t:ty),*) => {
+            $(
+                impl FieldType for $t {
+                    type Kind = Date;
+
+                    fn column_type() -> Type {
+                        Type::Text
+                    }
+
+                    fn to_value(&self) -> Value {
+                        Value::from_operand(&Operand::from(*self)).unwrap_or(Value::Null)
+                    }
+                }
+            )*
+        };
+    }
+
+    date_type!(NaiveDate, NaiveTime, NaiveDateTime);
+
+    impl<Tz: TimeZone> FieldType for DateTime<Tz>
+        where Tz::Offset: Display
+    {
+        type Kind = Date;
+
+        fn column_type() -> Type {
+            Type::Text
+        }
+
+        fn to_value(&self) -> Value {
+            Value::from_operand(&Operand::from(self.clone())).unwrap_or(Value::Null)
+        }
+    }
+}
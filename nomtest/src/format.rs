@@ -0,0 +1,269 @@
+//! Write result rows as csv, json or an aligned text table
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use csv;
+use serde_json::{self, Map};
+use unicode_width::UnicodeWidthStr;
+
+use row::{Row, Value};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Format {
+    Csv,
+    Json,
+    /// one json object per line
+    Ndjson,
+    Table,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "table" => Ok(Format::Table),
+            _ => Err(format!("unknown format: {}, expecting csv, json, ndjson or table", s)),
+        }
+    }
+}
+
+/// write the columns of the rows in the format
+pub fn write_rows<W, R>(out: &mut W, format: Format, columns: &[String], rows: &[R]) -> io::Result<()>
+    where W: Write,
+          R: Row
+{
+    let mut writer = RowWriter::new(out, format, columns.to_vec());
+    for row in rows {
+        writer.write(row)?;
+    }
+    writer.finish().map(|_| ())
+}
+
+/// writes the rows as they come, except for a table which is only written at
+/// the end as its columns are as wide as their widest value
+pub struct RowWriter<W: Write> {
+    out: W,
+    format: Format,
+    columns: Vec<String>,
+    /// no columns were given, json rows are written with all of their own
+    own_columns: bool,
+    rows: usize,
+    cells: Vec<Vec<(String, bool)>>,
+}
+
+impl<W: Write> RowWriter<W> {
+    /// without columns, the columns of the first row are the ones written
+    pub fn new(out: W, format: Format, columns: Vec<String>) -> Self {
+        RowWriter {
+            out,
+            format,
+            own_columns: columns.is_empty(),
+            columns,
+            rows: 0,
+            cells: vec![],
+        }
+    }
+
+    pub fn write<R: Row>(&mut self, row: &R) -> io::Result<()> {
+        if self.rows == 0 && self.own_columns {
+            self.columns = row.columns();
+        }
+        match self.format {
+            Format::Csv => {
+                if self.rows == 0 {
+                    let header = self.columns.clone();
+                    self.write_csv(&header)?;
+                }
+                let fields: Vec<String> = self.columns.iter().map(|c| text(row, c)).collect();
+                self.write_csv(&fields)?;
+            }
+            Format::Json => {
+                let object = serde_json::to_string_pretty(&self.json_object(row))?;
+                let indented: Vec<String> = object.lines().map(|line| format!("  {}", line)).collect();
+                let separator = if self.rows == 0 { "[" } else { "," };
+                writeln!(self.out, "{}", separator)?;
+                write!(self.out, "{}", indented.join("\n"))?;
+            }
+            Format::Ndjson => {
+                let object = self.json_object(row);
+                serde_json::to_writer(&mut self.out, &object)?;
+                writeln!(self.out)?;
+            }
+            Format::Table => {
+                let cells = self.columns
+                    .iter()
+                    .map(|c| match row.get(c) {
+                        Some(Value::Number(n)) => (n.to_string(), true),
+                        Some(value) => (value.to_text().unwrap_or_default(), false),
+                        None => (String::new(), false),
+                    })
+                    .collect();
+                self.cells.push(cells);
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// write what is left to write and hand back the output
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.format {
+            Format::Csv if self.rows == 0 => {
+                let header = self.columns.clone();
+                self.write_csv(&header)?;
+            }
+            Format::Json if self.rows == 0 => writeln!(self.out, "[]")?,
+            Format::Json => writeln!(self.out, "\n]")?,
+            Format::Table => write_table(&mut self.out, &self.columns, &self.cells)?,
+            Format::Csv | Format::Ndjson => (),
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn json_object<R: Row>(&self, row: &R) -> serde_json::Value {
+        if self.own_columns {
+            json_object(row, &row.columns())
+        } else {
+            json_object(row, &self.columns)
+        }
+    }
+
+    fn write_csv(&mut self, fields: &[String]) -> io::Result<()> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(fields)?;
+        let line = writer.into_inner().map_err(|e| e.into_error())?;
+        self.out.write_all(&line)
+    }
+}
+
+/// the json object of the columns of a row
+pub fn json_object<R: Row>(row: &R, columns: &[String]) -> serde_json::Value {
+    let mut object = Map::new();
+    for column in columns {
+        object.insert(column.to_string(), row.get_json(column));
+    }
+    serde_json::Value::Object(object)
+}
+
+/// numbers are aligned to the right, everything else to the left
+fn write_table<W: Write>(out: &mut W, columns: &[String], cells: &[Vec<(String, bool)>]) -> io::Result<()> {
+    if columns.is_empty() {
+        return Ok(());
+    }
+    let widths: Vec<usize> = columns.iter()
+        .enumerate()
+        .map(|(i, c)| {
+            cells.iter().map(|row| width(&row[i].0)).chain(Some(width(c))).max().unwrap_or(0)
+        })
+        .collect();
+    let header: Vec<String> = columns.iter().zip(&widths).map(|(c, w)| pad(c, *w, false)).collect();
+    writeln!(out, "{}", header.join(" | ").trim_end())?;
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    writeln!(out, "{}", rule.join("-+-"))?;
+    for row in cells {
+        let line: Vec<String> = row.iter()
+            .zip(&widths)
+            .map(|(&(ref cell, right), w)| pad(cell, *w, right))
+            .collect();
+        writeln!(out, "{}", line.join(" | ").trim_end())?;
+    }
+    Ok(())
+}
+
+fn text<R: Row>(row: &R, column: &str) -> String {
+    row.get(column).and_then(|value| value.to_text()).unwrap_or_default()
+}
+
+/// the columns the text takes in a terminal, a CJK character takes two
+fn width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+fn pad(s: &str, size: usize, right: bool) -> String {
+    let fill = " ".repeat(size.saturating_sub(width(s)));
+    if right {
+        format!("{}{}", fill, s)
+    } else {
+        format!("{}{}", s, fill)
+    }
+}
+
+#[cfg(test)]
+fn sample() -> (Vec<String>, Vec<::table::TableRow>) {
+    let table = ::table::read_csv("name,age\nJohn,30\n技術通報,\n\"Cena, John\",7.5\n".as_bytes())
+        .unwrap();
+    ((*table.columns).clone(), table.rows)
+}
+
+#[cfg(test)]
+fn written(format: Format) -> String {
+    let (columns, rows) = sample();
+    let mut out = vec![];
+    write_rows(&mut out, format, &columns, &rows).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_csv() {
+    assert_eq!(written(Format::Csv), "name,age\nJohn,30\n技術通報,\n\"Cena, John\",7.5\n");
+}
+
+#[test]
+fn test_json() {
+    let json: serde_json::Value = serde_json::from_str(&written(Format::Json)).unwrap();
+    assert_eq!(json,
+               serde_json::from_str::<serde_json::Value>(r#"[{"name": "John", "age": 30},
+                                                            {"name": "技術通報", "age": null},
+                                                            {"name": "Cena, John", "age": 7.5}]"#)
+                   .unwrap());
+}
+
+#[test]
+fn test_table() {
+    assert_eq!(written(Format::Table),
+               "name       | age\n\
+                -----------+----\n\
+                John       |  30\n\
+                技術通報   |\n\
+                Cena, John | 7.5\n");
+}
+
+#[test]
+fn test_ndjson() {
+    assert_eq!(written(Format::Ndjson),
+               "{\"name\":\"John\",\"age\":30}\n\
+                {\"name\":\"技術通報\",\"age\":null}\n\
+                {\"name\":\"Cena, John\",\"age\":7.5}\n");
+}
+
+#[test]
+fn test_own_columns() {
+    // json rows without columns given keep all of their fields, nested ones too
+    let rows: Vec<serde_json::Value> =
+        serde_json::from_str(r#"[{"a": 1, "b": {"c": [1, 2]}}, {"a": 2, "d": true}]"#).unwrap();
+    let mut writer = RowWriter::new(vec![], Format::Ndjson, vec![]);
+    for row in &rows {
+        writer.write(row).unwrap();
+    }
+    assert_eq!(String::from_utf8(writer.finish().unwrap()).unwrap(),
+               "{\"a\":1,\"b\":{\"c\":[1,2]}}\n{\"a\":2,\"d\":true}\n");
+    let mut writer = RowWriter::new(vec![], Format::Csv, vec![]);
+    for row in &rows {
+        writer.write(row).unwrap();
+    }
+    assert_eq!(String::from_utf8(writer.finish().unwrap()).unwrap(),
+               "a,b\n1,\"{\"\"c\"\":[1,2]}\"\n2,\n");
+    let empty: Vec<serde_json::Value> = vec![];
+    let mut out = vec![];
+    write_rows(&mut out, Format::Json, &[], &empty).unwrap();
+    assert_eq!(out, b"[]\n");
+}
@@ -0,0 +1,360 @@
+//! Full text search, `body=fts(english).fat&cat`
+//!
+//! The operators are those of postgresql, they differ in how the text of the
+//! search is read:
+//! - `fts`: `to_tsquery`, words joined with `&`, `|`, `!` and `<->`, `fat:*` is a prefix
+//! - `plfts`: `plainto_tsquery`, all the words
+//! - `phfts`: `phraseto_tsquery`, the words one after the other
+//! - `wfts`: `websearch_to_tsquery`, the words, `"quoted phrases"`, `or` and `-word`
+//!
+//! In memory a text is split into lowercase words which the search is matched
+//! against. It is an approximation of postgresql: the words are not stemmed,
+//! the stop words are kept and the language is ignored.
+
+use Equality;
+
+/// a search, the `tsquery` of postgresql
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum TsQuery {
+    /// a word, or a prefix: `fat:*`
+    Word { word: String, prefix: bool },
+    Not(Box<TsQuery>),
+    And(Vec<TsQuery>),
+    Or(Vec<TsQuery>),
+    /// the right side so many words after the left side, `fat <-> cat` is 1
+    Followed(Box<TsQuery>, usize, Box<TsQuery>),
+}
+
+impl TsQuery {
+    /// the search of a full text operator, None for another operator or a text without words
+    pub fn parse(equality: &Equality, text: &str) -> Option<TsQuery> {
+        match *equality {
+            Equality::FTS(_) => Parser { tokens: tokens(text), position: 0 }.parse(),
+            Equality::PLFTS(_) => all(words(text).into_iter().map(word).collect()),
+            Equality::PHFTS(_) => phrase(words(text)),
+            Equality::WFTS(_) => websearch(text),
+            _ => None,
+        }
+    }
+
+    /// whether the text has the words of the search
+    pub fn matches(&self, text: &str) -> bool {
+        self.matches_words(&words(text))
+    }
+
+    fn matches_words(&self, words: &[String]) -> bool {
+        match *self {
+            TsQuery::Not(ref query) => !query.matches_words(words),
+            TsQuery::And(ref queries) => queries.iter().all(|query| query.matches_words(words)),
+            TsQuery::Or(ref queries) => queries.iter().any(|query| query.matches_words(words)),
+            TsQuery::Word { .. } | TsQuery::Followed(..) => !self.positions(words).is_empty(),
+        }
+    }
+
+    /// where the search ends in the words, for the phrases. An AND is where any
+    /// of its parts is and a NOT is nowhere, close enough for `(a & b) <-> c`
+    fn positions(&self, words: &[String]) -> Vec<usize> {
+        match *self {
+            TsQuery::Word { ref word, prefix } => {
+                words.iter()
+                    .enumerate()
+                    .filter(|&(_, w)| w == word || prefix && w.starts_with(word.as_str()))
+                    .map(|(i, _)| i)
+                    .collect()
+            }
+            TsQuery::Followed(ref left, distance, ref right) => {
+                let left = left.positions(words);
+                right.positions(words)
+                    .into_iter()
+                    .filter(|&i| i >= distance && left.contains(&(i - distance)))
+                    .collect()
+            }
+            TsQuery::Not(_) => vec![],
+            TsQuery::And(_) if !self.matches_words(words) => vec![],
+            TsQuery::And(ref queries) | TsQuery::Or(ref queries) => {
+                let mut positions: Vec<usize> = queries.iter().flat_map(|query| query.positions(words)).collect();
+                positions.sort();
+                positions.dedup();
+                positions
+            }
+        }
+    }
+
+    /// the words of a phrase of single words one after the other, `fat <-> cat`
+    pub fn phrase_words(&self) -> Option<Vec<&str>> {
+        match *self {
+            TsQuery::Word { ref word, prefix: false } => Some(vec![word]),
+            TsQuery::Followed(ref left, 1, ref right) => {
+                let mut words = left.phrase_words()?;
+                words.extend(right.phrase_words()?);
+                Some(words)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// the lowercase words of a text, split at anything which is not a letter or a digit
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn word(word: String) -> TsQuery {
+    TsQuery::Word { word, prefix: false }
+}
+
+fn all(mut queries: Vec<TsQuery>) -> Option<TsQuery> {
+    match queries.len() {
+        0 => None,
+        1 => queries.pop(),
+        _ => Some(TsQuery::And(queries)),
+    }
+}
+
+fn any(mut queries: Vec<TsQuery>) -> Option<TsQuery> {
+    match queries.len() {
+        0 => None,
+        1 => queries.pop(),
+        _ => Some(TsQuery::Or(queries)),
+    }
+}
+
+/// the words one after the other
+fn phrase(words: Vec<String>) -> Option<TsQuery> {
+    let mut words = words.into_iter().map(word);
+    let first = words.next()?;
+    Some(words.fold(first, |phrase, word| TsQuery::Followed(Box::new(phrase), 1, Box::new(word))))
+}
+
+/// `"quoted phrases"`, `-word` and `or` between words, the other words are AND-ed
+fn websearch(text: &str) -> Option<TsQuery> {
+    let mut groups: Vec<Vec<TsQuery>> = vec![vec![]];
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let negated = chars.peek() == Some(&'-');
+        if negated {
+            chars.next();
+        }
+        let query = match chars.peek() {
+            None => break,
+            Some(&'"') => {
+                chars.next();
+                let quoted: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                phrase(words(&quoted))
+            }
+            Some(_) => {
+                let mut chunk = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '"' {
+                        break;
+                    }
+                    chunk.push(c);
+                    chars.next();
+                }
+                if !negated && chunk.eq_ignore_ascii_case("or") {
+                    if groups.last().is_some_and(|group| !group.is_empty()) {
+                        groups.push(vec![]);
+                    }
+                    continue;
+                }
+                phrase(words(&chunk))
+            }
+        };
+        if let Some(query) = query {
+            let query = if negated { TsQuery::Not(Box::new(query)) } else { query };
+            if let Some(group) = groups.last_mut() {
+                group.push(query);
+            }
+        }
+    }
+    any(groups.into_iter().filter_map(all).collect())
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+enum Token {
+    Word(String, bool),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Followed(usize),
+}
+
+/// the tokens of a `to_tsquery` text, the weights of `fat:AB` are dropped
+fn tokens(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => tokens.push(Token::And),
+            '|' => tokens.push(Token::Or),
+            '!' => tokens.push(Token::Not),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '<' => {
+                let inner: String = chars.by_ref().take_while(|&c| c != '>').collect();
+                let distance = if inner == "-" { Some(1) } else { inner.trim().parse().ok() };
+                tokens.extend(distance.map(Token::Followed));
+            }
+            c if c.is_alphanumeric() => {
+                let mut text = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() {
+                        break;
+                    }
+                    text.push(c);
+                    chars.next();
+                }
+                let mut prefix = false;
+                if chars.peek() == Some(&':') {
+                    chars.next();
+                    while let Some(&c) = chars.peek() {
+                        if c != '*' && !c.is_ascii_alphabetic() {
+                            break;
+                        }
+                        prefix |= c == '*';
+                        chars.next();
+                    }
+                }
+                tokens.push(Token::Word(text.to_lowercase(), prefix));
+            }
+            _ => (),
+        }
+    }
+    tokens
+}
+
+/// a `to_tsquery` text, `!` before `<->` before `&` before `|`. It is lenient,
+/// two words without an operator are AND-ed and a stray operator is skipped
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Option<TsQuery> {
+        let mut queries = vec![];
+        while self.position < self.tokens.len() {
+            match self.or() {
+                Some(query) => queries.push(query),
+                None => self.position += 1,
+            }
+        }
+        all(queries)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn or(&mut self) -> Option<TsQuery> {
+        let mut queries = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            queries.extend(self.and());
+        }
+        any(queries)
+    }
+
+    fn and(&mut self) -> Option<TsQuery> {
+        let mut queries = vec![self.followed()?];
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            queries.extend(self.followed());
+        }
+        all(queries)
+    }
+
+    fn followed(&mut self) -> Option<TsQuery> {
+        let mut query = self.unary()?;
+        while let Some(&Token::Followed(distance)) = self.peek() {
+            self.position += 1;
+            if let Some(right) = self.unary() {
+                query = TsQuery::Followed(Box::new(query), distance, Box::new(right));
+            }
+        }
+        Some(query)
+    }
+
+    fn unary(&mut self) -> Option<TsQuery> {
+        let token = self.peek()?.clone();
+        match token {
+            Token::Not => {
+                self.position += 1;
+                self.unary().map(|query| TsQuery::Not(Box::new(query)))
+            }
+            Token::Open => {
+                self.position += 1;
+                let query = self.or();
+                if self.peek() == Some(&Token::Close) {
+                    self.position += 1;
+                }
+                query
+            }
+            Token::Word(word, prefix) => {
+                self.position += 1;
+                Some(TsQuery::Word { word, prefix })
+            }
+            Token::And | Token::Or | Token::Close | Token::Followed(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+fn search(equality: Equality, text: &str) -> TsQuery {
+    TsQuery::parse(&equality, text).unwrap()
+}
+
+#[test]
+fn test_to_tsquery() {
+    let query = search(Equality::FTS(None), "fat & (cat | !dog)");
+    assert_eq!(query, TsQuery::And(vec![word("fat".to_string()),
+                                        TsQuery::Or(vec![word("cat".to_string()),
+                                                         TsQuery::Not(Box::new(word("dog".to_string())))])]));
+    assert!(query.matches("The fat cat"));
+    assert!(query.matches("a FAT bird"));
+    assert!(!query.matches("the fat dog"));
+    assert!(!query.matches("fatter cats"));
+    let query = search(Equality::FTS(None), "sup:* <-> cat");
+    assert!(query.matches("the super cat"));
+    assert!(!query.matches("the super fat cat"));
+    assert!(search(Equality::FTS(None), "super <2> cat").matches("the super fat cat"));
+    // lenient, the words are AND-ed
+    assert!(search(Equality::FTS(None), "cat dog &").matches("dog, cat"));
+    assert_eq!(TsQuery::parse(&Equality::FTS(None), "& !"), None);
+    assert_eq!(TsQuery::parse(&Equality::EQ, "cat"), None);
+}
+
+#[test]
+fn test_plain_and_phrase() {
+    let query = search(Equality::PLFTS(Some("english".to_string())), "Fat  cats!");
+    assert!(query.matches("cats are fat"));
+    assert!(!query.matches("fat cat"));
+    let query = search(Equality::PHFTS(None), "the fat cat");
+    assert_eq!(query.phrase_words(), Some(vec!["the", "fat", "cat"]));
+    assert!(query.matches("Look at the fat cat."));
+    assert!(!query.matches("the cat is fat"));
+    assert_eq!(TsQuery::parse(&Equality::PLFTS(None), " ... "), None);
+}
+
+#[test]
+fn test_websearch() {
+    let query = search(Equality::WFTS(None), r#""fat cat" or dog -bird"#);
+    assert!(query.matches("a fat cat and a bird"));
+    assert!(query.matches("a dog"));
+    assert!(!query.matches("a dog and a bird"));
+    assert!(!query.matches("a cat, fat"));
+    let query = search(Equality::WFTS(None), "or cat OR");
+    assert_eq!(query, word("cat".to_string()));
+}
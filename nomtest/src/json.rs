@@ -0,0 +1,48 @@
+//! Operands as json values, shared by the json based backends
+
+use serde_json::{Number, Value};
+
+use relative::SystemClock;
+use Operand;
+
+/// the json value of a literal operand, None for columns, functions and lists, a
+/// relative time which `relative::resolve` has not resolved is resolved against
+/// the clock of the system
+pub fn from_operand(operand: &Operand) -> Option<Value> {
+    match *operand {
+        Operand::Number(n) => Some(number(n)),
+        Operand::Boolean(b) => Some(Value::Bool(b)),
+        Operand::Value(ref v) => Some(Value::String(v.to_string())),
+        Operand::Relative(ref time) => Some(Value::String(time.resolve(&SystemClock))),
+        Operand::Column(_) | Operand::Function(_) | Operand::List(_) => None,
+    }
+}
+
+/// the right side of `is` and `is_not`: null, true or false
+pub fn is_operand(operand: &Operand) -> Option<Value> {
+    match *operand {
+        Operand::Value(ref v) if v.eq_ignore_ascii_case("null") => Some(Value::Null),
+        Operand::Boolean(b) => Some(Value::Bool(b)),
+        _ => None,
+    }
+}
+
+/// whole numbers are written as integers, they are what the documents usually hold
+pub fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        Number::from_f64(n).map(Value::Number).unwrap_or(Value::Null)
+    }
+}
+
+#[test]
+fn test_from_operand() {
+    assert_eq!(from_operand(&Operand::Number(20f64)), Some(Value::from(20)));
+    assert_eq!(from_operand(&Operand::Number(-0.3)), Some(Value::from(-0.3)));
+    assert_eq!(from_operand(&Operand::Value("技術通報".to_string())),
+               Some(Value::from("技術通報")));
+    assert_eq!(from_operand(&Operand::Column("age".to_string())), None);
+    assert_eq!(is_operand(&Operand::Value("NULL".to_string())), Some(Value::Null));
+    assert_eq!(is_operand(&Operand::Value("nothing".to_string())), None);
+}
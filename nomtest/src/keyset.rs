@@ -0,0 +1,369 @@
+//! Keyset pagination, the rows after or before a cursor
+//!
+//! A cursor holds the values of the `order_by` operands in a row, signed so a
+//! client can not forge one or reuse it with another order. `resolve` checks
+//! the cursor of a query and turns it into a filter of the rows which sort
+//! after it, so a page is found without skipping over the ones before:
+//!
+//! ```text
+//! order_by=name,id&after=<"John", 7>&limit=20
+//! name > 'John' OR name IS NULL OR (name = 'John' AND id > 7)
+//! ```
+//! The comparison follows the direction and the nulls placement of each order,
+//! the resolved query spells them out so sql puts the nulls where the filter
+//! expects them. MongoDB can not place the nulls and is only exact for columns
+//! without any. The last order should be unique, like an id: the rows which
+//! tie with the cursor on every order are neither after nor before it.
+
+use std::fmt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_json::{self, Map};
+use sha2::Sha256;
+
+use eval;
+use expr::Expr;
+use row::{Row, Value};
+use {Condition, Direction, Equality, Limit, NullsWhere, Operand, Order, Query, Range, Seek};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum CursorError {
+    /// not a cursor made by a `Signer`
+    Malformed,
+    /// the cursor was signed with another secret, or changed since
+    BadSignature,
+    /// the cursor is of a query with another order, it holds the order it was made for
+    OrderMismatch(String),
+    /// the query has no order, so no row comes after another
+    MissingOrder,
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CursorError::Malformed => write!(f, "malformed cursor"),
+            CursorError::BadSignature => write!(f, "the signature of the cursor does not match"),
+            CursorError::OrderMismatch(ref order) => write!(f, "the cursor is for order_by={}", order),
+            CursorError::MissingOrder => write!(f, "a cursor needs an order_by"),
+        }
+    }
+}
+
+impl ::std::error::Error for CursorError {}
+
+/// makes and checks cursors with an HMAC-SHA256 of a secret
+#[derive(Clone)]
+pub struct Signer {
+    secret: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(secret: &[u8]) -> Self {
+        Signer { secret: secret.to_vec() }
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::new_from_slice(&self.secret).expect("a key of any length")
+    }
+
+    /// the cursor of a row in a query ordered by `order_by`, for the next or the previous page
+    pub fn cursor<R: Row>(&self, order_by: &[Order], row: &R) -> String {
+        let values: Vec<Value> = order_by.iter().map(|order| eval::operand_value(&order.operand, row)).collect();
+        self.sign(order_by, &values)
+    }
+
+    /// the cursor of the values of the `order_by` operands, in order:
+    /// `base64url(payload).base64url(hmac)`
+    pub fn sign(&self, order_by: &[Order], values: &[Value]) -> String {
+        let mut payload = Map::new();
+        payload.insert("order_by".to_string(), serde_json::Value::from(order_text(order_by)));
+        payload.insert("values".to_string(), values.iter().map(Value::to_json).collect());
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::Value::Object(payload).to_string());
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    /// the values of a cursor made for a query ordered by `order_by`
+    pub fn verify(&self, cursor: &str, order_by: &[Order]) -> Result<Vec<Value>, CursorError> {
+        let (payload, signature) = match cursor.find('.') {
+            Some(i) => (&cursor[..i], &cursor[i + 1..]),
+            None => return Err(CursorError::Malformed),
+        };
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| CursorError::Malformed)?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| CursorError::BadSignature)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| CursorError::Malformed)?;
+        let payload: serde_json::Value = serde_json::from_slice(&payload).map_err(|_| CursorError::Malformed)?;
+        let order = payload.get("order_by").and_then(serde_json::Value::as_str).ok_or(CursorError::Malformed)?;
+        if order != order_text(order_by) {
+            return Err(CursorError::OrderMismatch(order.to_string()));
+        }
+        match payload.get("values").and_then(serde_json::Value::as_array) {
+            Some(values) if values.len() == order_by.len() => Ok(values.iter().map(Value::from).collect()),
+            _ => Err(CursorError::Malformed),
+        }
+    }
+}
+
+/// the order with its direction and nulls placement, `id.asc.nulls_last` and
+/// `id` are the same order
+fn order_text(order_by: &[Order]) -> String {
+    order_by.iter().map(|order| explicit(order).to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// the order with the direction and nulls placement it has by default,
+/// nulls are last in ascending and first in descending order
+pub fn explicit(order: &Order) -> Order {
+    let direction = order.direction.clone().unwrap_or(Direction::ASC);
+    let nulls_where = order.nulls_where.clone().unwrap_or(match direction {
+        Direction::ASC => NullsWhere::LAST,
+        Direction::DESC => NullsWhere::FIRST,
+    });
+    Order {
+        operand: order.operand.clone(),
+        direction: Some(direction),
+        nulls_where: Some(nulls_where),
+    }
+}
+
+/// the opposite order, where the last row comes first
+fn reverse(order: &Order) -> Order {
+    let order = explicit(order);
+    Order {
+        direction: Some(match order.direction {
+            Some(Direction::DESC) => Direction::ASC,
+            _ => Direction::DESC,
+        }),
+        nulls_where: Some(match order.nulls_where {
+            Some(NullsWhere::FIRST) => NullsWhere::LAST,
+            _ => NullsWhere::FIRST,
+        }),
+        ..order
+    }
+}
+
+/// the query of a page, and whether its rows come in the reverse order
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Resolved {
+    pub query: Query,
+    /// the rows before a cursor are found in the reverse order, nearest first,
+    /// and have to be reversed back
+    pub reversed: bool,
+}
+
+impl Resolved {
+    /// the rows of the resolved query in the order of the original one
+    pub fn restore_order<T>(&self, rows: &mut [T]) {
+        if self.reversed {
+            rows.reverse();
+        }
+    }
+}
+
+/// the query with its cursor checked and turned into a filter, AND-ed to the
+/// others, and its keyset into a limit. A query without a cursor is unchanged
+pub fn resolve(query: &Query, signer: &Signer) -> Result<Resolved, CursorError> {
+    let keyset = match query.range {
+        Some(Range::Keyset(ref keyset)) => keyset,
+        _ => return Ok(Resolved { query: query.clone(), reversed: false }),
+    };
+    if query.order_by.is_empty() {
+        return Err(CursorError::MissingOrder);
+    }
+    let values = signer.verify(&keyset.cursor, &query.order_by)?;
+    let mut resolved = query.clone();
+    resolved.order_by = match keyset.seek {
+        Seek::After => query.order_by.iter().map(explicit).collect(),
+        Seek::Before => query.order_by.iter().map(reverse).collect(),
+    };
+    let seek = seek_expr(&resolved.order_by, &values);
+    resolved.filters.extend(seek.to_filters());
+    resolved.range = keyset.limit.map(|limit| Range::Limit(Limit { limit, offset: None }));
+    Ok(Resolved {
+        query: resolved,
+        reversed: keyset.seek == Seek::Before,
+    })
+}
+
+/// the rows which sort after the values in the order, one per order is the
+/// first to differ: `k1 after v1 OR (k1 = v1 AND k2 after v2) OR ...`
+pub fn seek_expr(order_by: &[Order], values: &[Value]) -> Expr {
+    let mut terms = vec![];
+    let mut equal = vec![];
+    for (order, value) in order_by.iter().zip(values) {
+        if let Some(after) = after(order, value) {
+            let mut term = equal.clone();
+            term.push(after);
+            terms.push(if term.len() == 1 { term.remove(0) } else { Expr::And(term) });
+        }
+        equal.push(if value.is_null() {
+            condition(order, Equality::IS, Operand::Value("null".to_string()))
+        } else {
+            condition(order, Equality::EQ, operand(value))
+        });
+    }
+    match terms.len() {
+        // nothing sorts after the values, a filter which no row matches
+        0 => Expr::And(vec![condition(&order_by[0], Equality::IS, Operand::Value("null".to_string())),
+                            condition(&order_by[0], Equality::IS_NOT, Operand::Value("null".to_string()))]),
+        1 => terms.remove(0),
+        _ => Expr::Or(terms.into_iter().flat_map(|term| match term {
+            Expr::Or(exprs) => exprs,
+            term => vec![term],
+        }).collect()),
+    }
+}
+
+/// the rows whose operand sorts after the value in an explicit order, None when none does
+fn after(order: &Order, value: &Value) -> Option<Expr> {
+    let nulls_first = order.nulls_where == Some(NullsWhere::FIRST);
+    if value.is_null() {
+        return if nulls_first {
+            Some(condition(order, Equality::IS_NOT, Operand::Value("null".to_string())))
+        } else {
+            None
+        };
+    }
+    let equality = match order.direction {
+        Some(Direction::DESC) => Equality::LT,
+        _ => Equality::GT,
+    };
+    let beyond = condition(order, equality, operand(value));
+    if nulls_first {
+        Some(beyond)
+    } else {
+        Some(Expr::Or(vec![beyond, condition(order, Equality::IS, Operand::Value("null".to_string()))]))
+    }
+}
+
+fn condition(order: &Order, equality: Equality, right: Operand) -> Expr {
+    Expr::Condition(Condition {
+        left: order.operand.clone(),
+        equality,
+        right,
+    })
+}
+
+fn operand(value: &Value) -> Operand {
+    match *value {
+        Value::Null => Operand::Value("null".to_string()),
+        Value::Bool(b) => Operand::Boolean(b),
+        Value::Number(n) => Operand::Number(n),
+        Value::Text(ref s) => Operand::Value(s.to_string()),
+    }
+}
+
+#[cfg(test)]
+fn people() -> Vec<::serde_json::Value> {
+    ::serde_json::from_str(r#"[
+        {"id": 1, "name": "Ann", "age": 30},
+        {"id": 2, "name": "Bob", "age": null},
+        {"id": 3, "name": "Cid", "age": 25},
+        {"id": 4, "name": "Dan", "age": 30},
+        {"id": 5, "name": "Eve", "age": null},
+        {"id": 6, "name": "Fay", "age": 41}
+    ]"#).unwrap()
+}
+
+/// every page of a query, by following the cursor of the edge row of each
+#[cfg(test)]
+fn pages(order_by: &str, seek: &str, size: usize, start: Option<&[Value]>) -> Vec<Vec<i64>> {
+    use execute;
+
+    let signer = Signer::new(b"secret");
+    let order = ::parse_query(&format!("order_by={}", order_by)).unwrap().order_by;
+    let mut cursor = start.map(|values| signer.sign(&order, values));
+    let mut pages = vec![];
+    loop {
+        let mut input = format!("order_by={}&limit={}", order_by, size);
+        if let Some(ref cursor) = cursor {
+            input.push_str(&format!("&{}={}", seek, cursor));
+        }
+        let resolved = resolve(&::parse_query(&input).unwrap(), &signer).unwrap();
+        let mut rows = execute::execute(&resolved.query, people()).unwrap().rows;
+        resolved.restore_order(&mut rows);
+        let edge = match if seek == "after" { rows.last() } else { rows.first() } {
+            Some(edge) => edge,
+            None => return pages,
+        };
+        cursor = Some(signer.cursor(&order, edge));
+        pages.push(rows.iter()
+            .map(|row| match row.get("id") {
+                Some(Value::Number(id)) => id as i64,
+                _ => 0,
+            })
+            .collect());
+    }
+}
+
+#[test]
+fn test_signer() {
+    let signer = Signer::new(b"secret");
+    let order_by = ::parse_query("order_by=name.desc,id").unwrap().order_by;
+    let values = vec![Value::Text("Bob".to_string()), Value::Number(2.0)];
+    let cursor = signer.sign(&order_by, &values);
+    assert_eq!(signer.verify(&cursor, &order_by), Ok(values.clone()));
+    // `id` and `id.asc.nulls_last` are the same order
+    let explicit_order = ::parse_query("order_by=name.desc.nulls_first,id.asc.nulls_last").unwrap().order_by;
+    assert_eq!(signer.verify(&cursor, &explicit_order), Ok(values));
+    assert_eq!(signer.verify(&cursor, &order_by[..1]),
+               Err(CursorError::OrderMismatch("name.desc.nulls_first,id.asc.nulls_last".to_string())));
+    assert_eq!(Signer::new(b"other").verify(&cursor, &order_by), Err(CursorError::BadSignature));
+    let tampered = format!("A{}", &cursor[1..]);
+    assert_eq!(signer.verify(&tampered, &order_by), Err(CursorError::BadSignature));
+    assert_eq!(signer.verify("abc", &order_by), Err(CursorError::Malformed));
+    // the cursor goes through the parser as it is
+    let query = ::parse_query(&format!("order_by=name.desc,id&after={}", cursor)).unwrap();
+    assert_eq!(query.range.map(|range| match range {
+        Range::Keyset(keyset) => keyset.cursor,
+        _ => String::new(),
+    }), Some(cursor));
+}
+
+#[test]
+fn test_seek_expr() {
+    let order_by: Vec<Order> = ::parse_query("order_by=age,id.desc").unwrap().order_by.iter().map(explicit).collect();
+    let expr = seek_expr(&order_by, &[Value::Number(30.0), Value::Number(4.0)]);
+    assert_eq!(expr.to_filter().unwrap(), ::parse_filter("age=gt.30|age=is.null|age=eq.30&id=lt.4"));
+    // the nulls are last, nothing comes after them but the next key
+    let expr = seek_expr(&order_by, &[Value::Null, Value::Number(4.0)]);
+    assert_eq!(expr.to_filter().unwrap(), ::parse_filter("age=is.null&id=lt.4"));
+    let reversed: Vec<Order> = order_by.iter().map(reverse).collect();
+    let expr = seek_expr(&reversed, &[Value::Null, Value::Number(4.0)]);
+    assert_eq!(expr.to_filter().unwrap(), ::parse_filter("age=is_not.null|age=is.null&(id=gt.4|id=is.null)"));
+}
+
+#[test]
+fn test_resolve() {
+    let signer = Signer::new(b"secret");
+    let query = ::parse_query("order_by=id&limit=2").unwrap();
+    assert_eq!(resolve(&query, &signer), Ok(Resolved { query: query.clone(), reversed: false }));
+    let cursor = signer.sign(&query.order_by, &[Value::Number(2.0)]);
+    let resolved = resolve(&::parse_query(&format!("a=eq.1&order_by=id&before={}&limit=2", cursor)).unwrap(),
+                           &signer).unwrap();
+    let mut expected = ::parse_query("a=eq.1&order_by=id.desc.nulls_first&limit=2").unwrap();
+    expected.filters.push(::parse_filter("id=lt.2"));
+    assert_eq!(resolved.query, expected);
+    assert!(resolved.reversed);
+    assert_eq!(resolve(&::parse_query(&format!("after={}", cursor)).unwrap(), &signer).map(|r| r.reversed),
+               Err(CursorError::MissingOrder));
+}
+
+#[test]
+fn test_pages() {
+    // the nulls are last in ascending order, the ties on age are broken by id
+    assert_eq!(pages("age,id", "after", 2, None), vec![vec![3, 1], vec![4, 6], vec![2, 5]]);
+    assert_eq!(pages("age.desc,id.desc", "after", 4, None), vec![vec![5, 2, 6, 4], vec![1, 3]]);
+    assert_eq!(pages("age.nulls_first,id", "after", 3, None), vec![vec![2, 5, 3], vec![1, 4, 6]]);
+    // the pages before the last row, walked back to the first
+    let last = [Value::Null, Value::Number(5.0)];
+    assert_eq!(pages("age,id", "before", 2, Some(&last)), vec![vec![6, 2], vec![1, 4], vec![3]]);
+}
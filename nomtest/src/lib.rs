@@ -0,0 +1,1733 @@
+#[macro_use]
+extern crate nom;
+extern crate csv;
+extern crate rusqlite;
+extern crate serde_json;
+extern crate tiny_http;
+extern crate toml;
+extern crate hmac;
+extern crate sha2;
+extern crate base64;
+extern crate regex;
+extern crate regex_syntax;
+extern crate unicode_normalization;
+extern crate unicode_width;
+extern crate caseless;
+extern crate icu_collator;
+extern crate icu_locid;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "uuid")]
+extern crate uuid;
+
+use nom::{IResult,digit};
+
+use std::fmt;
+use std::str;
+use std::str::FromStr;
+
+use limits::{LimitError, Limits};
+use regexp::PatternError;
+use relative::RelativeTime;
+use expr::Expr;
+
+pub mod builder;
+pub mod collation;
+pub mod convert;
+pub mod elastic;
+pub mod eval;
+pub mod execute;
+pub mod expr;
+pub mod field;
+pub mod format;
+pub mod fts;
+pub mod json;
+pub mod keyset;
+pub mod like;
+pub mod limits;
+pub mod merge;
+pub mod mongo;
+pub mod policy;
+pub mod regexp;
+pub mod relative;
+pub mod ndjson;
+pub mod normalize;
+pub mod row;
+pub mod schema;
+pub mod simplify;
+pub mod serve;
+pub mod sql;
+pub mod table;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Equation {
+    pub left: Operand,
+    pub right: Operand,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Function {
+    pub function: String,
+    pub params: Vec<Operand>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Operand {
+    Column(String),
+    Function(Function),
+    Number(f64),
+    Boolean(bool),
+    Value(String),
+    /// a date or a time relative to the present, `now-7d`
+    Relative(RelativeTime),
+    /// the items of `in` and `not_in`, `id=in.1,2,3`
+    List(Vec<Operand>),
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Connector {
+    AND,
+    OR,
+}
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Direction {
+    ASC,
+    DESC,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum NullsWhere {
+    FIRST,
+    LAST,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Order {
+    pub operand: Operand,
+    pub direction: Option<Direction>,
+    pub nulls_where: Option<NullsWhere>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+pub enum Equality {
+    EQ, // = ,  eq
+    NEQ, // != , neq
+    LT, // <,  lt
+    LTE, // <=, lte
+    GT, // >, gt
+    GTE, // >=, gte
+    IN, // IN, in
+    NOT_IN, // NOT IN, not_in
+    IS, // IS, is
+    IS_NOT, // IS NOT, is_not
+    LIKE, // LIKE, like
+    ILIKE, // ILIKE case insensitive like, postgresql specific
+    ST, // Starts with, which will become ILIKE 'value%'
+    FTS(Option<String>), // @@ to_tsquery, fts, with an optional language: fts(english)
+    PLFTS(Option<String>), // @@ plainto_tsquery, plfts
+    PHFTS(Option<String>), // @@ phraseto_tsquery, phfts
+    WFTS(Option<String>), // @@ websearch_to_tsquery, wfts
+    MATCH, // ~, match, a regular expression
+    IMATCH, // ~*, imatch, case insensitive
+}
+
+impl Equality {
+    /// the language of a full text search, `fts(english)`
+    pub fn language(&self) -> Option<&str> {
+        match *self {
+            Equality::FTS(ref language) | Equality::PLFTS(ref language) |
+            Equality::PHFTS(ref language) | Equality::WFTS(ref language) => language.as_ref().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    pub fn is_full_text(&self) -> bool {
+        matches!(*self, Equality::FTS(_) | Equality::PLFTS(_) | Equality::PHFTS(_) | Equality::WFTS(_))
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Condition {
+    pub left: Operand,
+    pub equality: Equality,
+    pub right: Operand,
+}
+
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Filter {
+    pub condition: Condition,
+    /// the filter's condition will use this connector to connect to the rest of the filters (sub_filters)
+    pub connector: Option<Connector>,
+    pub sub_filters: Vec<Filter>,
+}
+
+
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Query {
+    pub from: Vec<Operand>,
+    pub join: Vec<Join>,
+    pub filters: Vec<Filter>,
+    pub group_by: Vec<Operand>,
+    pub having: Vec<Filter>,
+    pub order_by: Vec<Order>,
+    pub range: Option<Range>,
+    pub equations: Vec<Equation>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Page {
+    pub page: i64,
+    pub page_size: i64,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Limit {
+    pub limit: i64,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Range {
+    Page(Page),
+    Limit(Limit),
+    Keyset(Keyset),
+}
+
+/// which side of the cursor the rows are on
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Seek {
+    After,
+    Before,
+}
+
+/// the rows after or before a cursor, `after=<cursor>&limit=20`
+///
+/// The cursor is signed, `keyset::resolve` checks it and turns it into a filter.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Keyset {
+    pub seek: Seek,
+    pub cursor: String,
+    pub limit: Option<i64>,
+}
+
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum JoinType {
+    CROSS,
+    INNER,
+    OUTER,
+    NATURAL,
+}
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Modifier {
+    LEFT,
+    RIGHT,
+    FULL,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Join {
+    pub modifier: Option<Modifier>,
+    pub join_type: Option<JoinType>,
+    pub table: Operand,
+    pub column1: Vec<String>,
+    pub column2: Vec<String>,
+}
+
+
+
+// a value runs until the next connector or closing parenthesis
+named!(value<&'a str>, 
+  map_res!(complete!(recognize!(many1!(is_not_s!("&|)"))))
+    ,str::from_utf8
+  )
+);
+
+// nested fields are addressed with a dotted or json path: `address.city`, `$.tags[0]`
+named!(column<&'a str>, 
+  map_res!(recognize!(many1!(one_of!("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_.$[]")))
+    ,str::from_utf8
+  )
+);
+
+/*
+named!(column <&str>, map_res!(
+        complete!(alphanumeric),
+        str::from_utf8
+    )
+);
+*/
+
+named!(boolean <bool>,
+    alt!(tag!("true") => {|_| true} |
+         tag!("false") => {|_| false}
+        )
+);
+
+named!(number<i64>,
+    map_res!(
+      map_res!(
+        ws!(digit),
+        str::from_utf8
+      ),
+      FromStr::from_str
+    )
+);
+
+named!(operand <Operand>,
+   //NOTE: assume the right value to be value, and the left to be always column
+   map!(value, value_operand)
+);
+
+/// the whole value decides the type, so `1,2` and `12abc` are not taken as numbers,
+/// nor a numeral too long for a f64 which would be infinite
+fn value_operand(v: &str) -> Operand {
+    if let IResult::Done(rest, f) = float(v.as_bytes()) {
+        if rest.is_empty() && f.is_finite() {
+            return Operand::Number(f);
+        }
+    }
+    if let IResult::Done(rest, b) = boolean(v.as_bytes()) {
+        if rest.is_empty() {
+            return Operand::Boolean(b);
+        }
+    }
+    // a relative time is marked, so a text such as `now` stays a text
+    if let Some(time) = v.strip_prefix('@').and_then(|time| time.parse().ok()) {
+        return Operand::Relative(time);
+    }
+    Operand::Value(v.to_string())
+}
+
+/// the right operand of `in` and `not_in` is a list, each item typed on its own.
+/// A range comparison is to a time rather than to a text, so there a relative
+/// time needs no `@`: `created_at=gt.now-7d`
+fn compared_operand(equality: &Equality, operand: Operand) -> Operand {
+    match (equality, operand) {
+        (&Equality::IN, Operand::Value(v)) | (&Equality::NOT_IN, Operand::Value(v)) => {
+            Operand::List(v.split(',').map(value_operand).collect())
+        }
+        (&Equality::IN, operand) | (&Equality::NOT_IN, operand) => Operand::List(vec![operand]),
+        (equality, Operand::Value(v)) if is_range_comparison(equality) => match v.parse() {
+            Ok(time) => Operand::Relative(time),
+            Err(()) => Operand::Value(v),
+        },
+        (_, operand) => operand,
+    }
+}
+
+fn is_range_comparison(equality: &Equality) -> bool {
+    matches!(*equality, Equality::LT | Equality::LTE | Equality::GT | Equality::GTE)
+}
+
+impl Operand {
+    /// the items of the right operand of `in` and `not_in`, `id=in.1,2,3`,
+    /// any other operand is a list of one
+    pub fn list_items(&self) -> Vec<Operand> {
+        match *self {
+            Operand::List(ref items) => items.clone(),
+            _ => vec![self.clone()],
+        }
+    }
+}
+
+impl Page {
+    /// the number of rows skipped before the page, pages start at 1.
+    /// None when it does not fit an i64, which a parsed query never has
+    pub fn offset(&self) -> Option<i64> {
+        (self.page - 1).max(0).checked_mul(self.page_size)
+    }
+}
+
+impl Range {
+    /// the number of rows skipped and the number of rows kept, pages start at 1.
+    /// None for a keyset, its cursor is a filter which has to be resolved first
+    pub fn bounds(&self) -> Option<(usize, usize)> {
+        let (offset, limit) = match *self {
+            Range::Page(ref page) => (page.offset().unwrap_or(i64::MAX), page.page_size),
+            Range::Limit(ref limit) => (limit.offset.unwrap_or(0), limit.limit),
+            Range::Keyset(_) => return None,
+        };
+        Some((offset.max(0) as usize, limit.max(0) as usize))
+    }
+
+    /// the number of rows kept at most, whatever comes before them
+    pub fn size(&self) -> usize {
+        let size = match *self {
+            Range::Page(ref page) => page.page_size,
+            Range::Limit(ref limit) => limit.limit,
+            Range::Keyset(ref keyset) => keyset.limit.unwrap_or(i64::MAX),
+        };
+        size.max(0) as usize
+    }
+}
+
+impl Function {
+    /// the name of the column a function call results into, `count(*)`, `sum(price)`
+    pub fn column_name(&self) -> String {
+        let params: Vec<String> = self.params
+            .iter()
+            .map(|param| match *param {
+                Operand::Column(ref c) | Operand::Value(ref c) => c.to_string(),
+                Operand::Number(n) => n.to_string(),
+                Operand::Boolean(b) => b.to_string(),
+                Operand::Relative(ref time) => format!("@{}", time),
+                Operand::Function(ref f) => f.column_name(),
+                Operand::List(_) => param.to_string(),
+            })
+            .collect();
+        if params.is_empty() {
+            format!("{}(*)", self.function)
+        } else {
+            format!("{}({})", self.function, params.join(","))
+        }
+    }
+}
+
+/// the operands, conditions and queries are written back in the syntax they are parsed from
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Column(ref c) | Operand::Value(ref c) => write!(f, "{}", c),
+            Operand::Function(ref function) => write!(f, "{}", function.column_name()),
+            Operand::Number(n) => write!(f, "{}", n),
+            Operand::Boolean(b) => write!(f, "{}", b),
+            Operand::Relative(ref time) => write!(f, "@{}", time),
+            Operand::List(ref items) => {
+                let items: Vec<String> = items.iter().map(Operand::to_string).collect();
+                write!(f, "{}", items.join(","))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Equality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tag = match *self {
+            Equality::EQ => "eq",
+            Equality::NEQ => "neq",
+            Equality::LT => "lt",
+            Equality::LTE => "lte",
+            Equality::GT => "gt",
+            Equality::GTE => "gte",
+            Equality::IN => "in",
+            Equality::NOT_IN => "not_in",
+            Equality::IS => "is",
+            Equality::IS_NOT => "is_not",
+            Equality::LIKE => "like",
+            Equality::ILIKE => "ilike",
+            Equality::ST => "st",
+            Equality::FTS(_) => "fts",
+            Equality::PLFTS(_) => "plfts",
+            Equality::PHFTS(_) => "phfts",
+            Equality::WFTS(_) => "wfts",
+            Equality::MATCH => "match",
+            Equality::IMATCH => "imatch",
+        };
+        write!(f, "{}", tag)?;
+        match self.language() {
+            Some(language) => write!(f, "({})", language),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the relative time of a range comparison is written without its `@`, as it is parsed
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.right {
+            Operand::Relative(ref time) if is_range_comparison(&self.equality) => {
+                write!(f, "{}={}.{}", self.left, self.equality, time)
+            }
+            _ => write!(f, "{}={}.{}", self.left, self.equality, self.right),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", expr::Expr::from(self))
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.operand)?;
+        match self.direction {
+            Some(Direction::ASC) => write!(f, ".asc")?,
+            Some(Direction::DESC) => write!(f, ".desc")?,
+            None => (),
+        }
+        match self.nulls_where {
+            Some(NullsWhere::FIRST) => write!(f, ".nulls_first"),
+            Some(NullsWhere::LAST) => write!(f, ".nulls_last"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the joins have no syntax and are left out
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn list<T: fmt::Display>(items: &[T]) -> String {
+            let items: Vec<String> = items.iter().map(T::to_string).collect();
+            items.join(",")
+        }
+        // the filters are AND-ed together, an OR among them is put in parentheses
+        let mut params: Vec<String> = expr::Expr::from_filters(&self.filters).iter().map(expr::Expr::to_string).collect();
+        if !self.from.is_empty() {
+            params.push(format!("from={}", list(&self.from)));
+        }
+        if !self.group_by.is_empty() {
+            params.push(format!("group_by={}", list(&self.group_by)));
+        }
+        params.extend(self.having.iter().map(|having| format!("having={}", having)));
+        if !self.order_by.is_empty() {
+            params.push(format!("order_by={}", list(&self.order_by)));
+        }
+        match self.range {
+            Some(Range::Limit(ref limit)) => {
+                params.push(format!("limit={}", limit.limit));
+                if let Some(offset) = limit.offset {
+                    params.push(format!("offset={}", offset));
+                }
+            }
+            Some(Range::Page(ref page)) => {
+                params.push(format!("page={}&page_size={}", page.page, page.page_size));
+            }
+            Some(Range::Keyset(ref keyset)) => {
+                let seek = match keyset.seek {
+                    Seek::After => "after",
+                    Seek::Before => "before",
+                };
+                params.push(format!("{}={}", seek, keyset.cursor));
+                if let Some(limit) = keyset.limit {
+                    params.push(format!("limit={}", limit));
+                }
+            }
+            None => (),
+        }
+        params.extend(self.equations.iter().map(|equation| format!("{}={}", equation.left, equation.right)));
+        write!(f, "{}", params.join("&"))
+    }
+}
+
+named!(equality<Equality>,
+    // longer tags first, so `lte` is not taken as `lt`
+    alt!(tag!("neq") => {|_| Equality::NEQ} |
+         tag!("eq") => {|_| Equality::EQ} | 
+         tag!("lte") => {|_| Equality::LTE} |
+         tag!("lt") => {|_| Equality::LT} |
+         tag!("gte") => {|_| Equality::GTE} |
+         tag!("gt") => {|_| Equality::GT} |
+         tag!("not_in") => {|_| Equality::NOT_IN} |
+         tag!("in") => {|_| Equality::IN} |
+         tag!("is_not") => {|_| Equality::IS_NOT} |
+         tag!("is") => {|_| Equality::IS} |
+         tag!("like") => {|_| Equality::LIKE} |
+         tag!("ilike") => {|_| Equality::ILIKE} |
+         tag!("st") => {|_| Equality::ST}
+    )
+);
+
+
+named!(connector <Connector>,
+   alt!(tag!("&") => {|_| Connector::AND} |
+        tag!("|") => {|_| Connector::OR}
+   )
+);
+
+
+/// fold the terms of a filter into an expression, a term is either a single condition
+/// or a parenthesized group. AND binds tighter than OR, and a group which adds nothing
+/// is inlined, `(a&b)|c` is `a&b|c`
+fn fold_terms(initial: Expr, remainder: Vec<(Connector, Expr)>) -> Expr {
+    fn push(exprs: &mut Vec<Expr>, expr: Expr, and: bool) {
+        match expr {
+            Expr::And(inner) if and => exprs.extend(inner),
+            Expr::Or(inner) if !and => exprs.extend(inner),
+            expr => exprs.push(expr),
+        }
+    }
+    fn collapse(mut exprs: Vec<Expr>, and: bool) -> Expr {
+        if exprs.len() == 1 {
+            exprs.remove(0)
+        } else if and {
+            Expr::And(exprs)
+        } else {
+            Expr::Or(exprs)
+        }
+    }
+    let mut disjuncts = vec![];
+    let mut conjuncts = vec![];
+    push(&mut conjuncts, initial, true);
+    for (conn, term) in remainder {
+        if conn == Connector::OR {
+            push(&mut disjuncts, collapse(conjuncts, true), false);
+            conjuncts = vec![];
+        }
+        push(&mut conjuncts, term, true);
+    }
+    push(&mut disjuncts, collapse(conjuncts, true), false);
+    collapse(disjuncts, false)
+}
+
+named!(filter_term <Expr>,
+    alt_complete!(
+        condition => {Expr::Condition} |
+        delimited!(tag!("("), filter_tree, tag!(")"))
+    )
+);
+
+named!(filter_tree <Expr>,
+    do_parse!(
+        initial: filter_term >>
+        remainder: many0!(
+           do_parse!(conn: connector >>
+               term: filter_term >> 
+                (conn, term)
+           )
+        )
+     >> (fold_terms(initial, remainder))
+    )
+);
+
+// a chain has to start with a condition, `(a|b)&(c|d)` is not a single filter
+named!(filter <Filter>,
+    map_opt!(filter_tree, |expr: Expr| expr.to_filter())
+);
+
+// the filters of a query parameter, which are AND-ed together
+named!(filters < Vec<Filter> >,
+    map!(filter_tree, |expr: Expr| expr.to_filters())
+);
+
+named!(filter_expr <Filter>,
+    alt_complete!(filter | delimited!(tag!("("), filter_expr, tag!(")")))
+);
+    
+
+
+
+named!(equation <Equation>, 
+    map!(separated_pair!(column,
+        tag!("="),
+        operand 
+    ),
+    |(col,op):(&str,Operand)|{
+        Equation{
+            left: Operand::Column(col.to_string()),
+            right: op
+        }
+    }
+    )
+);
+
+
+named!(condition <Condition>,
+    alt_complete!(search_condition | compare_condition)
+);
+
+named!(compare_condition <Condition>,
+    map!(tuple!(
+        column_operand,
+        tag!("="),
+        equality,
+        tag!("."),
+        operand
+    ),
+    |(left,_,eq,_,op):(Operand,_,Equality,_,Operand)|{
+        Condition{
+            left,
+            right: compared_operand(&eq, op),
+            equality: eq,
+        }
+    }
+    )
+);
+
+// a full text search or a regular expression, whose text has its own `&`, `|`
+// and parentheses: `body=fts(english).fat&(cat|dog)`, `name=match.^(Jo|Ja)`
+named!(search_condition <Condition>,
+    do_parse!(
+        left: column_operand >>
+        tag!("=") >>
+        equality: alt!(search_equality | pattern_equality) >>
+        tag!(".") >>
+        text: grouped_text >>
+        (Condition{
+            left,
+            equality,
+            right: Operand::Value(text.to_string())
+        })
+    )
+);
+
+named!(search_equality <Equality>,
+    do_parse!(
+        tag: alt!(tag!("plfts") | tag!("phfts") | tag!("wfts") | tag!("fts")) >>
+        language: opt!(complete!(delimited!(tag!("("), language, tag!(")")))) >>
+        ({
+            let language = language.map(str::to_string);
+            match tag {
+                b"plfts" => Equality::PLFTS(language),
+                b"phfts" => Equality::PHFTS(language),
+                b"wfts" => Equality::WFTS(language),
+                _ => Equality::FTS(language),
+            }
+        })
+    )
+);
+
+named!(pattern_equality <Equality>,
+    alt!(tag!("imatch") => {|_| Equality::IMATCH} |
+         tag!("match") => {|_| Equality::MATCH}
+    )
+);
+
+named!(language<&'a str>,
+    map_res!(recognize!(many1!(one_of!("abcdefghijklmnopqrstuvwxyz_"))), str::from_utf8)
+);
+
+/// the text of a search or a pattern runs until a `&` or a `|` which starts the
+/// next parameter or condition, one with a `=` before the next connector, or until
+/// a `)` which closes a group of the filter. The escaped characters, `\)`, and
+/// those of a class, `[)]`, do not count
+fn grouped_text(input: &[u8]) -> IResult<&[u8], &str> {
+    let mut depth = 0;
+    let mut end = input.len();
+    let mut escaped = false;
+    let mut class = None;
+    for (i, &c) in input.iter().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        if let Some(start) = class {
+            // a `]` right after the `[` or `[^` is in the class
+            if c == b']' && i > start + 1 && !(i == start + 2 && input[start + 1] == b'^') {
+                class = None;
+            }
+            continue;
+        }
+        match c {
+            b'\\' => escaped = true,
+            b'[' => class = Some(i),
+            b'(' => depth += 1,
+            b')' if depth == 0 => {
+                end = i;
+                break;
+            }
+            b')' => depth -= 1,
+            b'&' | b'|' => {
+                let next = &input[i + 1..];
+                let term = next.iter().position(|&c| c == b'&' || c == b'|').map_or(next, |j| &next[..j]);
+                if term.contains(&b'=') {
+                    end = i;
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    match str::from_utf8(&input[..end]) {
+        Ok(text) if !text.is_empty() => IResult::Done(&input[end..], text),
+        _ => IResult::Error(error_position!(nom::ErrorKind::Custom(0), input)),
+    }
+}
+
+// a column or an aggregate on a column, `count(*)`, `sum(price)`
+named!(column_operand <Operand>,
+    alt_complete!(
+        function => {Operand::Function} |
+        column => {|c:&str| Operand::Column(c.to_string())}
+    )
+);
+
+named!(function <Function>,
+    do_parse!(
+        name: column >>
+        tag!("(") >>
+        params: separated_list!(complete!(tag!(",")), function_param) >>
+        tag!(")") >>
+        (Function{
+            function: name.to_string(),
+            params
+        })
+    )
+);
+
+named!(function_param <Operand>,
+    alt_complete!(
+        tag!("*") => {|_| Operand::Value("*".to_string())} |
+        column => {|c:&str| Operand::Column(c.to_string())}
+    )
+);
+
+named!(direction <Direction>,
+    alt!(tag!("asc") => {|_| Direction::ASC} |
+         tag!("desc") => {|_| Direction::DESC}
+    )
+);
+
+named!(nulls_where <NullsWhere>,
+    alt!(tag!("nulls_first") => {|_| NullsWhere::FIRST} |
+         tag!("nulls_last") => {|_| NullsWhere::LAST}
+    )
+);
+
+// `name`, `name.desc`, `name.asc.nulls_first`, `count(*).desc`
+named!(order <Order>,
+    map!(
+        do_parse!(
+            operand: column_operand >>
+            direction: opt!(complete!(preceded!(tag!("."), direction))) >>
+            nulls_where: opt!(complete!(preceded!(tag!("."), nulls_where))) >>
+            (Order{
+                operand,
+                direction,
+                nulls_where
+            })
+        ),
+        split_order_suffixes
+    )
+);
+
+/// a dotted column takes in the `.desc` and `.nulls_last` suffixes of its order,
+/// they are split off again here
+fn split_order_suffixes(mut order: Order) -> Order {
+    if let Operand::Column(ref mut column) = order.operand {
+        if order.nulls_where.is_none() {
+            if column.ends_with(".nulls_first") || column.ends_with(".nulls_last") {
+                let dot = column.rfind('.').unwrap_or(0);
+                order.nulls_where = match &column[dot + 1..] {
+                    "nulls_first" => Some(NullsWhere::FIRST),
+                    _ => Some(NullsWhere::LAST),
+                };
+                column.truncate(dot);
+            }
+            if order.direction.is_none() {
+                if column.ends_with(".asc") {
+                    order.direction = Some(Direction::ASC);
+                    column.truncate(column.len() - ".asc".len());
+                } else if column.ends_with(".desc") {
+                    order.direction = Some(Direction::DESC);
+                    column.truncate(column.len() - ".desc".len());
+                }
+            }
+        }
+    }
+    order
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+enum QueryParam {
+    Filters(Vec<Filter>),
+    Equation(Equation),
+    From(Vec<Operand>),
+    GroupBy(Vec<Operand>),
+    Having(Vec<Filter>),
+    OrderBy(Vec<Order>),
+    Limit(i64),
+    Offset(i64),
+    Page(i64),
+    PageSize(i64),
+    Cursor(Seek, String),
+}
+
+named!(query_param <QueryParam>,
+    alt_complete!(
+        preceded!(tag!("from="), separated_nonempty_list!(complete!(tag!(",")), column_operand))
+            => {QueryParam::From} |
+        preceded!(tag!("group_by="), separated_nonempty_list!(complete!(tag!(",")), column_operand))
+            => {QueryParam::GroupBy} |
+        preceded!(tag!("having="), filters) => {QueryParam::Having} |
+        preceded!(tag!("order_by="), separated_nonempty_list!(complete!(tag!(",")), order))
+            => {QueryParam::OrderBy} |
+        preceded!(tag!("limit="), range_number) => {QueryParam::Limit} |
+        preceded!(tag!("offset="), range_number) => {QueryParam::Offset} |
+        preceded!(tag!("page="), range_number) => {QueryParam::Page} |
+        preceded!(tag!("page_size="), range_number) => {QueryParam::PageSize} |
+        preceded!(tag!("after="), cursor) => {|cursor: &str| QueryParam::Cursor(Seek::After, cursor.to_string())} |
+        preceded!(tag!("before="), cursor) => {|cursor: &str| QueryParam::Cursor(Seek::Before, cursor.to_string())} |
+        filters => {QueryParam::Filters} |
+        equation => {QueryParam::Equation}
+    )
+);
+
+// the number has to be the whole value, `limit=10abc` is not a limit
+named!(range_number <i64>,
+    terminated!(number, alt_complete!(eof!() | peek!(tag!("&"))))
+);
+
+// a cursor is base64url, with a `.` before its signature
+named!(cursor<&'a str>,
+    terminated!(
+        map_res!(complete!(recognize!(many1!(one_of!(
+            "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_."
+        )))), str::from_utf8),
+        alt_complete!(eof!() | peek!(tag!("&")))
+    )
+);
+
+named!(query_params < Vec<QueryParam> >,
+    separated_list!(complete!(tag!("&")), query_param)
+);
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum ParseError {
+    /// the query could not be parsed from this byte offset on
+    Syntax(usize),
+    /// the range parameters do not make up a range, ie: `offset` without `limit`
+    InvalidRange(String),
+    /// the query is larger than the limits allow
+    Limit(LimitError),
+    /// the pattern of a `match` or `imatch` is not valid or allowed
+    Pattern(PatternError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Syntax(position) => write!(f, "syntax error at position {}", position),
+            ParseError::InvalidRange(ref reason) => write!(f, "invalid range: {}", reason),
+            ParseError::Limit(ref e) => write!(f, "query too large: {}", e),
+            ParseError::Pattern(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for ParseError {}
+
+/// parse a query string which has to stay within the limits, the input is
+/// checked before it is parsed
+pub fn parse_query_with_limits(input: &str, limits: &Limits) -> Result<Query, ParseError> {
+    limits::check_input(input, limits).map_err(ParseError::Limit)?;
+    let query = parse_query(input)?;
+    limits::check_query(&query, limits).map_err(ParseError::Limit)?;
+    Ok(query)
+}
+
+/// parse a whole query string: `age=lt.20&(product=eq.134|price=lt.100.0)&order_by=name.desc&limit=10`
+///
+/// The parameters are separated with `&` and the filters among them are AND-ed together.
+/// `from`, `group_by`, `having`, `order_by`, `limit`, `offset`, `page`, `page_size`,
+/// `after` and `before` have their own meaning, any other `key=value` is kept as an equation
+pub fn parse_query(input: &str) -> Result<Query, ParseError> {
+    if input.is_empty() {
+        return Ok(Query::default());
+    }
+    let params = match query_params(input.as_bytes()) {
+        IResult::Done(rest, params) => {
+            if !rest.is_empty() {
+                let position = input.len() - rest.len();
+                // point at the parameter which failed rather than at its separator
+                let position = if rest[0] == b'&' { position + 1 } else { position };
+                return Err(ParseError::Syntax(position));
+            }
+            params
+        }
+        _ => return Err(ParseError::Syntax(0)),
+    };
+    let mut query = Query::default();
+    let mut limit = None;
+    let mut offset = None;
+    let mut page = None;
+    let mut page_size = None;
+    let mut cursor = None;
+    for param in params {
+        match param {
+            QueryParam::Filters(filters) => query.filters.extend(filters),
+            QueryParam::Equation(equation) => query.equations.push(equation),
+            QueryParam::From(from) => query.from.extend(from),
+            QueryParam::GroupBy(group_by) => query.group_by.extend(group_by),
+            QueryParam::Having(having) => query.having.extend(having),
+            QueryParam::OrderBy(order_by) => query.order_by.extend(order_by),
+            QueryParam::Limit(n) => limit = Some(n),
+            QueryParam::Offset(n) => offset = Some(n),
+            QueryParam::Page(n) => page = Some(n),
+            QueryParam::PageSize(n) => page_size = Some(n),
+            QueryParam::Cursor(seek, value) => {
+                if cursor.is_some() {
+                    return Err(ParseError::InvalidRange("only one of after and before".to_string()));
+                }
+                cursor = Some((seek, value));
+            }
+        }
+    }
+    check_patterns(&query).map_err(ParseError::Pattern)?;
+    if let Some((seek, cursor)) = cursor {
+        if offset.is_some() || page.is_some() || page_size.is_some() {
+            return Err(ParseError::InvalidRange("a cursor can not be mixed with offset and pages".to_string()));
+        }
+        query.range = Some(Range::Keyset(Keyset {
+            seek,
+            cursor,
+            limit,
+        }));
+        return Ok(query);
+    }
+    query.range = match (limit, offset, page, page_size) {
+        (None, None, None, None) => None,
+        (Some(limit), offset, None, None) => Some(Range::Limit(Limit{
+            limit,
+            offset,
+        })),
+        (None, Some(_), None, None) => {
+            return Err(ParseError::InvalidRange("offset needs a limit".to_string()));
+        }
+        (None, None, page, Some(page_size)) => {
+            let page = page.unwrap_or(1);
+            if page < 1 {
+                return Err(ParseError::InvalidRange("pages start at 1".to_string()));
+            }
+            let page = Page{
+                page,
+                page_size,
+            };
+            if page.offset().is_none() {
+                return Err(ParseError::InvalidRange("the offset of the page is too large".to_string()));
+            }
+            Some(Range::Page(page))
+        }
+        (None, None, Some(_), None) => {
+            return Err(ParseError::InvalidRange("page needs a page_size".to_string()));
+        }
+        _ => {
+            return Err(ParseError::InvalidRange("page can not be mixed with limit and offset".to_string()));
+        }
+    };
+    Ok(query)
+}
+
+/// the patterns of the regular expressions are compiled, to refuse those which
+/// are not valid or could take too long
+fn check_patterns(query: &Query) -> Result<(), PatternError> {
+    for filter in query.filters.iter().chain(&query.having) {
+        let expr = expr::Expr::from(filter);
+        for condition in expr.conditions() {
+            let case_insensitive = match condition.equality {
+                Equality::MATCH => false,
+                Equality::IMATCH => true,
+                _ => continue,
+            };
+            let pattern = condition.right.to_string();
+            regexp::compile(&pattern, case_insensitive)?;
+        }
+    }
+    Ok(())
+}
+
+named!(condition_expr <Condition>,
+    alt_complete!(condition | complete!(delimited!(tag!("("), condition_expr, tag!(")"))))
+);
+
+
+named!(unsigned_float <f64>, map_res!(
+  map_res!(
+    recognize!(
+      alt_complete!(
+        delimited!(digit, tag!("."), opt!(complete!(digit))) |
+        delimited!(opt!(digit), tag!("."), complete!(digit)) |
+        complete!(digit)
+      )
+    ),
+    str::from_utf8
+  ),
+  FromStr::from_str
+));
+
+named!(float <f64>, map!(
+  pair!(
+    opt!(alt!(tag!("+") | tag!("-"))),
+    unsigned_float
+  ),
+  |(sign, value): (Option<&[u8]>, f64)| {
+    sign.and_then(|s| if s[0] == b'-' { Some(-1f64) } else { None }).unwrap_or(1f64) * value
+  }
+));
+
+
+#[cfg(test)]
+fn parse_filter(input: &str) -> Filter {
+    match filter_expr(input.as_bytes()) {
+        IResult::Done(rest, filter) => {
+            assert!(rest.is_empty(), "unparsed: {:?}", ::std::str::from_utf8(rest));
+            filter
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+fn assert_golden(name: &str, actual: &serde_json::Value) {
+    let path = format!("{}/tests/golden/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+    let golden = ::std::fs::read_to_string(&path).expect(&path);
+    let expected: serde_json::Value = serde_json::from_str(&golden).expect(&path);
+    assert_eq!(&expected, actual, "golden file: {}", path);
+}
+
+#[test]
+fn test_identifier(){
+    assert_eq!(column("ahello".as_bytes()), IResult::Done(&b""[..],"ahello"));
+    assert_eq!(column("hello_".as_bytes()), IResult::Done(&b""[..],"hello_"));
+    assert_eq!(column("hello1".as_bytes()), IResult::Done(&b""[..],"hello1"));
+}
+
+#[test]
+fn test_value(){
+    assert_eq!(value("hello world!".as_bytes()), IResult::Done(&b""[..],"hello world!"));
+    assert_eq!(value("技術通報".as_bytes()), IResult::Done(&b""[..],"技術通報"));
+    assert_eq!(value("John&age=lt.20".as_bytes()), IResult::Done(&b"&age=lt.20"[..],"John"));
+    assert_eq!(value("John|Jane".as_bytes()), IResult::Done(&b"|Jane"[..],"John"));
+}
+
+#[test]
+fn test_param(){
+    assert_eq!(query_param(&b"product=eq.134"[..]), IResult::Done(&b""[..], 
+        QueryParam::Filters(vec![Filter{
+            connector: None,
+            condition: Condition{
+                left: Operand::Column("product".to_string()),
+                equality: Equality::EQ,
+                right: Operand::Number(134f64)
+            },
+            sub_filters: vec![]
+          }]
+        )));
+
+    assert_eq!(query_param(&b"product=134"[..]), IResult::Done(&b""[..], 
+        QueryParam::Equation(Equation{
+            left: Operand::Column("product".to_string()),
+            right: Operand::Number(134f64)
+          }
+        )));
+}
+
+#[test]
+fn test_params(){
+    let product = Filter{
+        connector: None,
+        condition: Condition{
+            left: Operand::Column("product".to_string()),
+            equality: Equality::EQ,
+            right: Operand::Number(134f64)
+        },
+        sub_filters: vec![]
+    };
+    assert_eq!(query_params(&b"product=eq.134"[..]), IResult::Done(&b""[..], 
+        vec![QueryParam::Filters(vec![product.clone()])]
+        ));
+
+    assert_eq!(query_params(&b"product=eq.134&size=2"[..]), IResult::Done(&b""[..], 
+        vec![QueryParam::Filters(vec![product]),
+            QueryParam::Equation(Equation{
+                left: Operand::Column("size".to_string()),
+                right: Operand::Number(2f64)
+            })
+          ]
+        ));
+}
+
+// (filter)&condition wont match
+#[test]
+fn test_filter_issue1(){
+    assert_eq!(filter(&b"age=lt.20&product=eq.134&price=lt.100.0"[..]), IResult::Done(&b""[..], 
+        Filter{
+            connector: None,
+            condition: Condition{
+                    left: Operand::Column("age".to_string()),
+                    equality: Equality::LT,
+                    right: Operand::Number(20f64)
+                },
+            sub_filters:vec![
+                Filter{
+                    condition:Condition{
+                        left: Operand::Column("product".to_string()),
+                        equality: Equality::EQ,
+                        right: Operand::Number(134f64)
+                    },
+                    connector: Some(Connector::AND),
+                    sub_filters: vec![
+                    ]
+                },
+                Filter{
+                    connector: Some(Connector::AND),
+                    condition: Condition{
+                        left: Operand::Column("price".to_string()),
+                        equality: Equality::LT,
+                        right: Operand::Number(100.0)
+                    },
+                    sub_filters: vec![]
+                }
+            ]
+        }
+        ));
+}
+// (filter)&(filter) are the filters of the query
+#[test]
+fn test_filter_issue2(){
+    assert_eq!(filters(&b"(a=eq.1|b=eq.2)&(c=eq.3|d=eq.4)"[..]), IResult::Done(&b""[..], 
+        vec![parse_filter("a=eq.1|b=eq.2"), parse_filter("c=eq.3|d=eq.4")]));
+}
+
+#[test]
+fn test_filters(){
+    assert_eq!(filter(&b"product=eq.134"[..]), IResult::Done(&b""[..], 
+        Filter{
+            connector: None,
+            condition:Condition{
+                left: Operand::Column("product".to_string()),
+                equality: Equality::EQ,
+                right: Operand::Number(134f64)
+            },
+            sub_filters: vec![]
+        }
+        ));
+
+    assert_eq!(filter(&b"product=eq.134&price=lt.100.0"[..]), IResult::Done(&b""[..], 
+        Filter{
+            condition:Condition{
+                left: Operand::Column("product".to_string()),
+                equality: Equality::EQ,
+                right: Operand::Number(134f64)
+            },
+            connector: None,
+            sub_filters: vec![
+                Filter{
+                    connector: Some(Connector::AND),
+                    condition: Condition{
+                        left: Operand::Column("price".to_string()),
+                        equality: Equality::LT,
+                        right: Operand::Number(100.0)
+                    },
+                    sub_filters: vec![]
+                }
+            ]
+        }
+        ));
+
+    assert_eq!(filter(&b"product=eq.134|price=lt.100.0"[..]), IResult::Done(&b""[..], 
+        Filter{
+            condition:Condition{
+                left: Operand::Column("product".to_string()),
+                equality: Equality::EQ,
+                right: Operand::Number(134f64)
+            },
+            connector: None,
+            sub_filters: vec![
+                Filter{
+                    connector: Some(Connector::OR),
+                    condition: Condition{
+                        left: Operand::Column("price".to_string()),
+                        equality: Equality::LT,
+                        right: Operand::Number(100.0)
+                    },
+                    sub_filters: vec![]
+                }
+            ]
+        }
+        ));
+
+    assert_eq!(filter_expr(&b"(product=eq.134|price=lt.100.0)"[..]), IResult::Done(&b""[..], 
+        Filter{
+            condition:Condition{
+                left: Operand::Column("product".to_string()),
+                equality: Equality::EQ,
+                right: Operand::Number(134f64)
+            },
+            connector: None,
+            sub_filters: vec![
+                Filter{
+                    connector: Some(Connector::OR),
+                    condition: Condition{
+                        left: Operand::Column("price".to_string()),
+                        equality: Equality::LT,
+                        right: Operand::Number(100.0)
+                    },
+                    sub_filters: vec![]
+                }
+            ]
+        }
+        ));
+    
+}
+
+#[test]
+fn test_paren_filter_exprs(){
+    assert_eq!(filter_expr(&b"(product=eq.134)|(price=lt.100.0)"[..]), IResult::Done(&b""[..], 
+        Filter{
+            condition:Condition{
+                left: Operand::Column("product".to_string()),
+                equality: Equality::EQ,
+                right: Operand::Number(134f64)
+            },
+            connector: None,
+            sub_filters: vec![
+                Filter{
+                    condition: Condition{
+                        left: Operand::Column("price".to_string()),
+                        equality: Equality::LT,
+                        right: Operand::Number(100.0)
+                    },
+                    connector: Some(Connector::OR),
+                    sub_filters: vec![]
+                }
+            ]
+        }
+        ));
+
+    assert_eq!(filter_expr(&b"age=lt.20&(product=eq.134|price=lt.100.0)"[..]), IResult::Done(&b""[..], 
+        Filter{
+            condition: Condition{
+                    left: Operand::Column("age".to_string()),
+                    equality: Equality::LT,
+                    right: Operand::Number(20f64)
+                },
+            connector: None,
+            sub_filters:vec![
+                Filter{
+                    condition:Condition{
+                        left: Operand::Column("product".to_string()),
+                        equality: Equality::EQ,
+                        right: Operand::Number(134f64)
+                    },
+                    connector: Some(Connector::OR),
+                    sub_filters: vec![
+                        Filter{
+                            connector: None,
+                            condition: Condition{
+                                left: Operand::Column("price".to_string()),
+                                equality: Equality::LT,
+                                right: Operand::Number(100.0)
+                            },
+                            sub_filters: vec![]
+                        }
+                    ]
+                }
+            ]
+        }
+        ));
+}
+
+#[test]
+fn test_leading_group(){
+    use expr::Expr;
+    // a group with OR can not be the head of a chain, the next condition takes its place
+    assert_eq!(Expr::from(&parse_filter("(a=eq.1|b=eq.2)&c=eq.3")),
+        Expr::from(&parse_filter("c=eq.3&(a=eq.1|b=eq.2)")));
+    assert_eq!(Expr::from(&parse_filter("(a=eq.1|b=eq.2)&(x=eq.0|y=eq.0)&c=eq.3|d=eq.4")),
+        Expr::from(&parse_filter("c=eq.3&(a=eq.1|b=eq.2)&(x=eq.0|y=eq.0)|d=eq.4")));
+    // inlined when that keeps the meaning
+    assert_eq!(parse_filter("(a=eq.1&b=eq.2)|c=eq.3"), parse_filter("a=eq.1&b=eq.2|c=eq.3"));
+    assert_eq!(parse_filter("(a=eq.1|b=eq.2)|c=eq.3"), parse_filter("a=eq.1|b=eq.2|c=eq.3"));
+    // a chain can not start with a group, the groups are separate filters of the query
+    assert!(filter(&b"(a=eq.1|b=eq.2)&(c=eq.3|d=eq.4)"[..]).is_err());
+    assert_eq!(parse_query("(a=eq.1|b=eq.2)&(c=eq.3|d=eq.4)").unwrap().filters,
+        vec![parse_filter("a=eq.1|b=eq.2"), parse_filter("c=eq.3|d=eq.4")]);
+    assert_eq!(parse_query("having=(count(*)=gt.1|sum(x)=gt.1)&(a=eq.1|b=eq.2)").unwrap().having,
+        vec![parse_filter("count(*)=gt.1|sum(x)=gt.1"), parse_filter("a=eq.1|b=eq.2")]);
+    assert_eq!(parse_query("(a=eq.1|b=eq.2)&(c=eq.3|d=eq.4)|e=eq.5").unwrap().filters,
+        vec![parse_filter("a=eq.1&(c=eq.3|d=eq.4)|b=eq.2&(c=eq.3|d=eq.4)|e=eq.5")]);
+}
+
+#[test]
+fn test_display_filters(){
+    for input in &["a=eq.1|b=eq.2&c=eq.3", "(a=eq.1|b=eq.2)&(c=eq.3|d=eq.4)&e=eq.5&limit=10",
+                   "having=count(*)=gt.1|sum(x)=gt.2&having=min(x)=eq.0"] {
+        let query = parse_query(input).unwrap();
+        assert_eq!(parse_query(&query.to_string()), Ok(query));
+    }
+    let query = Query{
+        filters: vec![parse_filter("a=eq.1|b=eq.2"), parse_filter("c=eq.3"), parse_filter("d=eq.4|e=eq.5")],
+        ..Query::default()
+    };
+    assert_eq!(query.to_string(), "(a=eq.1|b=eq.2)&c=eq.3&(d=eq.4|e=eq.5)");
+    // parsed back into a single chain, which starts with the condition
+    assert_eq!(parse_query(&query.to_string()).unwrap().filters,
+               vec![parse_filter("c=eq.3&(a=eq.1|b=eq.2)&(d=eq.4|e=eq.5)")]);
+}
+
+#[test]
+fn test_parse_query(){
+    assert_eq!(parse_query("age=lt.20&(product=eq.134|price=lt.100.0)&order_by=name.desc,age&limit=10&offset=20&format=csv"),
+        Ok(Query{
+            filters: vec![parse_filter("age=lt.20&(product=eq.134|price=lt.100.0)")],
+            order_by: vec![
+                Order{
+                    operand: Operand::Column("name".to_string()),
+                    direction: Some(Direction::DESC),
+                    nulls_where: None,
+                },
+                Order{
+                    operand: Operand::Column("age".to_string()),
+                    direction: None,
+                    nulls_where: None,
+                }
+            ],
+            range: Some(Range::Limit(Limit{
+                limit: 10,
+                offset: Some(20),
+            })),
+            equations: vec![Equation{
+                left: Operand::Column("format".to_string()),
+                right: Operand::Value("csv".to_string()),
+            }],
+            ..Default::default()
+        }));
+    assert_eq!(parse_query(""), Ok(Query::default()));
+}
+
+#[test]
+fn test_parse_group_by(){
+    let count = Operand::Function(Function{
+        function: "count".to_string(),
+        params: vec![Operand::Value("*".to_string())],
+    });
+    assert_eq!(parse_query("from=users&group_by=dept&having=count(*)=gt.1&order_by=count(*).desc.nulls_last,dept.nulls_first&page=2&page_size=50"),
+        Ok(Query{
+            from: vec![Operand::Column("users".to_string())],
+            group_by: vec![Operand::Column("dept".to_string())],
+            having: vec![Filter{
+                condition: Condition{
+                    left: count.clone(),
+                    equality: Equality::GT,
+                    right: Operand::Number(1f64),
+                },
+                connector: None,
+                sub_filters: vec![],
+            }],
+            order_by: vec![
+                Order{
+                    operand: count,
+                    direction: Some(Direction::DESC),
+                    nulls_where: Some(NullsWhere::LAST),
+                },
+                Order{
+                    operand: Operand::Column("dept".to_string()),
+                    direction: None,
+                    nulls_where: Some(NullsWhere::FIRST),
+                }
+            ],
+            range: Some(Range::Page(Page{
+                page: 2,
+                page_size: 50,
+            })),
+            ..Default::default()
+        }));
+}
+
+#[test]
+fn test_nested_columns(){
+    use expr::Expr;
+    let query = parse_query("address.city=eq.Paris&$.tags[0]=eq.new&order_by=address.zip.desc.nulls_last,name")
+        .unwrap();
+    assert_eq!(Expr::from_filters(&query.filters).unwrap().conditions().iter()
+        .map(|c| c.left.clone()).collect::<Vec<_>>(),
+        vec![Operand::Column("address.city".to_string()), Operand::Column("$.tags[0]".to_string())]);
+    assert_eq!(query.order_by, vec![
+        Order{
+            operand: Operand::Column("address.zip".to_string()),
+            direction: Some(Direction::DESC),
+            nulls_where: Some(NullsWhere::LAST),
+        },
+        Order{
+            operand: Operand::Column("name".to_string()),
+            direction: None,
+            nulls_where: None,
+        }
+    ]);
+}
+
+#[test]
+fn test_parse_errors(){
+    assert_eq!(parse_query("age=lt.20&&name=st.J"), Err(ParseError::Syntax(10)));
+    assert_eq!(parse_query("(age=lt.20"), Err(ParseError::Syntax(0)));
+    assert_eq!(parse_query("offset=10"), Err(ParseError::InvalidRange("offset needs a limit".to_string())));
+    assert_eq!(parse_query("page=2"), Err(ParseError::InvalidRange("page needs a page_size".to_string())));
+    assert_eq!(parse_query("page=9223372036854775807&page_size=10"),
+               Err(ParseError::InvalidRange("the offset of the page is too large".to_string())));
+    assert_eq!(parse_query("page=0&page_size=10"), Err(ParseError::InvalidRange("pages start at 1".to_string())));
+    assert!(parse_query("limit=10&page=2&page_size=10").is_err());
+    assert!(parse_query("after=abc&before=abc").is_err());
+    assert!(parse_query("after=abc&page_size=10").is_err());
+    // not a number, so an equation
+    assert_eq!(parse_query("limit=ten").map(|q| q.equations.len()), Ok(1));
+}
+
+#[test]
+fn test_search_condition(){
+    let query = parse_query("body=fts(english).fat&cat&limit=10").unwrap();
+    assert_eq!(query.filters, vec![Filter{
+        connector: None,
+        condition: Condition{
+            left: Operand::Column("body".to_string()),
+            equality: Equality::FTS(Some("english".to_string())),
+            right: Operand::Value("fat&cat".to_string()),
+        },
+        sub_filters: vec![],
+    }]);
+    assert_eq!(query.to_string(), "body=fts(english).fat&cat&limit=10");
+    // the group of the search and the group of the filter
+    let filter = parse_filter("a=eq.1&(body=wfts.\"fat cat\" or dog|title=plfts.fat & (cat|dog))");
+    assert_eq!(filter.sub_filters[0].condition.right, Operand::Value("\"fat cat\" or dog".to_string()));
+    assert_eq!(filter.sub_filters[0].sub_filters[0].condition.right, Operand::Value("fat & (cat|dog)".to_string()));
+    assert_eq!(parse_filter("t=phfts.fat cat|count(*)=gt.1").sub_filters.len(), 1);
+    assert!(parse_query("body=fts(English).cat").is_err());
+    // not a search, so an equation
+    assert_eq!(parse_query("body=fts.&a=eq.1").map(|q| (q.filters.len(), q.equations.len())), Ok((1, 1)));
+}
+
+#[test]
+fn test_pattern_condition(){
+    let query = parse_query("name=match.^Jo(hn|e)$&nick=imatch.[a&|)]\\)+&age=gt.1").unwrap();
+    assert_eq!(query.filters[0].condition, Condition{
+        left: Operand::Column("name".to_string()),
+        equality: Equality::MATCH,
+        right: Operand::Value("^Jo(hn|e)$".to_string()),
+    });
+    // the `&`, `|` and `)` of a class and an escaped `)` are in the pattern
+    let nick = &query.filters[0].sub_filters[0].condition;
+    assert_eq!(nick.equality, Equality::IMATCH);
+    assert_eq!(nick.right, Operand::Value("[a&|)]\\)+".to_string()));
+    assert_eq!(query.filters[0].sub_filters.len(), 2);
+    assert_eq!(query.to_string(), "name=match.^Jo(hn|e)$&nick=imatch.[a&|)]\\)+&age=gt.1");
+    assert_eq!(parse_query("name=match.(a+)+"), Err(ParseError::Pattern(PatternError::NestedRepetition)));
+    assert!(matches!(parse_query("name=imatch.(a"), Err(ParseError::Pattern(PatternError::Syntax(_)))));
+}
+
+#[test]
+fn test_keyset(){
+    let query = parse_query("order_by=id&after=eyJhIjoxfQ.c2ln&limit=20").unwrap();
+    assert_eq!(query.range, Some(Range::Keyset(Keyset{
+        seek: Seek::After,
+        cursor: "eyJhIjoxfQ.c2ln".to_string(),
+        limit: Some(20),
+    })));
+    assert_eq!(query.to_string(), "order_by=id&after=eyJhIjoxfQ.c2ln&limit=20");
+    let query = parse_query("before=a-b_c").unwrap();
+    // the rows of a keyset depend on its cursor
+    assert_eq!(query.range.as_ref().map(Range::bounds), Some(None));
+    assert_eq!(query.range.as_ref().map(Range::size), Some(i64::MAX as usize));
+    // not a cursor, so an equation
+    assert_eq!(parse_query("after=a+b").map(|q| (q.range, q.equations.len())), Ok((None, 1)));
+}
+
+#[test]
+fn test_boolean(){
+    assert_eq!(boolean(&b"true"[..]), IResult::Done(&b""[..], true));
+    assert_eq!(boolean(&b"false"[..]), IResult::Done(&b""[..], false));
+}
+
+#[test]
+fn test_cond(){
+    assert_eq!(condition(&b"product=eq.134"[..]), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("product".to_string()),
+            equality: Equality::EQ,
+            right: Operand::Number(134f64)
+          }
+        ));
+
+    assert_eq!(condition(&b"active=eq.true"[..]), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("active".to_string()),
+            equality: Equality::EQ,
+            right: Operand::Boolean(true)
+          }
+        ));
+    assert_eq!(condition(&b"price=lt.-0.3"[..]), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("price".to_string()),
+            equality: Equality::LT,
+            right: Operand::Number(-0.3)
+          }
+        ));
+    
+    assert_eq!(condition(&b"name=st.John"[..]), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("name".to_string()),
+            equality: Equality::ST,
+            right: Operand::Value("John".to_string())
+          }
+        ));
+
+    assert_eq!(condition(&b"name=st.John Cena"[..]), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("name".to_string()),
+            equality: Equality::ST,
+            right: Operand::Value("John Cena".to_string())
+          }
+        ));
+
+    assert_eq!(condition_expr(&b"(name=st.John)"[..]), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("name".to_string()),
+            equality: Equality::ST,
+            right: Operand::Value("John".to_string())
+          }
+        ));
+    assert_eq!(condition_expr(&b"((name=st.John))"[..]), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("name".to_string()),
+            equality: Equality::ST,
+            right: Operand::Value("John".to_string())
+          }
+        ));
+    assert_eq!(condition("name=st.技術通".as_bytes()), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("name".to_string()),
+            equality: Equality::ST,
+            right: Operand::Value("技術通".to_string())
+          }
+        ));
+    assert_eq!(condition("name=ilike.*° ͜ʖ ͡°*".as_bytes()), IResult::Done(&b""[..], 
+        Condition{
+            left: Operand::Column("name".to_string()),
+            equality: Equality::ILIKE,
+            right: Operand::Value("*° ͜ʖ ͡°*".to_string())
+          }
+        ));
+}
+
+#[test]
+fn test_equality(){
+    assert_eq!(equality(&b"eq"[..]), IResult::Done(&b""[..], Equality::EQ));
+    assert_eq!(equality(&b"neq"[..]), IResult::Done(&b""[..], Equality::NEQ));
+    assert_eq!(equality(&b"st"[..]), IResult::Done(&b""[..], Equality::ST));
+    assert_eq!(equality(&b"ilike"[..]), IResult::Done(&b""[..], Equality::ILIKE));
+    assert_eq!(equality(&b"lte"[..]), IResult::Done(&b""[..], Equality::LTE));
+    assert_eq!(equality(&b"gte"[..]), IResult::Done(&b""[..], Equality::GTE));
+    assert_eq!(equality(&b"is_not"[..]), IResult::Done(&b""[..], Equality::IS_NOT));
+    assert_eq!(equality(&b"not_in"[..]), IResult::Done(&b""[..], Equality::NOT_IN));
+}
+
+
+
+#[test]
+fn test_operand() {
+    assert_eq!(operand(&b"product"[..]), 
+        IResult::Done(&b""[..],Operand::Value("product".to_string()))); 
+
+    assert_eq!(operand(&b"1234"[..]), 
+        IResult::Done(&b""[..],Operand::Number(1234f64))); 
+
+    assert_eq!(operand(&b"true"[..]), 
+        IResult::Done(&b""[..],Operand::Boolean(true))); 
+
+    assert_eq!(operand(&b"false"[..]), 
+        IResult::Done(&b""[..],Operand::Boolean(false))); 
+
+    // half match?
+    //assert_eq!(operand(&b"true false"[..]), 
+    //    IResult::Done(&b""[..],Operand::Column("true false".to_string()))); 
+
+    assert_eq!(operand(&b"Hello world!"[..]), 
+        IResult::Done(&b""[..],Operand::Value("Hello world!".to_string()))); 
+
+    assert_eq!(operand(&b"hello world!"[..]), 
+        IResult::Done(&b""[..],Operand::Value("hello world!".to_string()))); 
+
+    assert_eq!(operand(&b"1,2"[..]), 
+        IResult::Done(&b""[..],Operand::Value("1,2".to_string()))); 
+
+    assert_eq!(operand(&b"12abc"[..]), 
+        IResult::Done(&b""[..],Operand::Value("12abc".to_string()))); 
+
+    let long = "9".repeat(400);
+    assert_eq!(operand(long.as_bytes()), IResult::Done(&b""[..], Operand::Value(long.clone())));
+
+    // only a marked relative time is one
+    assert_eq!(operand(&b"now"[..]), 
+        IResult::Done(&b""[..],Operand::Value("now".to_string()))); 
+    assert_eq!(operand(&b"@now-7d"[..]), 
+        IResult::Done(&b""[..],Operand::Relative("now-7d".parse().unwrap()))); 
+    assert_eq!(::parse_filter("day=in.today,@today").condition.right.list_items(),
+        vec![Operand::Value("today".to_string()), Operand::Relative("today".parse().unwrap())]);
+}
+
+#[test]
+fn test_relative_condition() {
+    // a range comparison needs no `@`, anywhere else a relative time is marked
+    let query = parse_query("created_at=gt.now-7d&due=lt.@today+1w&status=eq.now&tag=in.today,@today&name=gte.nowhere").unwrap();
+    let rights: Vec<Operand> = query.filters[0].sub_filters.iter().map(|f| f.condition.right.clone()).collect();
+    assert_eq!(query.filters[0].condition.right, Operand::Relative("now-7d".parse().unwrap()));
+    assert_eq!(rights, vec![Operand::Relative("today+1w".parse().unwrap()),
+                            Operand::Value("now".to_string()),
+                            Operand::List(vec![Operand::Value("today".to_string()),
+                                               Operand::Relative("today".parse().unwrap())]),
+                            Operand::Value("nowhere".to_string())]);
+    assert_eq!(query.to_string(), "created_at=gt.now-7d&due=lt.today+1w&status=eq.now&tag=in.today,@today&name=gte.nowhere");
+}
+
+#[test]
+fn test_column() {
+    assert_eq!(column(&b"product"[..]), IResult::Done(&b""[..], "product"));
+    //assert_eq!(column(&b"product_id"[..]), IResult::Done(&b""[..], "product_id"));
+}
+
+#[test]
+fn unsigned_float_test() {
+  assert_eq!(unsigned_float(&b"123.456"[..]), IResult::Done(&b""[..], 123.456));
+  assert_eq!(unsigned_float(&b"0.123"[..]),   IResult::Done(&b""[..], 0.123));
+  assert_eq!(unsigned_float(&b"123.0"[..]),   IResult::Done(&b""[..], 123.0));
+  assert_eq!(unsigned_float(&b"123."[..]),    IResult::Done(&b""[..], 123.0));
+  assert_eq!(unsigned_float(&b".123"[..]),    IResult::Done(&b""[..], 0.123));
+  assert_eq!(unsigned_float(&b"123456"[..]), IResult::Done(&b""[..], 123456f64));
+}
+
+#[test]
+fn float_test() {
+  assert_eq!(float(&b"123.456"[..]),  IResult::Done(&b""[..], 123.456));
+  assert_eq!(float(&b"+123.456"[..]), IResult::Done(&b""[..], 123.456));
+  assert_eq!(float(&b"-123.456"[..]), IResult::Done(&b""[..], -123.456));
+}
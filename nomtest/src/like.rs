@@ -0,0 +1,310 @@
+//! Normalisation of the values used by `like`, `ilike` and `st`
+//!
+//! Users write `*` as the wildcard in the url, `name=ilike.*john*`,
+//! any other character is taken literally, including `%`, `_` and `\`
+//! which would otherwise have a special meaning in a sql LIKE pattern.
+//! Every output backend goes through `LikePattern` so they all agree on
+//! what a pattern matches.
+
+use collation::Collation;
+use relative::SystemClock;
+use {Equality, Operand};
+
+/// the escape character used in the rendered sql LIKE pattern,
+/// sql backends need to add `ESCAPE '\'` to the expression
+pub const ESCAPE: char = '\\';
+
+/// the wildcard users write in the url
+pub const WILDCARD: char = '*';
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Token {
+    /// text to be matched as is
+    Literal(String),
+    /// matches any sequence of characters, including an empty one
+    Wildcard,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct LikePattern {
+    pub tokens: Vec<Token>,
+    pub case_insensitive: bool,
+}
+
+impl LikePattern {
+    /// parse a user value, `*` becomes a wildcard and everything else is literal
+    pub fn new(value: &str, case_insensitive: bool) -> Self {
+        let mut tokens = vec![];
+        let mut literal = String::new();
+        for ch in value.chars() {
+            if ch == WILDCARD {
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(literal));
+                    literal = String::new();
+                }
+                // consecutive wildcards are the same as one
+                if tokens.last() != Some(&Token::Wildcard) {
+                    tokens.push(Token::Wildcard);
+                }
+            } else {
+                literal.push(ch);
+            }
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        LikePattern {
+            tokens,
+            case_insensitive,
+        }
+    }
+
+    /// the pattern of a condition, `st` is turned into a case insensitive prefix match.
+    /// Returns None if the equality is not a pattern match or the operand has no text value
+    pub fn from_condition(equality: &Equality, right: &Operand) -> Option<Self> {
+        let value = operand_text(right)?;
+        match *equality {
+            Equality::LIKE => Some(LikePattern::new(&value, false)),
+            Equality::ILIKE => Some(LikePattern::new(&value, true)),
+            Equality::ST => {
+                let mut pattern = LikePattern::new(&value, true);
+                if pattern.tokens.last() != Some(&Token::Wildcard) {
+                    pattern.tokens.push(Token::Wildcard);
+                }
+                Some(pattern)
+            }
+            _ => None,
+        }
+    }
+
+    /// render into a sql LIKE pattern, escaped with `ESCAPE`
+    pub fn to_like(&self) -> String {
+        let mut like = String::new();
+        for token in &self.tokens {
+            match *token {
+                Token::Literal(ref literal) => {
+                    for ch in literal.chars() {
+                        if ch == '%' || ch == '_' || ch == ESCAPE {
+                            like.push(ESCAPE);
+                        }
+                        like.push(ch);
+                    }
+                }
+                Token::Wildcard => like.push('%'),
+            }
+        }
+        like
+    }
+
+    /// render into a regular expression, anchored on the ends which have no wildcard,
+    /// the caller is responsible for the case insensitive and dot-all flags
+    pub fn to_regex(&self) -> String {
+        let mut regex = String::new();
+        if self.tokens.first() != Some(&Token::Wildcard) {
+            regex.push('^');
+        }
+        for (i, token) in self.tokens.iter().enumerate() {
+            match *token {
+                Token::Literal(ref literal) => {
+                    for ch in literal.chars() {
+                        if "\\.+*?()|[]{}^$".contains(ch) {
+                            regex.push('\\');
+                        }
+                        regex.push(ch);
+                    }
+                }
+                Token::Wildcard => {
+                    if i > 0 && i + 1 < self.tokens.len() {
+                        regex.push_str(".*");
+                    }
+                }
+            }
+        }
+        if self.tokens.last() != Some(&Token::Wildcard) {
+            regex.push('$');
+        }
+        regex
+    }
+
+    /// render into an elasticsearch wildcard pattern, `?` and `*` are escaped with `\`
+    pub fn to_wildcard(&self) -> String {
+        let mut wildcard = String::new();
+        for token in &self.tokens {
+            match *token {
+                Token::Literal(ref literal) => {
+                    for ch in literal.chars() {
+                        if ch == '*' || ch == '?' || ch == '\\' {
+                            wildcard.push('\\');
+                        }
+                        wildcard.push(ch);
+                    }
+                }
+                Token::Wildcard => wildcard.push('*'),
+            }
+        }
+        wildcard
+    }
+
+    /// whether the whole text matches the pattern
+    pub fn matches(&self, text: &str) -> bool {
+        self.matches_with(text, &Collation::default())
+    }
+
+    /// whether the whole text matches the pattern once both are folded by the
+    /// collation, a case insensitive pattern folds the case in any collation
+    pub fn matches_with(&self, text: &str, collation: &Collation) -> bool {
+        let collation = Collation {
+            case_insensitive: collation.case_insensitive || self.case_insensitive,
+            ..collation.clone()
+        };
+        if collation == Collation::default() {
+            return matches_tokens(&self.tokens, text);
+        }
+        let tokens: Vec<Token> = self.tokens
+            .iter()
+            .map(|token| match *token {
+                Token::Literal(ref literal) => Token::Literal(collation.fold(literal)),
+                Token::Wildcard => Token::Wildcard,
+            })
+            .collect();
+        matches_tokens(&tokens, &collation.fold(text))
+    }
+
+    /// the literal text when the pattern has no wildcard at all
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.tokens.as_slice() {
+            [] => Some(""),
+            [Token::Literal(ref literal)] => Some(literal),
+            _ => None,
+        }
+    }
+}
+
+/// the literals have to appear in order, the first one is anchored at the start
+/// and the last one at the end unless there is a wildcard before or after them.
+/// Taking the leftmost occurence of the literals in between is always enough
+fn matches_tokens(tokens: &[Token], text: &str) -> bool {
+    let mut rest = text;
+    let mut after_wildcard = false;
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            Token::Wildcard => after_wildcard = true,
+            Token::Literal(ref literal) => {
+                let is_last = i + 1 == tokens.len();
+                if is_last {
+                    return if after_wildcard {
+                        rest.ends_with(literal.as_str())
+                    } else {
+                        rest == literal
+                    };
+                }
+                if after_wildcard {
+                    match rest.find(literal.as_str()) {
+                        Some(pos) => rest = &rest[pos + literal.len()..],
+                        None => return false,
+                    }
+                } else if rest.starts_with(literal.as_str()) {
+                    rest = &rest[literal.len()..];
+                } else {
+                    return false;
+                }
+                after_wildcard = false;
+            }
+        }
+    }
+    after_wildcard || rest.is_empty()
+}
+
+/// the text a pattern is made of, numbers and booleans are matched by their url form,
+/// a relative time not resolved beforehand by the clock of the system
+fn operand_text(operand: &Operand) -> Option<String> {
+    match *operand {
+        Operand::Value(ref v) => Some(v.to_string()),
+        Operand::Number(n) => Some(n.to_string()),
+        Operand::Boolean(b) => Some(b.to_string()),
+        Operand::Relative(ref time) => Some(time.resolve(&SystemClock)),
+        Operand::Column(_) | Operand::Function(_) | Operand::List(_) => None,
+    }
+}
+
+#[test]
+fn test_wildcard() {
+    let pattern = LikePattern::new("*° ͜ʖ ͡°*", true);
+    assert_eq!(pattern.tokens,
+               vec![Token::Wildcard, Token::Literal("° ͜ʖ ͡°".to_string()), Token::Wildcard]);
+    assert_eq!(pattern.to_like(), "%° ͜ʖ ͡°%");
+    assert_eq!(LikePattern::new("a**b", false).to_like(), "a%b");
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(LikePattern::new("100%", false).to_like(), "100\\%");
+    assert_eq!(LikePattern::new("user_id*", false).to_like(), "user\\_id%");
+    assert_eq!(LikePattern::new("C:\\temp", false).to_like(), "C:\\\\temp");
+    assert_eq!(LikePattern::new("100%", false).as_literal(), Some("100%"));
+    assert_eq!(LikePattern::new("1*", false).as_literal(), None);
+}
+
+#[test]
+fn test_regex() {
+    assert_eq!(LikePattern::new("*° ͜ʖ ͡°*", true).to_regex(), "° ͜ʖ ͡°");
+    assert_eq!(LikePattern::new("a.b*c", false).to_regex(), "^a\\.b.*c$");
+    assert_eq!(LikePattern::new("(1+1)*", false).to_regex(), "^\\(1\\+1\\)");
+    assert_eq!(LikePattern::new("", false).to_regex(), "^$");
+    assert_eq!(LikePattern::new("*", false).to_regex(), "");
+}
+
+#[test]
+fn test_wildcard_pattern() {
+    assert_eq!(LikePattern::new("why?*", false).to_wildcard(), "why\\?*");
+    assert_eq!(LikePattern::new("C:\\temp", false).to_wildcard(), "C:\\\\temp");
+}
+
+#[test]
+fn test_matches() {
+    assert!(LikePattern::new("*° ͜ʖ ͡°*", true).matches("hey ° ͜ʖ ͡° there"));
+    assert!(LikePattern::new("Jo*", false).matches("John"));
+    assert!(!LikePattern::new("Jo*", false).matches("john"));
+    assert!(LikePattern::new("Jo*", true).matches("john"));
+    assert!(LikePattern::new("*a*b", false).matches("xaxbab"));
+    assert!(!LikePattern::new("*a*b", false).matches("xaxba"));
+    assert!(LikePattern::new("a*a", false).matches("aa"));
+    assert!(!LikePattern::new("a*a", false).matches("a"));
+    assert!(LikePattern::new("100%", false).matches("100%"));
+    assert!(!LikePattern::new("100%", false).matches("1000"));
+    assert!(LikePattern::new("", false).matches(""));
+    assert!(LikePattern::new("*", false).matches(""));
+    // full case folding
+    assert!(LikePattern::new("*STRASSE", true).matches("Hauptstraße"));
+}
+
+#[test]
+fn test_matches_with() {
+    let insensitive = Collation::insensitive();
+    assert!(LikePattern::new("cafe*", false).matches_with("Café noir", &insensitive));
+    assert!(!LikePattern::new("cafe*", false).matches("Café noir"));
+    let nfc = Collation { normalization: Some(::collation::Normalization::Nfc), ..Default::default() };
+    assert!(LikePattern::new("*caf\u{e9}", false).matches_with("cafe\u{301}", &nfc));
+    assert!(!LikePattern::new("*caf\u{e9}", false).matches("cafe\u{301}"));
+}
+
+#[test]
+fn test_from_condition() {
+    let st = LikePattern::from_condition(&Equality::ST, &Operand::Value("John_".to_string()));
+    assert_eq!(st.map(|p| (p.to_like(), p.case_insensitive)),
+               Some(("John\\_%".to_string(), true)));
+
+    let st = LikePattern::from_condition(&Equality::ST, &Operand::Value("Jo*".to_string()));
+    assert_eq!(st.map(|p| p.to_like()), Some("Jo%".to_string()));
+
+    let like = LikePattern::from_condition(&Equality::LIKE, &Operand::Number(134f64));
+    assert_eq!(like.map(|p| (p.to_like(), p.case_insensitive)),
+               Some(("134".to_string(), false)));
+
+    assert_eq!(LikePattern::from_condition(&Equality::EQ, &Operand::Value("a".to_string())),
+               None);
+}
@@ -0,0 +1,245 @@
+//! Caps on the size of a query, so a single query string can not exhaust the
+//! parser or the database
+//!
+//! The input is scanned for the nesting depth, the number of conditions, the
+//! length of the lists and of the values before it is parsed, so nothing is
+//! allocated and no parser recurses for a query which is too large. The number
+//! of joins, the page size and the offset are checked on the parsed query.
+
+use std::fmt;
+
+use {Query, Range};
+
+/// the operators of a condition, `=` comes before and `.` after them
+const OPERATORS: [&str; 19] = ["neq", "eq", "lte", "lt", "gte", "gt", "not_in", "in", "is_not", "is", "like",
+                               "ilike", "st", "fts", "plfts", "phfts", "wfts", "match", "imatch"];
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Limits {
+    /// the levels of parentheses
+    pub max_depth: usize,
+    /// the conditions of the filters and having filters together
+    pub max_conditions: usize,
+    /// the items of a comma separated list: `in` values, `order_by`, `group_by`
+    pub max_list_length: usize,
+    /// the bytes of a name or a value
+    pub max_value_length: usize,
+    pub max_joins: usize,
+    /// the largest `limit` or `page_size`
+    pub max_page_size: usize,
+    /// the most rows skipped, by `offset` or by the `page` number
+    pub max_offset: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 8,
+            max_conditions: 64,
+            max_list_length: 256,
+            max_value_length: 1024,
+            max_joins: 4,
+            max_page_size: 1000,
+            max_offset: 100000,
+        }
+    }
+}
+
+impl Limits {
+    /// no limit at all
+    pub fn unlimited() -> Self {
+        Limits {
+            max_depth: usize::MAX,
+            max_conditions: usize::MAX,
+            max_list_length: usize::MAX,
+            max_value_length: usize::MAX,
+            max_joins: usize::MAX,
+            max_page_size: usize::MAX,
+            max_offset: usize::MAX,
+        }
+    }
+}
+
+/// the limit which is exceeded, with its value
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum LimitError {
+    Depth(usize),
+    Conditions(usize),
+    ListLength(usize),
+    ValueLength(usize),
+    Joins(usize),
+    PageSize(usize),
+    Offset(usize),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitError::Depth(max) => write!(f, "more than {} levels of parentheses", max),
+            LimitError::Conditions(max) => write!(f, "more than {} conditions", max),
+            LimitError::ListLength(max) => write!(f, "a list of more than {} items", max),
+            LimitError::ValueLength(max) => write!(f, "a value of more than {} bytes", max),
+            LimitError::Joins(max) => write!(f, "more than {} joins", max),
+            LimitError::PageSize(max) => write!(f, "a page of more than {} rows", max),
+            LimitError::Offset(max) => write!(f, "an offset of more than {} rows", max),
+        }
+    }
+}
+
+impl ::std::error::Error for LimitError {}
+
+/// check a query string before it is parsed
+pub fn check_input(input: &str, limits: &Limits) -> Result<(), LimitError> {
+    let mut depth = 0;
+    let mut conditions = 0;
+    for term in input.split(['&', '|']) {
+        for c in term.bytes() {
+            match c {
+                b'(' => {
+                    depth += 1;
+                    if depth > limits.max_depth {
+                        return Err(LimitError::Depth(limits.max_depth));
+                    }
+                }
+                b')' => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+        }
+        if is_condition(term) {
+            conditions += 1;
+            if conditions > limits.max_conditions {
+                return Err(LimitError::Conditions(limits.max_conditions));
+            }
+        }
+        if term.split(',').count() > limits.max_list_length {
+            return Err(LimitError::ListLength(limits.max_list_length));
+        }
+        let order = term.starts_with("order_by=");
+        let mut start = 0;
+        for piece in term.split(['(', ')', ',', '=']) {
+            let before = if start > 0 { Some(term.as_bytes()[start - 1]) } else { None };
+            start += piece.len() + 1;
+            if bare(piece, before, order).len() > limits.max_value_length {
+                return Err(LimitError::ValueLength(limits.max_value_length));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// a name or a value without what the syntax adds to it: the operator in front
+/// of a value, `eq.`, the `.` after the language of a search, `fts(english).`,
+/// and the direction and the nulls of an order, `.desc.nulls_last`
+fn bare(piece: &str, before: Option<u8>, order: bool) -> &str {
+    let mut piece = match before {
+        Some(b'=') => OPERATORS.iter()
+            .find_map(|operator| piece.strip_prefix(operator).and_then(|rest| rest.strip_prefix('.')))
+            .unwrap_or(piece),
+        Some(b')') => piece.strip_prefix('.').unwrap_or(piece),
+        _ => piece,
+    };
+    if order {
+        while let Some(rest) = [".asc", ".desc", ".nulls_first", ".nulls_last"]
+            .iter()
+            .find_map(|suffix| piece.strip_suffix(suffix)) {
+            piece = rest;
+        }
+    }
+    piece
+}
+
+/// a term has a condition when one of its `=` is followed by an operator and a `.`
+fn is_condition(term: &str) -> bool {
+    term.match_indices('=').any(|(i, _)| {
+        let rest = &term[i + 1..];
+        // a full text search can have a language, `fts(english).`
+        OPERATORS.iter().any(|operator| {
+            rest.starts_with(operator) && (rest[operator.len()..].starts_with('.') || rest[operator.len()..].starts_with('('))
+        })
+    })
+}
+
+/// check the parts of a parsed query the input scan can not see
+pub fn check_query(query: &Query, limits: &Limits) -> Result<(), LimitError> {
+    if query.join.len() > limits.max_joins {
+        return Err(LimitError::Joins(limits.max_joins));
+    }
+    let page_size = match query.range {
+        Some(Range::Limit(ref limit)) => limit.limit,
+        Some(Range::Page(ref page)) => page.page_size,
+        // a cursor without a limit would be every row after it
+        Some(Range::Keyset(ref keyset)) => keyset.limit.unwrap_or(i64::MAX),
+        None => 0,
+    };
+    if page_size.max(0) as u64 > limits.max_page_size as u64 {
+        return Err(LimitError::PageSize(limits.max_page_size));
+    }
+    // a keyset skips nothing, its cursor is a filter
+    let offset = query.range.as_ref().and_then(Range::bounds).map_or(0, |bounds| bounds.0);
+    if offset > limits.max_offset {
+        return Err(LimitError::Offset(limits.max_offset));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn small() -> Limits {
+    Limits {
+        max_depth: 2,
+        max_conditions: 3,
+        max_list_length: 3,
+        max_value_length: 10,
+        max_joins: 0,
+        max_page_size: 50,
+        max_offset: 100,
+    }
+}
+
+#[test]
+fn test_check_input() {
+    let check = |input: &str| check_input(input, &small());
+    assert_eq!(check("a=eq.1&(b=eq.2|(c=in.1,2,3))&order_by=a,b&limit=10"), Ok(()));
+    assert_eq!(check("((((a=eq.1))))"), Err(LimitError::Depth(2)));
+    assert_eq!(check("a=eq.1&b=eq.2&c=eq.3&having=count(*)=gt.1"), Err(LimitError::Conditions(3)));
+    assert_eq!(check("a=in.1,2,3,4"), Err(LimitError::ListLength(3)));
+    assert_eq!(check("order_by=a,b,c,d"), Err(LimitError::ListLength(3)));
+    // the operator, the language and the order are not part of the value
+    assert_eq!(check("name=eq.abcdefghij"), Ok(()));
+    assert_eq!(check("name=eq.abcdefghijk"), Err(LimitError::ValueLength(10)));
+    assert_eq!(check("name=fts(english).abcdefghij|a=in.abcdefghij,1"), Ok(()));
+    assert_eq!(check("order_by=abcdefghij.desc.nulls_last"), Ok(()));
+    assert_eq!(check("order_by=abcdefghij.descending"), Err(LimitError::ValueLength(10)));
+    assert_eq!(check("abcdefghijk=eq.1"), Err(LimitError::ValueLength(10)));
+    // equations and ranges are not conditions
+    assert_eq!(check("a=eq.1&b=eq.2&c=eq.3&format=csv&limit=10&order_by=a.desc"), Ok(()));
+}
+
+#[test]
+fn test_parse_with_limits() {
+    use ParseError;
+    assert_eq!(::parse_query_with_limits("a=eq.1&limit=100", &small()),
+               Err(ParseError::Limit(LimitError::PageSize(50))));
+    assert_eq!(::parse_query_with_limits("page_size=9999999", &small()),
+               Err(ParseError::Limit(LimitError::PageSize(50))));
+    assert_eq!(::parse_query_with_limits("a=in.1,2,3,4", &small()),
+               Err(ParseError::Limit(LimitError::ListLength(3))));
+    assert_eq!(::parse_query_with_limits("page=2&page_size=50", &small()),
+               ::parse_query("page=2&page_size=50"));
+    assert_eq!(::parse_query_with_limits("order_by=id&after=abc.def", &small()),
+               Err(ParseError::Limit(LimitError::PageSize(50))));
+    assert!(::parse_query_with_limits("order_by=id&after=abc.def&limit=50", &small()).is_ok());
+    assert_eq!(::parse_query_with_limits("limit=10&offset=101", &small()),
+               Err(ParseError::Limit(LimitError::Offset(100))));
+    assert_eq!(::parse_query_with_limits("page=4&page_size=50", &small()),
+               Err(ParseError::Limit(LimitError::Offset(100))));
+    assert_eq!(::parse_query_with_limits("page=3&page_size=50", &small()),
+               ::parse_query("page=3&page_size=50"));
+    // deep enough to overflow the stack of the parser
+    let deep = format!("{}a=eq.1{}", "(".repeat(100000), ")".repeat(100000));
+    assert_eq!(::parse_query_with_limits(&deep, &Limits::default()),
+               Err(ParseError::Limit(LimitError::Depth(8))));
+}
@@ -0,0 +1,217 @@
+//! Layering the query of a client over the defaults of an endpoint
+//!
+//! The base query holds what the endpoint wants whatever the client asks: its
+//! table, a baseline filter, an order and a page size. The filters and having
+//! filters of both are AND-ed together, so the client can only narrow the
+//! rows down, and the page size of the base is also the largest the client
+//! gets. Where the two queries disagree and one side has to win, the merge
+//! says so in its conflicts rather than dropping one side silently.
+
+use std::fmt;
+
+use {Equation, Operand, Order, Query, Range};
+
+/// how the order of the overlay is combined with the order of the base
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum OrderMerge {
+    /// the order of the overlay when it has one, the order of the base otherwise
+    Replace,
+    /// the order of the base, then the orders of the overlay on other operands
+    Append,
+}
+
+/// how the equations of the overlay are combined with those of the base
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum EquationMerge {
+    /// an equation of the overlay replaces the one of the base with the same left side
+    Override,
+    /// the equation of the base is kept, a different one in the overlay is a conflict
+    Keep,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct MergePolicy {
+    pub order: OrderMerge,
+    pub equations: EquationMerge,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy {
+            order: OrderMerge::Replace,
+            equations: EquationMerge::Override,
+        }
+    }
+}
+
+/// where the overlay asked for something the base does not allow, the base wins
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Conflict {
+    /// the overlay selects from other tables
+    From(Vec<Operand>),
+    /// the overlay groups by other operands
+    GroupBy(Vec<Operand>),
+    /// the overlay orders by an operand of the order of the base, another way
+    Order(Order),
+    /// the overlay asked for more rows a page than the base allows, which it got instead
+    PageSize { requested: usize, allowed: usize },
+    /// the overlay has another value for an equation of the base which is kept
+    Equation(Equation),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = |operands: &[Operand]| operands.iter().map(Operand::to_string).collect::<Vec<_>>().join(",");
+        match *self {
+            Conflict::From(ref from) => write!(f, "from={} is not allowed", list(from)),
+            Conflict::GroupBy(ref group_by) => write!(f, "group_by={} is not allowed", list(group_by)),
+            Conflict::Order(ref order) => write!(f, "order_by={} is not allowed", order),
+            Conflict::PageSize { requested, allowed } => {
+                write!(f, "a page of {} rows is more than the {} allowed", requested, allowed)
+            }
+            Conflict::Equation(ref equation) => {
+                write!(f, "{}={} is not allowed", equation.left, equation.right)
+            }
+        }
+    }
+}
+
+/// the merged query, and what the overlay did not get its way on
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Merged {
+    pub query: Query,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Query {
+    /// the overlay on top of the base:
+    /// - the filters and the having filters are AND-ed
+    /// - `from` and `group_by` are those of the base when it has them
+    /// - the joins of the overlay come after those of the base
+    /// - the order and the equations follow the policy
+    /// - the range is that of the overlay, with a page no larger than the range
+    ///   of the base, or that of the base when the overlay has none
+    pub fn merge(base: &Query, overlay: &Query, policy: MergePolicy) -> Merged {
+        let mut conflicts = vec![];
+        let mut query = base.clone();
+
+        if query.from.is_empty() {
+            query.from = overlay.from.clone();
+        } else if !overlay.from.is_empty() && overlay.from != base.from {
+            conflicts.push(Conflict::From(overlay.from.clone()));
+        }
+        for join in &overlay.join {
+            if !query.join.contains(join) {
+                query.join.push(join.clone());
+            }
+        }
+        query.filters.extend(overlay.filters.iter().cloned());
+        query.having.extend(overlay.having.iter().cloned());
+        if query.group_by.is_empty() {
+            query.group_by = overlay.group_by.clone();
+        } else if !overlay.group_by.is_empty() && overlay.group_by != base.group_by {
+            conflicts.push(Conflict::GroupBy(overlay.group_by.clone()));
+        }
+
+        match policy.order {
+            OrderMerge::Replace if !overlay.order_by.is_empty() => query.order_by = overlay.order_by.clone(),
+            OrderMerge::Replace => (),
+            OrderMerge::Append => {
+                for order in &overlay.order_by {
+                    match base.order_by.iter().find(|o| o.operand == order.operand) {
+                        Some(o) if o != order => conflicts.push(Conflict::Order(order.clone())),
+                        Some(_) => (),
+                        None => query.order_by.push(order.clone()),
+                    }
+                }
+            }
+        }
+
+        query.range = match (&base.range, &overlay.range) {
+            (Some(range), Some(requested)) => {
+                let (allowed, requested_size) = (range.size(), requested.size());
+                if requested_size > allowed {
+                    conflicts.push(Conflict::PageSize { requested: requested_size, allowed });
+                }
+                Some(clamp(requested, allowed))
+            }
+            (_, Some(requested)) => Some(requested.clone()),
+            (range, None) => range.clone(),
+        };
+
+        for equation in &overlay.equations {
+            match query.equations.iter().position(|e| e.left == equation.left) {
+                Some(i) if query.equations[i] == *equation => (),
+                Some(i) => match policy.equations {
+                    EquationMerge::Override => query.equations[i] = equation.clone(),
+                    EquationMerge::Keep => conflicts.push(Conflict::Equation(equation.clone())),
+                },
+                None => query.equations.push(equation.clone()),
+            }
+        }
+
+        Merged { query, conflicts }
+    }
+}
+
+/// the range with pages of at most `size` rows
+fn clamp(range: &Range, size: usize) -> Range {
+    let size = size.min(i64::MAX as usize) as i64;
+    let mut range = range.clone();
+    match range {
+        Range::Page(ref mut page) => page.page_size = page.page_size.min(size),
+        Range::Limit(ref mut limit) => limit.limit = limit.limit.min(size),
+        Range::Keyset(ref mut keyset) => keyset.limit = Some(keyset.limit.map_or(size, |limit| limit.min(size))),
+    }
+    range
+}
+
+#[cfg(test)]
+fn merged(base: &str, overlay: &str, policy: MergePolicy) -> Merged {
+    Query::merge(&::parse_query(base).unwrap(), &::parse_query(overlay).unwrap(), policy)
+}
+
+#[test]
+fn test_merge() {
+    let base = "deleted=is.null&from=users&order_by=name&limit=50&format=json";
+    let merged = merged(base, "age=lt.20&order_by=age.desc&limit=10&offset=20", MergePolicy::default());
+    // the filter of the base and the one of the client stay apart
+    let mut expected = ::parse_query("deleted=is.null&from=users&order_by=age.desc&limit=10&offset=20&format=json")
+        .unwrap();
+    expected.filters.push(::parse_filter("age=lt.20"));
+    assert_eq!(merged.query, expected);
+    assert_eq!(merged.conflicts, vec![]);
+    let merged = self::merged(base, "", MergePolicy::default());
+    assert_eq!(merged.query, ::parse_query(base).unwrap());
+}
+
+#[test]
+fn test_merge_conflicts() {
+    let base = "from=users&group_by=dept&order_by=name&page=1&page_size=50&format=json";
+    let policy = MergePolicy {
+        order: OrderMerge::Append,
+        equations: EquationMerge::Keep,
+    };
+    let merged = merged(base, "from=orders&group_by=age&order_by=name.desc,age&page=3&page_size=500&format=csv", policy);
+    assert_eq!(merged.query,
+               ::parse_query("from=users&group_by=dept&order_by=name,age&page=3&page_size=50&format=json").unwrap());
+    assert_eq!(merged.conflicts.len(), 5);
+    assert_eq!(merged.conflicts[3], Conflict::PageSize { requested: 500, allowed: 50 });
+    assert_eq!(merged.conflicts[4].to_string(), "format=csv is not allowed");
+    let merged = self::merged(base, "format=csv", MergePolicy::default());
+    assert_eq!(merged.query.equations, ::parse_query("format=csv").unwrap().equations);
+    assert_eq!(merged.conflicts, vec![]);
+}
@@ -0,0 +1,292 @@
+//! Translate a `Query` into a MongoDB find command
+//!
+//! ```json
+//! {"filter": {"age": {"$lt": 20}}, "sort": {"name": -1}, "skip": 10, "limit": 10}
+//! ```
+//! Only the filters, order and range are translated, grouping and joins
+//! need an aggregation pipeline and are reported as unsupported.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use expr::Expr;
+use json;
+use like::LikePattern;
+use {Condition, Direction, Equality, Filter, Operand, Order, Query, Range};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Error {
+    /// the part of the query which has no equivalent in a find command
+    Unsupported(String),
+    /// an operand which can not be used at this position, ie: a function
+    InvalidOperand(Operand),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Unsupported(ref what) => write!(f, "{} is not supported in mongodb", what),
+            Error::InvalidOperand(ref op) => write!(f, "invalid operand: {:?}", op),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// the find command of a query: `filter`, `sort`, `skip` and `limit`
+pub fn to_mongo(query: &Query) -> Result<Value, Error> {
+    if !query.join.is_empty() {
+        return Err(Error::Unsupported("join".to_string()));
+    }
+    if !query.group_by.is_empty() || !query.having.is_empty() {
+        return Err(Error::Unsupported("group_by".to_string()));
+    }
+    let mut command = Map::new();
+    command.insert("filter".to_string(), filter_document(&query.filters)?);
+    if !query.order_by.is_empty() {
+        command.insert("sort".to_string(), sort_document(&query.order_by)?);
+    }
+    match query.range {
+        Some(Range::Page(ref page)) => {
+            let skip = page.offset().ok_or_else(|| Error::Unsupported("an offset past i64".to_string()))?;
+            command.insert("skip".to_string(), Value::from(skip));
+            command.insert("limit".to_string(), Value::from(page.page_size));
+        }
+        Some(Range::Limit(ref limit)) => {
+            if let Some(offset) = limit.offset {
+                command.insert("skip".to_string(), Value::from(offset));
+            }
+            command.insert("limit".to_string(), Value::from(limit.limit));
+        }
+        Some(Range::Keyset(_)) => return Err(Error::Unsupported("an unresolved cursor".to_string())),
+        None => (),
+    }
+    Ok(Value::Object(command))
+}
+
+/// the query filter document, the filters are all AND-ed together
+pub fn filter_document(filters: &[Filter]) -> Result<Value, Error> {
+    match Expr::from_filters(filters) {
+        Some(expr) => expr_document(&expr),
+        None => Ok(Value::Object(Map::new())),
+    }
+}
+
+/// the sort document, MongoDB always puts nulls first in ascending order
+/// so `nulls_where` can not be honoured and is ignored
+pub fn sort_document(order_by: &[Order]) -> Result<Value, Error> {
+    let mut sort = Map::new();
+    for order in order_by {
+        let field = field_name(&order.operand)?;
+        let direction = match order.direction {
+            Some(Direction::DESC) => -1,
+            Some(Direction::ASC) | None => 1,
+        };
+        sort.insert(field, Value::from(direction));
+    }
+    Ok(Value::Object(sort))
+}
+
+fn expr_document(expr: &Expr) -> Result<Value, Error> {
+    match *expr {
+        Expr::Condition(ref condition) => condition_document(condition),
+        Expr::And(ref exprs) => logical("$and", exprs),
+        Expr::Or(ref exprs) => logical("$or", exprs),
+    }
+}
+
+fn logical(operator: &str, exprs: &[Expr]) -> Result<Value, Error> {
+    let mut documents = vec![];
+    for expr in exprs {
+        documents.push(expr_document(expr)?);
+    }
+    Ok(single(operator, Value::Array(documents)))
+}
+
+fn condition_document(condition: &Condition) -> Result<Value, Error> {
+    let field = field_name(&condition.left)?;
+    let right = &condition.right;
+    let predicate = match condition.equality {
+        Equality::EQ => single("$eq", json_value(right)?),
+        Equality::NEQ => single("$ne", json_value(right)?),
+        Equality::LT => single("$lt", json_value(right)?),
+        Equality::LTE => single("$lte", json_value(right)?),
+        Equality::GT => single("$gt", json_value(right)?),
+        Equality::GTE => single("$gte", json_value(right)?),
+        Equality::IN => single("$in", json_list(right)?),
+        Equality::NOT_IN => single("$nin", json_list(right)?),
+        // a missing field is the document equivalent of a sql NULL,
+        // `{"$eq": null}` matches both
+        Equality::IS => single("$eq", is_value(right)?),
+        Equality::IS_NOT => {
+            match is_value(right)? {
+                Value::Null => {
+                    let mut predicate = Map::new();
+                    predicate.insert("$exists".to_string(), Value::Bool(true));
+                    predicate.insert("$ne".to_string(), Value::Null);
+                    Value::Object(predicate)
+                }
+                value => single("$ne", value),
+            }
+        }
+        Equality::LIKE | Equality::ILIKE | Equality::ST => {
+            let pattern = LikePattern::from_condition(&condition.equality, right)
+                .ok_or_else(|| Error::InvalidOperand(right.clone()))?;
+            let mut predicate = Map::new();
+            predicate.insert("$regex".to_string(), Value::String(pattern.to_regex()));
+            let options = if pattern.case_insensitive { "is" } else { "s" };
+            predicate.insert("$options".to_string(), Value::String(options.to_string()));
+            Value::Object(predicate)
+        }
+        Equality::MATCH | Equality::IMATCH => {
+            let mut predicate = Map::new();
+            predicate.insert("$regex".to_string(), json_value(right)?);
+            if condition.equality == Equality::IMATCH {
+                predicate.insert("$options".to_string(), Value::String("i".to_string()));
+            }
+            Value::Object(predicate)
+        }
+        // `$text` searches the text index of the collection, not a field
+        Equality::FTS(_) | Equality::PLFTS(_) | Equality::PHFTS(_) | Equality::WFTS(_) => {
+            return Err(Error::Unsupported("full text search".to_string()));
+        }
+    };
+    Ok(single(&field, predicate))
+}
+
+fn single(key: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(key.to_string(), value);
+    Value::Object(map)
+}
+
+fn field_name(operand: &Operand) -> Result<String, Error> {
+    match *operand {
+        Operand::Column(ref column) => Ok(column.to_string()),
+        _ => Err(Error::InvalidOperand(operand.clone())),
+    }
+}
+
+fn json_value(operand: &Operand) -> Result<Value, Error> {
+    json::from_operand(operand).ok_or_else(|| Error::InvalidOperand(operand.clone()))
+}
+
+fn json_list(operand: &Operand) -> Result<Value, Error> {
+    let mut values = vec![];
+    for item in operand.list_items() {
+        values.push(json_value(&item)?);
+    }
+    Ok(Value::Array(values))
+}
+
+fn is_value(operand: &Operand) -> Result<Value, Error> {
+    json::is_operand(operand).ok_or_else(|| Error::InvalidOperand(operand.clone()))
+}
+
+#[test]
+fn test_mongo_comparison() {
+    let query = Query {
+        filters: vec![::parse_filter("age=lt.20&product=eq.134&price=lte.100.5")],
+        ..Default::default()
+    };
+    ::assert_golden("mongo/comparison", &to_mongo(&query).unwrap());
+}
+
+#[test]
+fn test_mongo_or() {
+    let query = Query {
+        filters: vec![::parse_filter("product=eq.134|price=lt.100.0&active=eq.true")],
+        ..Default::default()
+    };
+    ::assert_golden("mongo/or", &to_mongo(&query).unwrap());
+}
+
+#[test]
+fn test_mongo_patterns() {
+    let query = Query {
+        filters: vec![::parse_filter("name=st.John"),
+                      ::parse_filter("title=like.*1+1*"),
+                      ::parse_filter("nick=ilike.*° ͜ʖ ͡°*")],
+        ..Default::default()
+    };
+    ::assert_golden("mongo/patterns", &to_mongo(&query).unwrap());
+}
+
+#[test]
+fn test_mongo_regular_expressions() {
+    let query = ::parse_query("name=match.^Jo(hn|e)$|nick=imatch.bob").unwrap();
+    ::assert_golden("mongo/regular_expressions", &to_mongo(&query).unwrap());
+}
+
+#[test]
+fn test_mongo_relative_times() {
+    use relative::{self, FixedClock};
+    let query = ::parse_query("created_at=gt.now-7d&due=in.@today,@today+1w").unwrap();
+    // 2024-01-31T13:45:00Z
+    ::assert_golden("mongo/relative_times", &to_mongo(&relative::resolve(&query, &FixedClock(1706708700))).unwrap());
+}
+
+#[test]
+fn test_mongo_in_and_is() {
+    let query = Query {
+        filters: vec![::parse_filter("tag=in.red,green&status=not_in.1,2"),
+                      ::parse_filter("deleted_at=is.null&owner=is_not.null&active=is_not.true")],
+        ..Default::default()
+    };
+    ::assert_golden("mongo/in_and_is", &to_mongo(&query).unwrap());
+}
+
+#[test]
+fn test_mongo_sort_and_range() {
+    use {Limit, Page};
+    let mut query = Query {
+        order_by: vec![Order {
+                           operand: Operand::Column("name".to_string()),
+                           direction: Some(Direction::DESC),
+                           nulls_where: None,
+                       },
+                       Order {
+                           operand: Operand::Column("age".to_string()),
+                           direction: None,
+                           nulls_where: None,
+                       }],
+        range: Some(Range::Page(Page {
+            page: 3,
+            page_size: 20,
+        })),
+        ..Default::default()
+    };
+    ::assert_golden("mongo/sort_page", &to_mongo(&query).unwrap());
+
+    query.order_by.clear();
+    query.range = Some(Range::Limit(Limit {
+        limit: 10,
+        offset: Some(5),
+    }));
+    ::assert_golden("mongo/limit_offset", &to_mongo(&query).unwrap());
+}
+
+#[test]
+fn test_mongo_unsupported() {
+    let query = Query {
+        group_by: vec![Operand::Column("age".to_string())],
+        ..Default::default()
+    };
+    assert_eq!(to_mongo(&query), Err(Error::Unsupported("group_by".to_string())));
+    let query = ::parse_query("body=fts.fat&cat").unwrap();
+    assert_eq!(to_mongo(&query), Err(Error::Unsupported("full text search".to_string())));
+    let query = ::parse_query("order_by=id&after=abc.def").unwrap();
+    assert_eq!(to_mongo(&query), Err(Error::Unsupported("an unresolved cursor".to_string())));
+    let query = Query::default().page(i64::MAX, 10);
+    assert_eq!(to_mongo(&query), Err(Error::Unsupported("an offset past i64".to_string())));
+
+    let query = Query {
+        filters: vec![::parse_filter("deleted_at=is.yesterday")],
+        ..Default::default()
+    };
+    assert_eq!(to_mongo(&query),
+               Err(Error::InvalidOperand(Operand::Value("yesterday".to_string()))));
+}
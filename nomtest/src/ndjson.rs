@@ -0,0 +1,187 @@
+//! Records read from newline delimited json or from a json array
+//!
+//! The input is read one record at a time, a file much larger than the memory
+//! can be filtered as long as the records are not all kept. A record is a json
+//! object, an input which starts with `[` is an array of them, otherwise the
+//! objects follow each other separated by whitespace, one per line.
+
+use std::fmt;
+use std::io::{self, BufRead};
+
+use serde_json::{self, Value};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// the record at this index is not valid json
+    Json(usize, serde_json::Error),
+    /// the record at this index is not an object
+    NotAnObject(usize),
+    /// the input ends before the closing `]` of the array
+    UnterminatedArray,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Json(index, ref e) => write!(f, "record {}: {}", index + 1, e),
+            Error::NotAnObject(index) => write!(f, "record {}: not a json object", index + 1),
+            Error::UnterminatedArray => write!(f, "the json array is not closed"),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+enum State {
+    Start,
+    Array,
+    Lines,
+    Done,
+}
+
+/// the records of a reader, the iteration ends at the first error
+pub struct Records<R> {
+    reader: R,
+    state: State,
+    count: usize,
+}
+
+pub fn read_json<R: BufRead>(reader: R) -> Records<R> {
+    Records {
+        reader,
+        state: State::Start,
+        count: 0,
+    }
+}
+
+impl<R: BufRead> Records<R> {
+    /// the next byte which is not whitespace, it is left in the reader
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            let (skipped, next) = {
+                let buf = self.reader.fill_buf()?;
+                if buf.is_empty() {
+                    return Ok(None);
+                }
+                let skipped = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+                (skipped, buf.get(skipped).cloned())
+            };
+            self.reader.consume(skipped);
+            if next.is_some() {
+                return Ok(next);
+            }
+        }
+    }
+
+    /// an object is read up to its closing `}`, nothing after it is consumed
+    fn read_record(&mut self) -> Result<Value, Error> {
+        let index = self.count;
+        let record = serde_json::Deserializer::from_reader(&mut self.reader)
+            .into_iter::<Value>()
+            .next();
+        match record {
+            Some(Ok(record)) => {
+                if record.is_object() {
+                    self.count += 1;
+                    Ok(record)
+                } else {
+                    Err(Error::NotAnObject(index))
+                }
+            }
+            Some(Err(e)) => Err(Error::Json(index, e)),
+            None => Err(Error::UnterminatedArray),
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<Value>, Error> {
+        loop {
+            let next = self.peek()?;
+            match (self.state, next) {
+                (State::Done, _) => return Ok(None),
+                (State::Start, Some(b'[')) => {
+                    self.reader.consume(1);
+                    self.state = State::Array;
+                }
+                (State::Start, _) => self.state = State::Lines,
+                (State::Array, None) => return Err(Error::UnterminatedArray),
+                (State::Array, Some(b']')) => {
+                    self.reader.consume(1);
+                    self.state = State::Done;
+                }
+                (State::Array, Some(b',')) if self.count > 0 => self.reader.consume(1),
+                // anything but an object could read past the separator after it
+                (State::Array, Some(b'{')) => return self.read_record().map(Some),
+                (State::Array, Some(_)) => return Err(Error::NotAnObject(self.count)),
+                (State::Lines, None) => return Ok(None),
+                (State::Lines, Some(_)) => return self.read_record().map(Some),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = Result<Value, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => {
+                self.state = State::Done;
+                None
+            }
+            Err(e) => {
+                self.state = State::Done;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn names(input: &str) -> Vec<String> {
+    read_json(input.as_bytes())
+        .map(|record| record.unwrap()["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_read_json() {
+    assert_eq!(names("{\"name\": \"John\"}\n{\"name\": \"技術通報\"}\n"), vec!["John", "技術通報"]);
+    assert_eq!(names(" [ {\"name\": \"John\"} ,\n {\"name\": \"Jane\", \"tags\": [1, 2]} ] "),
+               vec!["John", "Jane"]);
+    assert_eq!(names("[]"), Vec::<String>::new());
+    assert_eq!(names(""), Vec::<String>::new());
+}
+
+#[test]
+fn test_read_json_errors() {
+    let errors = |input: &str| -> Vec<String> {
+        read_json(input.as_bytes()).filter_map(|r| r.err()).map(|e| e.to_string()).collect()
+    };
+    assert_eq!(errors("[{\"a\": 1}, 2]"), vec!["record 2: not a json object"]);
+    assert_eq!(errors("{\"a\": 1}\n[1]\n{\"a\": 2}"), vec!["record 2: not a json object"]);
+    assert_eq!(errors("[{\"a\": 1}"), vec!["the json array is not closed"]);
+    assert_eq!(errors("[,{\"a\": 1}]"), vec!["record 1: not a json object"]);
+    assert_eq!(errors("{\"a\": }").len(), 1);
+}
+
+#[test]
+fn test_error_ends_records() {
+    let mut records = read_json("{\"a\": 1}\n{\"a\": 2}\nnot json\n{\"a\": 3}".as_bytes());
+    assert!(records.next().unwrap().is_ok());
+    assert!(records.next().unwrap().is_ok());
+    assert!(records.next().unwrap().is_err());
+    assert!(records.next().is_none());
+}
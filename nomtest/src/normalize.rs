@@ -0,0 +1,258 @@
+//! A canonical form of a query, and a fingerprint of it
+//!
+//! Two queries which only differ in the order of the children of an AND or an
+//! OR, in duplicated conditions or in the way a literal is written normalize to
+//! the same query: `b=eq.2&a=eq.1&a=eq.1` and `a=eq.1&b=eq.2` are one query, so
+//! are `id=in.3,1,1` and `id=in.1,3`. The fingerprint is a hash of the
+//! normalized query, for caching results and for grouping queries in metrics.
+
+use std::fmt;
+
+use expr::Expr;
+use {Condition, Equality, Equation, Filter, Function, Join, JoinType, Limit, Modifier, Operand, Query, Range};
+
+const FNV_OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013B;
+
+/// a 128 bit FNV-1a hash of the normalized query, the same for every version of the crate
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Eq)]
+#[derive(Hash)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Fingerprint(pub u128);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl Query {
+    /// the fingerprint of the normalized query
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint(fnv1a(&canonical_text(&normalize(self))))
+    }
+
+    /// the fingerprint of the query with every literal replaced by `?`, the same
+    /// for `age=lt.20&limit=10` and `age=lt.30&limit=5`
+    pub fn masked_fingerprint(&self) -> Fingerprint {
+        Fingerprint(fnv1a(&canonical_text(&normalize(&mask(self)))))
+    }
+}
+
+/// the query with the children of the ANDs and ORs sorted and without duplicates,
+/// the nested ANDs and ORs flattened and the literals written one way only. The
+/// filters become a single filter, the having filters too, the order of `order_by`,
+/// `group_by` and `from` is kept as it matters
+pub fn normalize(query: &Query) -> Query {
+    let mut query = query.clone();
+    query.filters = normalize_filters(&query.filters);
+    query.having = normalize_filters(&query.having);
+    query.equations.sort_by_key(|equation| format!("{}={}", equation.left, equation.right));
+    query.equations.dedup();
+    query
+}
+
+fn normalize_filters(filters: &[Filter]) -> Vec<Filter> {
+    match Expr::from_filters(filters).map(canonical) {
+        // an AND of ORs only stays split over the filters
+        Some(expr) => expr.to_filters(),
+        None => vec![],
+    }
+}
+
+fn canonical(expr: Expr) -> Expr {
+    match expr {
+        Expr::Condition(condition) => Expr::Condition(canonical_condition(condition)),
+        Expr::And(exprs) => combine(exprs, true),
+        Expr::Or(exprs) => combine(exprs, false),
+    }
+}
+
+/// the children of an AND or of an OR, with those of the nested ANDs in an AND
+/// and of the ORs in an OR, conditions first and then in the order of their text
+fn combine(exprs: Vec<Expr>, and: bool) -> Expr {
+    let mut children = vec![];
+    for expr in exprs {
+        match canonical(expr) {
+            Expr::And(inner) if and => children.extend(inner),
+            Expr::Or(inner) if !and => children.extend(inner),
+            other => children.push(other),
+        }
+    }
+    children.sort_by_key(|expr| {
+        (!matches!(*expr, Expr::Condition(_)), expr.to_string())
+    });
+    children.dedup();
+    match (children.len(), and) {
+        (1, _) => children.remove(0),
+        (_, true) => Expr::And(children),
+        (_, false) => Expr::Or(children),
+    }
+}
+
+fn canonical_condition(condition: Condition) -> Condition {
+    let right = match condition.equality {
+        Equality::IN | Equality::NOT_IN => {
+            let mut items: Vec<Operand> = condition.right
+                .list_items()
+                .into_iter()
+                .map(canonical_operand)
+                .collect();
+            items.sort_by_key(Operand::to_string);
+            items.dedup_by_key(|item| item.to_string());
+            Operand::List(items)
+        }
+        Equality::IS | Equality::IS_NOT => match condition.right {
+            Operand::Value(ref v) if v.eq_ignore_ascii_case("null") => Operand::Value("null".to_string()),
+            ref right => canonical_operand(right.clone()),
+        },
+        _ => canonical_operand(condition.right),
+    };
+    Condition {
+        left: canonical_operand(condition.left),
+        equality: condition.equality,
+        right,
+    }
+}
+
+/// `-0` is `0`, the aggregates are in lowercase as they are called the same either way
+fn canonical_operand(operand: Operand) -> Operand {
+    match operand {
+        // the pattern matches -0 as well
+        Operand::Number(0.0) => Operand::Number(0.0),
+        Operand::Function(function) => Operand::Function(Function {
+            function: function.function.to_lowercase(),
+            params: function.params.into_iter().map(canonical_operand).collect(),
+        }),
+        operand => operand,
+    }
+}
+
+/// the query with its literals replaced by `?` and its range by a page of 0 rows
+fn mask(query: &Query) -> Query {
+    let mut query = query.clone();
+    for filter in query.filters.iter_mut().chain(query.having.iter_mut()) {
+        mask_filter(filter);
+    }
+    for equation in &mut query.equations {
+        *equation = Equation {
+            left: equation.left.clone(),
+            right: mask_operand(&equation.right),
+        };
+    }
+    if query.range.is_some() {
+        query.range = Some(Range::Limit(Limit { limit: 0, offset: None }));
+    }
+    query
+}
+
+fn mask_filter(filter: &mut Filter) {
+    filter.condition.left = mask_operand(&filter.condition.left);
+    filter.condition.right = mask_operand(&filter.condition.right);
+    for sub_filter in &mut filter.sub_filters {
+        mask_filter(sub_filter);
+    }
+}
+
+fn mask_operand(operand: &Operand) -> Operand {
+    match *operand {
+        Operand::Column(_) => operand.clone(),
+        Operand::Function(ref function) => Operand::Function(Function {
+            function: function.function.to_string(),
+            params: function.params.iter().map(mask_operand).collect(),
+        }),
+        _ => Operand::Value("?".to_string()),
+    }
+}
+
+/// the query string of the query, and its joins which the query string leaves out
+fn canonical_text(query: &Query) -> String {
+    let mut text = query.to_string();
+    for join in &query.join {
+        text.push_str("&join=");
+        text.push_str(&join_text(join));
+    }
+    text
+}
+
+/// `left.outer.orders(id;user_id)`, written out field by field so that the
+/// fingerprint does not change with the `Debug` of the join
+fn join_text(join: &Join) -> String {
+    let modifier = match join.modifier {
+        Some(Modifier::LEFT) => "left",
+        Some(Modifier::RIGHT) => "right",
+        Some(Modifier::FULL) => "full",
+        None => "",
+    };
+    let join_type = match join.join_type {
+        Some(JoinType::CROSS) => "cross",
+        Some(JoinType::INNER) => "inner",
+        Some(JoinType::OUTER) => "outer",
+        Some(JoinType::NATURAL) => "natural",
+        None => "",
+    };
+    format!("{}.{}.{}({};{})", modifier, join_type, join.table, join.column1.join(","), join.column2.join(","))
+}
+
+fn fnv1a(text: &str) -> u128 {
+    text.bytes().fold(FNV_OFFSET, |hash, byte| (hash ^ byte as u128).wrapping_mul(FNV_PRIME))
+}
+
+#[test]
+fn test_normalize() {
+    let normalized = |input: &str| normalize(&::parse_query(input).unwrap()).to_string();
+    assert_eq!(normalized("b=eq.2&a=eq.1&a=eq.1"), "a=eq.1&b=eq.2");
+    assert_eq!(normalized("d=eq.1&(c=eq.1|b=eq.1|a=eq.1)"), "d=eq.1&(a=eq.1|b=eq.1|c=eq.1)");
+    assert_eq!(normalized("id=in.3,1,1&x=is.NULL&y=eq.-0"), "id=in.1,3&x=is.null&y=eq.0");
+    assert_eq!(normalized("group_by=b,a&having=COUNT(*)=gt.1&order_by=b,a"),
+               "group_by=b,a&having=count(*)=gt.1&order_by=b,a");
+    assert_eq!(normalized("a=eq.1|b=eq.1&c=eq.1"), normalized("c=eq.1&b=eq.1|a=eq.1"));
+    // an AND of ORs stays split over the filters
+    let query = Query {
+        filters: vec![::parse_filter("b=eq.1|c=eq.1"), ::parse_filter("d=eq.1|a=eq.1")],
+        ..Query::default()
+    };
+    assert_eq!(normalize(&query).filters,
+               vec![::parse_filter("a=eq.1|d=eq.1"), ::parse_filter("b=eq.1|c=eq.1")]);
+    assert_eq!(normalize(&normalize(&query)), normalize(&query));
+}
+
+#[test]
+fn test_fingerprint() {
+    let fingerprint = |input: &str| ::parse_query(input).unwrap().fingerprint();
+    let masked = |input: &str| ::parse_query(input).unwrap().masked_fingerprint();
+    assert_eq!(fingerprint("b=eq.2&a=eq.1"), fingerprint("a=eq.1&b=eq.2&a=eq.1"));
+    assert!(fingerprint("a=eq.1") != fingerprint("a=eq.2"));
+    let split = Query {
+        filters: vec![::parse_filter("a=eq.1|b=eq.1"), ::parse_filter("c=eq.1|d=eq.1")],
+        ..Query::default()
+    };
+    assert!(split.fingerprint() != fingerprint("a=eq.1|b=eq.1&c=eq.1|d=eq.1"));
+    assert_eq!(masked("age=lt.20&limit=10"), masked("age=lt.30&limit=5"));
+    assert!(masked("age=lt.20") != masked("age=gt.20"));
+    assert!(masked("age=lt.20") != masked("age=lt.20&limit=10"));
+    assert_eq!(fnv1a(""), FNV_OFFSET);
+    assert_eq!(Fingerprint(0xab).to_string(), "000000000000000000000000000000ab");
+    // stable across versions, so it can be stored
+    assert_eq!(fingerprint("a=eq.1").to_string(), "57f67040cf3c64bf6f103dc82d945e0c");
+}
+
+#[test]
+fn test_join_text() {
+    let join = |modifier, join_type| Join {
+        modifier,
+        join_type,
+        table: Operand::Column("orders".to_string()),
+        column1: vec!["id".to_string()],
+        column2: vec!["user_id".to_string()],
+    };
+    let query = Query { join: vec![join(Some(Modifier::LEFT), Some(JoinType::OUTER))], ..::parse_query("a=eq.1").unwrap() };
+    assert_eq!(canonical_text(&query), "a=eq.1&join=left.outer.orders(id;user_id)");
+    let inner = Query { join: vec![join(None, Some(JoinType::INNER))], ..query.clone() };
+    assert!(inner.fingerprint() != query.fingerprint());
+    // stable across versions, whatever the Debug of a join
+    assert_eq!(query.fingerprint().to_string(), "0c3fcfd22685590d5e8bc57e44365bba");
+}
@@ -0,0 +1,271 @@
+//! Which columns a role may see, filter and order on, and the filters it can not do without
+//!
+//! A policy is applied to the query of a client before it is executed: a column
+//! the role is denied is an error, and the mandatory filters of the role, such as
+//! `tenant_id=eq.<tenant_id>`, are added with the values of the claims of the client.
+//! The filters of a query are all AND-ed together, each mandatory filter is one of
+//! them, so an OR in the filters of the client can not get around it.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use {value_operand, Filter, Operand, ParseError, Query};
+
+/// the columns a rule lets through, a rule is matched against a column and
+/// against its unqualified name, `salary` is also `users.salary`. Like in sql
+/// the names are case insensitive, `salary` is also `SALARY`
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub enum ColumnRule {
+    #[default]
+    All,
+    Allow(Vec<String>),
+    Deny(Vec<String>),
+}
+
+impl ColumnRule {
+    pub fn permits(&self, column: &str) -> bool {
+        let column = column.to_lowercase();
+        let listed = |columns: &[String]| {
+            names(&column).iter().any(|name| columns.iter().any(|c| c.to_lowercase() == *name))
+        };
+        match *self {
+            ColumnRule::All => true,
+            ColumnRule::Allow(ref columns) => listed(columns),
+            ColumnRule::Deny(ref columns) => !listed(columns),
+        }
+    }
+}
+
+/// the column and its names without the leading qualifiers: `a.b.c`, `b.c`, `c`
+fn names(column: &str) -> Vec<&str> {
+    let mut names = vec![column];
+    names.extend(column.match_indices('.').map(|(i, _)| &column[i + 1..]));
+    names
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Role {
+    /// the columns of the result, and of group_by as it shows their values
+    pub select: ColumnRule,
+    pub filter: ColumnRule,
+    pub order: ColumnRule,
+    /// filters AND-ed into the filters, `<name>` is the value of the claim `name`
+    pub filters: Vec<String>,
+    /// filters AND-ed into the having filters
+    pub having: Vec<String>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Policy {
+    pub roles: HashMap<String, Role>,
+}
+
+/// what a column is used for in a query
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Usage {
+    Select,
+    Filter,
+    Order,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Error {
+    UnknownRole(String),
+    /// the role can not use the column this way
+    Denied(String, Usage),
+    /// a mandatory filter needs a claim the client does not have
+    MissingClaim(String),
+    /// a mandatory filter of the policy does not parse
+    InvalidFilter(String, ParseError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownRole(ref role) => write!(f, "unknown role: {}", role),
+            Error::Denied(ref column, usage) => {
+                let usage = match usage {
+                    Usage::Select => "selected",
+                    Usage::Filter => "filtered on",
+                    Usage::Order => "ordered by",
+                };
+                write!(f, "{} can not be {}", column, usage)
+            }
+            Error::MissingClaim(ref claim) => write!(f, "missing claim: {}", claim),
+            Error::InvalidFilter(ref filter, ref e) => write!(f, "invalid mandatory filter {}: {}", filter, e),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+impl Policy {
+    /// the query of a client with the role, once checked and with the mandatory filters added
+    pub fn apply(&self, role: &str, query: &Query, claims: &HashMap<String, String>) -> Result<Query, Error> {
+        let role = self.roles.get(role).ok_or_else(|| Error::UnknownRole(role.to_string()))?;
+        let check = |operands: Vec<&Operand>, rule: &ColumnRule, usage: Usage| {
+            for column in operands.into_iter().flat_map(columns) {
+                if !rule.permits(&column) {
+                    return Err(Error::Denied(column, usage));
+                }
+            }
+            Ok(())
+        };
+        check(filter_operands(&query.filters), &role.filter, Usage::Filter)?;
+        check(filter_operands(&query.having), &role.filter, Usage::Filter)?;
+        check(query.group_by.iter().collect(), &role.select, Usage::Select)?;
+        check(query.order_by.iter().map(|order| &order.operand).collect(), &role.order, Usage::Order)?;
+
+        let mut query = query.clone();
+        query.filters.extend(mandatory(&role.filters, claims)?);
+        query.having.extend(mandatory(&role.having, claims)?);
+        Ok(query)
+    }
+
+    /// the columns of a result the role may see, in the same order
+    pub fn select_columns(&self, role: &str, columns: &[String]) -> Result<Vec<String>, Error> {
+        let role = self.roles.get(role).ok_or_else(|| Error::UnknownRole(role.to_string()))?;
+        Ok(columns.iter().filter(|column| role.select.permits(column)).cloned().collect())
+    }
+}
+
+/// both sides of every condition of the filters and of their sub filters
+fn filter_operands(filters: &[Filter]) -> Vec<&Operand> {
+    let mut operands = vec![];
+    let mut stack: Vec<&Filter> = filters.iter().collect();
+    while let Some(filter) = stack.pop() {
+        operands.push(&filter.condition.left);
+        operands.push(&filter.condition.right);
+        stack.extend(&filter.sub_filters);
+    }
+    operands
+}
+
+/// the columns an operand reads, a function reads the columns of its params
+fn columns(operand: &Operand) -> Vec<String> {
+    match *operand {
+        Operand::Column(ref column) => vec![column.to_string()],
+        Operand::Function(ref function) => function.params.iter().flat_map(columns).collect(),
+        _ => vec![],
+    }
+}
+
+/// the filters of the templates, the claims are put in after the parsing so
+/// whatever a claim holds it is only ever a value
+fn mandatory(templates: &[String], claims: &HashMap<String, String>) -> Result<Vec<Filter>, Error> {
+    let mut filters = vec![];
+    for template in templates {
+        let query = ::parse_query(template).map_err(|e| Error::InvalidFilter(template.to_string(), e))?;
+        for mut filter in query.filters {
+            fill_claims(&mut filter, claims)?;
+            filters.push(filter);
+        }
+    }
+    Ok(filters)
+}
+
+fn fill_claims(filter: &mut Filter, claims: &HashMap<String, String>) -> Result<(), Error> {
+    fill_operand(&mut filter.condition.left, claims)?;
+    fill_operand(&mut filter.condition.right, claims)?;
+    for sub_filter in &mut filter.sub_filters {
+        fill_claims(sub_filter, claims)?;
+    }
+    Ok(())
+}
+
+fn fill_operand(operand: &mut Operand, claims: &HashMap<String, String>) -> Result<(), Error> {
+    let claim = match *operand {
+        Operand::Value(ref v) if v.starts_with('<') && v.ends_with('>') && v.len() > 2 => {
+            v[1..v.len() - 1].to_string()
+        }
+        Operand::List(ref mut items) => {
+            return items.iter_mut().try_for_each(|item| fill_operand(item, claims));
+        }
+        _ => return Ok(()),
+    };
+    let value = claims.get(&claim).ok_or_else(|| Error::MissingClaim(claim.to_string()))?;
+    *operand = match value_operand(value) {
+        // a claim is data, not a time relative to the present
+        Operand::Relative(_) => Operand::Value(value.to_string()),
+        operand => operand,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+fn tenants() -> Policy {
+    let mut roles = HashMap::new();
+    roles.insert("member".to_string(),
+                 Role {
+                     select: ColumnRule::Deny(vec!["salary".to_string()]),
+                     filter: ColumnRule::Deny(vec!["salary".to_string()]),
+                     order: ColumnRule::Allow(vec!["name".to_string(), "age".to_string()]),
+                     filters: vec!["tenant_id=eq.<tenant_id>".to_string()],
+                     having: vec![],
+                 });
+    roles.insert("admin".to_string(), Role::default());
+    Policy { roles }
+}
+
+#[cfg(test)]
+fn claims(tenant_id: &str) -> HashMap<String, String> {
+    let mut claims = HashMap::new();
+    claims.insert("tenant_id".to_string(), tenant_id.to_string());
+    claims
+}
+
+#[test]
+fn test_mandatory_filters() {
+    use expr::Expr;
+    let query = ::parse_query("age=lt.20|age=gt.60&order_by=users.name").unwrap();
+    let applied = tenants().apply("member", &query, &claims("42")).unwrap();
+    assert_eq!(applied.filters.len(), 2);
+    assert_eq!(applied.filters[0], query.filters[0]);
+    assert_eq!(applied.filters[1], ::parse_filter("tenant_id=eq.42"));
+    // the OR of the client stays inside its own filter
+    match Expr::from_filters(&applied.filters) {
+        Some(Expr::And(exprs)) => assert_eq!(exprs.len(), 2),
+        other => panic!("{:?}", other),
+    }
+    // a claim is a value, never more of the query
+    let applied = tenants().apply("member", &Query::default(), &claims("1|tenant_id=gt.0")).unwrap();
+    assert_eq!(applied.filters[0].condition.right, Operand::Value("1|tenant_id=gt.0".to_string()));
+    assert!(applied.filters[0].sub_filters.is_empty());
+    let applied = tenants().apply("member", &Query::default(), &claims("@now")).unwrap();
+    assert_eq!(applied.filters[0].condition.right, Operand::Value("@now".to_string()));
+    assert_eq!(tenants().apply("member", &Query::default(), &HashMap::new()),
+               Err(Error::MissingClaim("tenant_id".to_string())));
+}
+
+#[test]
+fn test_denied_columns() {
+    let apply = |role: &str, input: &str| tenants().apply(role, &::parse_query(input).unwrap(), &claims("1"));
+    assert_eq!(apply("member", "name=eq.a|(age=gt.1&users.salary=gt.100)"),
+               Err(Error::Denied("users.salary".to_string(), Usage::Filter)));
+    assert_eq!(apply("member", "group_by=dept&having=max(salary)=gt.1"),
+               Err(Error::Denied("salary".to_string(), Usage::Filter)));
+    assert_eq!(apply("member", "order_by=dept"), Err(Error::Denied("dept".to_string(), Usage::Order)));
+    assert_eq!(apply("member", "group_by=salary"), Err(Error::Denied("salary".to_string(), Usage::Select)));
+    assert!(apply("admin", "salary=gt.1&order_by=salary").is_ok());
+    assert_eq!(apply("member", "SALARY=gt.1"), Err(Error::Denied("SALARY".to_string(), Usage::Filter)));
+    assert_eq!(apply("member", "t.SALARY=gt.1"), Err(Error::Denied("t.SALARY".to_string(), Usage::Filter)));
+    assert_eq!(apply("member", "group_by=Salary"), Err(Error::Denied("Salary".to_string(), Usage::Select)));
+    assert!(apply("member", "order_by=Name,AGE").is_ok());
+    assert_eq!(apply("guest", ""), Err(Error::UnknownRole("guest".to_string())));
+    let columns: Vec<String> = vec!["name".to_string(), "salary".to_string()];
+    assert_eq!(tenants().select_columns("member", &columns), Ok(vec!["name".to_string()]));
+}
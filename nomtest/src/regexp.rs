@@ -0,0 +1,146 @@
+//! Regular expressions, `name=match.^Jo(hn|e)$` and `name=imatch.^jo`
+//!
+//! A pattern is checked when the query is parsed, so it can not make the
+//! database or the evaluator spend an unbounded time on a row: it is short,
+//! its repetitions are counted at most `MAX_REPETITION` times, a repetition
+//! of variable length is never repeated itself, `(a+)+` is refused, and its
+//! compiled program stays small. The syntax is that of the `regex` crate,
+//! which runs in linear time, the patterns are mostly the same in postgresql,
+//! mysql and sqlite but lookarounds and backreferences are not allowed.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Hir, HirKind};
+use regex_syntax::ParserBuilder;
+
+/// the bytes of a pattern
+pub const MAX_LENGTH: usize = 256;
+/// the levels of groups and repetitions
+pub const MAX_NESTING: u32 = 16;
+/// the largest count of a repetition, `a{1000}`
+pub const MAX_REPETITION: u32 = 1000;
+/// the bytes of the compiled program
+pub const MAX_SIZE: usize = 1 << 18;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum PatternError {
+    /// the pattern is longer than `MAX_LENGTH`
+    TooLong(usize),
+    /// not a pattern, with the reason
+    Syntax(String),
+    /// a repetition of variable length inside another repetition, `(a+)+`
+    NestedRepetition,
+    /// a repetition counted more than `MAX_REPETITION` times
+    Repetition(u32),
+    /// the compiled pattern is larger than `MAX_SIZE`
+    TooComplex,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatternError::TooLong(length) => write!(f, "the pattern is longer than {} bytes", length),
+            PatternError::Syntax(ref reason) => write!(f, "invalid pattern: {}", reason),
+            PatternError::NestedRepetition => write!(f, "a repetition can not be repeated"),
+            PatternError::Repetition(count) => write!(f, "a repetition of more than {} times", count),
+            PatternError::TooComplex => write!(f, "the pattern is too complex"),
+        }
+    }
+}
+
+impl ::std::error::Error for PatternError {}
+
+/// the compiled pattern, once it is checked
+pub fn compile(pattern: &str, case_insensitive: bool) -> Result<Regex, PatternError> {
+    if pattern.len() > MAX_LENGTH {
+        return Err(PatternError::TooLong(MAX_LENGTH));
+    }
+    let hir = ParserBuilder::new()
+        .nest_limit(MAX_NESTING)
+        .case_insensitive(case_insensitive)
+        .build()
+        .parse(pattern)
+        .map_err(|e| PatternError::Syntax(match e {
+            regex_syntax::Error::Parse(ref e) => e.kind().to_string(),
+            regex_syntax::Error::Translate(ref e) => e.kind().to_string(),
+            e => e.to_string(),
+        }))?;
+    check(&hir, false)?;
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .nest_limit(MAX_NESTING)
+        .size_limit(MAX_SIZE)
+        .build()
+        .map_err(|e| match e {
+            regex::Error::CompiledTooBig(_) => PatternError::TooComplex,
+            e => PatternError::Syntax(e.to_string()),
+        })
+}
+
+/// the repetitions of the pattern, `repeated` when it is inside one which repeats
+fn check(hir: &Hir, repeated: bool) -> Result<(), PatternError> {
+    match *hir.kind() {
+        HirKind::Repetition(ref repetition) => {
+            let count = repetition.max.unwrap_or(repetition.min);
+            if count > MAX_REPETITION {
+                return Err(PatternError::Repetition(MAX_REPETITION));
+            }
+            if repeated && repetition.max != Some(repetition.min) {
+                return Err(PatternError::NestedRepetition);
+            }
+            check(&repetition.sub, repeated || repetition.max != Some(1))
+        }
+        HirKind::Capture(ref capture) => check(&capture.sub, repeated),
+        HirKind::Concat(ref hirs) | HirKind::Alternation(ref hirs) => {
+            hirs.iter().try_for_each(|hir| check(hir, repeated))
+        }
+        HirKind::Empty | HirKind::Literal(_) | HirKind::Class(_) | HirKind::Look(_) => Ok(()),
+    }
+}
+
+thread_local! {
+    /// the patterns compiled lately, so a filter does not compile its pattern for every row
+    static COMPILED: RefCell<HashMap<(String, bool), Regex>> = RefCell::new(HashMap::new());
+}
+
+/// whether the text matches the pattern anywhere, None when it is not a valid pattern
+pub fn is_match(pattern: &str, case_insensitive: bool, text: &str) -> Option<bool> {
+    COMPILED.with(|compiled| {
+        let mut compiled = compiled.borrow_mut();
+        let key = (pattern.to_string(), case_insensitive);
+        if !compiled.contains_key(&key) {
+            if compiled.len() >= 64 {
+                compiled.clear();
+            }
+            compiled.insert(key.clone(), compile(pattern, case_insensitive).ok()?);
+        }
+        Some(compiled[&key].is_match(text))
+    })
+}
+
+#[test]
+fn test_compile() {
+    assert!(compile("^Jo(hn|e)$", false).unwrap().is_match("John"));
+    assert!(compile("^jo", true).unwrap().is_match("JOE"));
+    assert!(compile("(ab){3}(cd)*[0-9]+", false).is_ok());
+    assert_eq!(compile("(a+)+$", false).err(), Some(PatternError::NestedRepetition));
+    assert_eq!(compile("(a|b?)*", false).err(), Some(PatternError::NestedRepetition));
+    assert_eq!(compile("a{1001}", false).err(), Some(PatternError::Repetition(MAX_REPETITION)));
+    assert_eq!(compile(&"a".repeat(300), false).err(), Some(PatternError::TooLong(MAX_LENGTH)));
+    assert_eq!(compile("\\w{1000}\\w{1000}", false).err(), Some(PatternError::TooComplex));
+    assert!(matches!(compile("(?=a)", false), Err(PatternError::Syntax(_))));
+    assert!(matches!(compile("(a", false), Err(PatternError::Syntax(_))));
+}
+
+#[test]
+fn test_is_match() {
+    assert_eq!(is_match("c.t", false, "the cat"), Some(true));
+    assert_eq!(is_match("C.T", false, "the cat"), Some(false));
+    assert_eq!(is_match("C.T", true, "the cat"), Some(true));
+    assert_eq!(is_match("(", false, "the cat"), None);
+}
@@ -0,0 +1,356 @@
+//! Dates and times relative to the present, `created_at=gt.now-7d`
+//!
+//! A range comparison, `lt`, `lte`, `gt` or `gte`, takes a relative time as it
+//! is. Anywhere else it is marked with a `@`, `day=in.@today,2024-01-01`, so a
+//! text such as `status=eq.now` stays a text. A relative time is an anchor, `now`, `today` or `start_of_month`, shifted
+//! by any number of durations: `s`, `m` (minutes), `h`, `d`, `w`, `mo` and
+//! `y`. A month or a year keeps the day of the month, or takes the last day
+//! of a shorter month: `2024-01-31` and a month is `2024-02-29`.
+//!
+//! The query keeps the relative time as it was written, so a saved search
+//! prints back the same, and it is resolved against a `Clock` in utc when the
+//! query is rendered or evaluated. `resolve`, `resolve_filter` and
+//! `resolve_operand` do it with any clock before the query, a filter or an
+//! operand is rendered, evaluated or converted, which is how the tests freeze
+//! time. What is left unresolved is resolved with the clock of the system. An anchor on a
+//! day resolves into a date, `2024-01-31`, unless it is shifted by hours,
+//! minutes or seconds, `now` into a time, `2024-01-31T13:45:00Z`, the same
+//! forms as the dates of the `chrono` feature.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use {Filter, Operand, Query};
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// the present, in seconds since the unix epoch in utc
+pub trait Clock {
+    fn now(&self) -> i64;
+}
+
+/// the clock of the system
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    }
+}
+
+/// a clock which is always at the same time, for the tests
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct FixedClock(pub i64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> i64 {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Anchor {
+    /// the present time
+    Now,
+    /// the start of the present day
+    Today,
+    /// the start of the first day of the present month
+    StartOfMonth,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Unit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Unit {
+    /// the seconds of the unit, None for months and years which vary
+    fn seconds(self) -> Option<i64> {
+        match self {
+            Unit::Second => Some(1),
+            Unit::Minute => Some(60),
+            Unit::Hour => Some(60 * 60),
+            Unit::Day => Some(DAY),
+            Unit::Week => Some(7 * DAY),
+            Unit::Month | Unit::Year => None,
+        }
+    }
+}
+
+/// a duration added to the anchor, `-7d` is an amount of -7 days
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Shift {
+    pub amount: i64,
+    pub unit: Unit,
+}
+
+/// `now`, `today+1w`, `start_of_month-1mo+14d`
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct RelativeTime {
+    pub anchor: Anchor,
+    pub shifts: Vec<Shift>,
+}
+
+impl RelativeTime {
+    pub fn new(anchor: Anchor) -> Self {
+        RelativeTime { anchor, shifts: vec![] }
+    }
+
+    /// the time shifted by an amount of the unit, negative to go back
+    pub fn shift(mut self, amount: i64, unit: Unit) -> Self {
+        self.shifts.push(Shift { amount, unit });
+        self
+    }
+
+    /// the date, or the time in utc, at the present of the clock
+    pub fn resolve<C: Clock + ?Sized>(&self, clock: &C) -> String {
+        let now = clock.now();
+        let today = now.div_euclid(DAY);
+        let (mut seconds, mut is_date) = match self.anchor {
+            Anchor::Now => (now, false),
+            Anchor::Today => (today * DAY, true),
+            Anchor::StartOfMonth => {
+                let (year, month, _) = civil_from_days(today);
+                (days_from_civil(year, month, 1) * DAY, true)
+            }
+        };
+        for shift in &self.shifts {
+            match shift.unit.seconds() {
+                Some(unit) => {
+                    seconds = seconds.saturating_add(shift.amount.saturating_mul(unit));
+                    is_date &= unit % DAY == 0;
+                }
+                None => {
+                    let months = if shift.unit == Unit::Year { shift.amount.saturating_mul(12) } else { shift.amount };
+                    seconds = add_months(seconds, months);
+                }
+            }
+        }
+        let (year, month, day) = civil_from_days(seconds.div_euclid(DAY));
+        if is_date {
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        } else {
+            let time = seconds.rem_euclid(DAY);
+            format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                    year, month, day, time / 3600, time / 60 % 60, time % 60)
+        }
+    }
+}
+
+impl fmt::Display for RelativeTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let anchor = match self.anchor {
+            Anchor::Now => "now",
+            Anchor::Today => "today",
+            Anchor::StartOfMonth => "start_of_month",
+        };
+        write!(f, "{}", anchor)?;
+        for shift in &self.shifts {
+            let unit = match shift.unit {
+                Unit::Second => "s",
+                Unit::Minute => "m",
+                Unit::Hour => "h",
+                Unit::Day => "d",
+                Unit::Week => "w",
+                Unit::Month => "mo",
+                Unit::Year => "y",
+            };
+            write!(f, "{:+}{}", shift.amount, unit)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RelativeTime {
+    type Err = ();
+
+    /// an anchor and the shifts right after it, without any space
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (anchor, mut rest) = [("now", Anchor::Now), ("today", Anchor::Today), ("start_of_month", Anchor::StartOfMonth)]
+            .iter()
+            .find_map(|&(name, anchor)| s.strip_prefix(name).map(|rest| (anchor, rest)))
+            .ok_or(())?;
+        let mut time = RelativeTime::new(anchor);
+        while !rest.is_empty() {
+            let negative = match rest.as_bytes()[0] {
+                b'+' => false,
+                b'-' => true,
+                _ => return Err(()),
+            };
+            rest = &rest[1..];
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            // at most 6 digits, so no amount can overflow
+            if digits == 0 || digits > 6 {
+                return Err(());
+            }
+            let amount: i64 = rest[..digits].parse().map_err(|_| ())?;
+            rest = &rest[digits..];
+            let letters = rest.find(|c: char| !c.is_ascii_lowercase()).unwrap_or(rest.len());
+            let unit = match &rest[..letters] {
+                "s" => Unit::Second,
+                "m" => Unit::Minute,
+                "h" => Unit::Hour,
+                "d" => Unit::Day,
+                "w" => Unit::Week,
+                "mo" => Unit::Month,
+                "y" => Unit::Year,
+                _ => return Err(()),
+            };
+            rest = &rest[letters..];
+            time = time.shift(if negative { -amount } else { amount }, unit);
+        }
+        Ok(time)
+    }
+}
+
+/// the query with the relative times of its filters resolved against the clock,
+/// also those in the lists of `in` and `not_in`
+pub fn resolve<C: Clock + ?Sized>(query: &Query, clock: &C) -> Query {
+    let mut query = query.clone();
+    for filter in query.filters.iter_mut().chain(query.having.iter_mut()) {
+        resolve_in_place(filter, clock);
+    }
+    query
+}
+
+/// the filter with its relative times resolved against the clock, for the evaluator
+pub fn resolve_filter<C: Clock + ?Sized>(filter: &Filter, clock: &C) -> Filter {
+    let mut filter = filter.clone();
+    resolve_in_place(&mut filter, clock);
+    filter
+}
+
+fn resolve_in_place<C: Clock + ?Sized>(filter: &mut Filter, clock: &C) {
+    filter.condition.left = resolve_operand(&filter.condition.left, clock);
+    filter.condition.right = resolve_operand(&filter.condition.right, clock);
+    for sub_filter in &mut filter.sub_filters {
+        resolve_in_place(sub_filter, clock);
+    }
+}
+
+/// the operand with its relative times resolved, the others are as they are
+pub fn resolve_operand<C: Clock + ?Sized>(operand: &Operand, clock: &C) -> Operand {
+    match *operand {
+        Operand::Relative(ref time) => Operand::Value(time.resolve(clock)),
+        Operand::List(ref items) => Operand::List(items.iter().map(|item| resolve_operand(item, clock)).collect()),
+        Operand::Function(ref function) => {
+            let mut function = function.clone();
+            function.params = function.params.iter().map(|param| resolve_operand(param, clock)).collect();
+            Operand::Function(function)
+        }
+        _ => operand.clone(),
+    }
+}
+
+/// the month, and the day of a shorter month is the last one, the time of the day is kept
+fn add_months(seconds: i64, months: i64) -> i64 {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(DAY));
+    let months = (year * 12 + month - 1).saturating_add(months);
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+    let day = day.min(days_in_month(year, month));
+    days_from_civil(year, month, day) * DAY + seconds.rem_euclid(DAY)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// the days since 1970-01-01 of a date of the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// the year, the month and the day of the days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (if month <= 2 { year_of_era + era * 400 + 1 } else { year_of_era + era * 400 }, month, day)
+}
+
+/// 2024-01-31T13:45:00Z
+#[cfg(test)]
+const CLOCK: FixedClock = FixedClock(1706708700);
+
+#[test]
+fn test_parse() {
+    let time: RelativeTime = "now-7d".parse().unwrap();
+    assert_eq!(time, RelativeTime::new(Anchor::Now).shift(-7, Unit::Day));
+    for input in &["now", "today+1w", "start_of_month-1mo+14d", "now+90m-1h+30s", "today-1y"] {
+        assert_eq!(input.parse::<RelativeTime>().map(|time| time.to_string()), Ok(input.to_string()));
+    }
+    for input in &["nowadays", "today+", "now-7", "now 7d", "today+1M", "now-1234567d", "yesterday"] {
+        assert_eq!(input.parse::<RelativeTime>(), Err(()), "{}", input);
+    }
+}
+
+#[test]
+fn test_resolve() {
+    let resolve = |input: &str| input.parse::<RelativeTime>().unwrap().resolve(&CLOCK);
+    assert_eq!(resolve("now"), "2024-01-31T13:45:00Z");
+    assert_eq!(resolve("now-7d"), "2024-01-24T13:45:00Z");
+    assert_eq!(resolve("today"), "2024-01-31");
+    assert_eq!(resolve("today+1w"), "2024-02-07");
+    assert_eq!(resolve("today+12h"), "2024-01-31T12:00:00Z");
+    assert_eq!(resolve("start_of_month"), "2024-01-01");
+    assert_eq!(resolve("start_of_month-1d"), "2023-12-31");
+    // the last day of a shorter month
+    assert_eq!(resolve("today+1mo"), "2024-02-29");
+    assert_eq!(resolve("today+1y+1mo"), "2025-02-28");
+    assert_eq!(resolve("now-1y-1m"), "2023-01-31T13:44:00Z");
+    assert_eq!(RelativeTime::new(Anchor::Today).resolve(&FixedClock(-1)), "1969-12-31");
+    assert!(SystemClock.now() > CLOCK.now());
+}
+
+#[test]
+fn test_resolve_query() {
+    let query = ::parse_query("created_at=gt.now-7d&(due=lt.today+1w|day=in.@today,@start_of_month,2024-01-01)").unwrap();
+    assert_eq!(query.to_string(), "created_at=gt.now-7d&(due=lt.today+1w|day=in.@today,@start_of_month,2024-01-01)");
+    let resolved = resolve(&query, &CLOCK);
+    assert_eq!(resolved.to_string(),
+               "created_at=gt.2024-01-24T13:45:00Z&(due=lt.2024-02-07|day=in.2024-01-31,2024-01-01,2024-01-01)");
+    assert_eq!(resolve(&resolved, &CLOCK), resolved);
+}
@@ -0,0 +1,226 @@
+//! Rows of in-memory data, which the filters are evaluated against
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use serde_json;
+
+use json;
+use relative::SystemClock;
+use Operand;
+
+/// a typed value of a column
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl Value {
+    /// the value of a literal operand, columns, functions and lists have no value on their own,
+    /// a relative time which `relative::resolve_filter` has not resolved is resolved
+    /// against the clock of the system
+    pub fn from_operand(operand: &Operand) -> Option<Value> {
+        match *operand {
+            Operand::Number(n) => Some(Value::Number(n)),
+            Operand::Boolean(b) => Some(Value::Bool(b)),
+            Operand::Value(ref v) => Some(Value::Text(v.to_string())),
+            Operand::Relative(ref time) => Some(Value::Text(time.resolve(&SystemClock))),
+            Operand::Column(_) | Operand::Function(_) | Operand::List(_) => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    /// compare two values like sql would, None when either is NULL or they can not be compared.
+    /// A text is compared to a number or a boolean by what it reads as, `"20"` is the number 20
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            (Value::Text(a), Value::Number(b)) => a.trim().parse::<f64>().ok()?.partial_cmp(b),
+            (Value::Number(a), Value::Text(b)) => a.partial_cmp(&b.trim().parse::<f64>().ok()?),
+            (Value::Text(a), Value::Bool(b)) => Some(parse_bool(a)?.cmp(b)),
+            (Value::Bool(a), Value::Text(b)) => Some(a.cmp(&parse_bool(b)?)),
+            (Value::Bool(_), Value::Number(_)) | (Value::Number(_), Value::Bool(_)) => None,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match *self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(b) => serde_json::Value::Bool(b),
+            Value::Number(n) => json::number(n),
+            Value::Text(ref s) => serde_json::Value::String(s.to_string()),
+        }
+    }
+
+    /// the text form used for pattern matching, None for NULL
+    pub fn to_text(&self) -> Option<String> {
+        match *self {
+            Value::Null => None,
+            Value::Bool(b) => Some(b.to_string()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Text(ref s) => Some(s.to_string()),
+        }
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+impl<'a> From<&'a serde_json::Value> for Value {
+    /// arrays and objects are kept as their json text
+    fn from(json: &'a serde_json::Value) -> Self {
+        match *json {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(ref n) => n.as_f64().map(Value::Number).unwrap_or(Value::Null),
+            serde_json::Value::String(ref s) => Value::Text(s.to_string()),
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => Value::Text(json.to_string()),
+        }
+    }
+}
+
+/// a row the filters can be evaluated against
+pub trait Row {
+    /// the value of a column, None when the row has no such column,
+    /// which is treated the same as NULL
+    fn get(&self, column: &str) -> Option<Value>;
+
+    /// the names of the columns in order, empty when the row does not know them
+    fn columns(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// the json of a column, json rows keep their nested arrays and objects as they are
+    fn get_json(&self, column: &str) -> serde_json::Value {
+        self.get(column).unwrap_or(Value::Null).to_json()
+    }
+}
+
+impl Row for HashMap<String, Value> {
+    fn get(&self, column: &str) -> Option<Value> {
+        HashMap::get(self, column).cloned()
+    }
+
+    fn columns(&self) -> Vec<String> {
+        let mut columns: Vec<String> = self.keys().cloned().collect();
+        columns.sort();
+        columns
+    }
+}
+
+/// a column is a key of the object or the path of a nested field
+impl Row for serde_json::Value {
+    fn get(&self, column: &str) -> Option<Value> {
+        lookup(self, column).map(Value::from)
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.as_object().map(|object| object.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    fn get_json(&self, column: &str) -> serde_json::Value {
+        lookup(self, column).cloned().unwrap_or(serde_json::Value::Null)
+    }
+}
+
+impl<R: Row> Row for &R {
+    fn get(&self, column: &str) -> Option<Value> {
+        R::get(*self, column)
+    }
+
+    fn columns(&self) -> Vec<String> {
+        R::columns(*self)
+    }
+
+    fn get_json(&self, column: &str) -> serde_json::Value {
+        R::get_json(*self, column)
+    }
+}
+
+/// the field of a json value at a dotted path, `address.city`, or a json path,
+/// `$.tags[0].name`. A key which has dots in it is found as it is first
+pub fn lookup<'a>(json: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    if let Some(value) = json.as_object().and_then(|object| object.get(path)) {
+        return Some(value);
+    }
+    let path = if path == "$" {
+        ""
+    } else if path.starts_with("$.") || path.starts_with("$[") {
+        path[1..].trim_start_matches('.')
+    } else {
+        path
+    };
+    let mut current = json;
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        let (key, mut indexes) = match segment.find('[') {
+            Some(bracket) => (&segment[..bracket], &segment[bracket..]),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            current = current.as_object()?.get(key)?;
+        }
+        while !indexes.is_empty() {
+            let end = indexes.find(']')?;
+            let index: usize = indexes[1..end].parse().ok()?;
+            current = current.as_array()?.get(index)?;
+            indexes = &indexes[end + 1..];
+            if !indexes.is_empty() && !indexes.starts_with('[') {
+                return None;
+            }
+        }
+    }
+    Some(current)
+}
+
+#[test]
+fn test_compare() {
+    assert_eq!(Value::Number(1.0).compare(&Value::Number(2.0)), Some(Ordering::Less));
+    assert_eq!(Value::Text("20".to_string()).compare(&Value::Number(20.0)),
+               Some(Ordering::Equal));
+    assert_eq!(Value::Text("abc".to_string()).compare(&Value::Number(20.0)), None);
+    assert_eq!(Value::Null.compare(&Value::Null), None);
+    assert_eq!(Value::Text("true".to_string()).compare(&Value::Bool(true)),
+               Some(Ordering::Equal));
+}
+
+#[test]
+fn test_json_row() {
+    let json: serde_json::Value = serde_json::from_str(r#"{"age": 20, "name": "技術通報", "tags": [1]}"#)
+        .unwrap();
+    assert_eq!(Row::get(&json, "age"), Some(Value::Number(20.0)));
+    assert_eq!(Row::get(&json, "name"), Some(Value::Text("技術通報".to_string())));
+    assert_eq!(Row::get(&json, "tags"), Some(Value::Text("[1]".to_string())));
+    assert_eq!(Row::get(&json, "missing"), None);
+}
+
+#[test]
+fn test_json_path() {
+    let json: serde_json::Value = serde_json::from_str(
+        r#"{"address": {"city": "Paris"}, "tags": [{"name": "new"}, {"name": "old"}], "a.b": 1}"#)
+        .unwrap();
+    assert_eq!(Row::get(&json, "address.city"), Some(Value::Text("Paris".to_string())));
+    assert_eq!(Row::get(&json, "$.address.city"), Some(Value::Text("Paris".to_string())));
+    assert_eq!(Row::get(&json, "tags[1].name"), Some(Value::Text("old".to_string())));
+    assert_eq!(Row::get(&json, "$.tags[0].name"), Some(Value::Text("new".to_string())));
+    assert_eq!(Row::get(&json, "a.b"), Some(Value::Number(1.0)));
+    assert_eq!(Row::get(&json, "tags[2].name"), None);
+    assert_eq!(Row::get(&json, "address.city.zip"), None);
+    assert_eq!(json.get_json("tags[0]"), json["tags"][0]);
+    assert_eq!(json.columns(), vec!["address", "tags", "a.b"]);
+}
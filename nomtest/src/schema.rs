@@ -0,0 +1,796 @@
+//! Check a parsed `Query` against the tables and columns it is meant for
+//!
+//! `price=lt.banana` and `nonexistent=eq.1` parse fine, `validate` finds what
+//! the database would fail on: unknown tables and columns, operands which do
+//! not fit the type of their column, and operators which make no sense for a
+//! type such as `like` on a boolean. All the problems are reported, not only
+//! the first one.
+//!
+//! A schema is written by hand, loaded from a declarative toml or json file
+//! or read from a sqlite database. Its foreign keys are the hints to resolve
+//! the columns of a join which does not give them.
+//!
+//! ```toml
+//! [tables.users]
+//! columns = { id = { type = "integer", nullable = false }, name = "text", dept_id = "integer" }
+//! foreign_keys = [{ columns = ["dept_id"], table = "depts", references = ["id"] }]
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use rusqlite::{self, Connection};
+use serde_json;
+use toml;
+
+use expr::Expr;
+use {Condition, Equality, Filter, Function, Operand, Query};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Type {
+    Bool,
+    Integer,
+    /// a real number, integers fit in it too
+    Number,
+    Text,
+}
+
+impl Type {
+    fn is_numeric(self) -> bool {
+        self == Type::Integer || self == Type::Number
+    }
+
+    /// whether a literal operand is a value of the type, any literal fits in a text
+    fn accepts(self, operand: &Operand) -> bool {
+        match (self, operand) {
+            (Type::Text, _) => true,
+            (Type::Bool, &Operand::Boolean(_)) => true,
+            (Type::Integer, &Operand::Number(n)) => n.fract() == 0.0,
+            (Type::Number, &Operand::Number(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// whether two columns of the types can be compared
+    fn compatible(self, other: Type) -> bool {
+        self == other || (self.is_numeric() && other.is_numeric())
+    }
+
+    /// whether the operator makes sense for the type
+    fn supports(self, equality: &Equality) -> bool {
+        match *equality {
+            Equality::LIKE | Equality::ILIKE | Equality::ST => self == Type::Text,
+            Equality::FTS(_) | Equality::PLFTS(_) | Equality::PHFTS(_) | Equality::WFTS(_) => self == Type::Text,
+            Equality::MATCH | Equality::IMATCH => self == Type::Text,
+            Equality::LT | Equality::LTE | Equality::GT | Equality::GTE => self != Type::Bool,
+            _ => true,
+        }
+    }
+}
+
+impl Type {
+    /// the type of a column declared in sqlite, by the same rules sqlite
+    /// uses for the affinity of a column, dates are kept as text
+    pub fn from_sqlite(declared: &str) -> Type {
+        let declared = declared.to_uppercase();
+        let has = |part: &str| declared.contains(part);
+        if has("BOOL") {
+            Type::Bool
+        } else if has("INT") {
+            Type::Integer
+        } else if has("CHAR") || has("CLOB") || has("TEXT") || has("DATE") || has("TIME") ||
+                  has("BLOB") || declared.is_empty() {
+            Type::Text
+        } else {
+            Type::Number
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bool" | "boolean" => Ok(Type::Bool),
+            "int" | "integer" => Ok(Type::Integer),
+            "number" | "real" | "float" => Ok(Type::Number),
+            "text" | "string" => Ok(Type::Text),
+            _ => Err(format!("unknown type: {}, expecting boolean, integer, number or text", s)),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Type::Bool => "boolean",
+            Type::Integer => "integer",
+            Type::Number => "number",
+            Type::Text => "text",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Column {
+    pub name: String,
+    pub column_type: Type,
+    pub nullable: bool,
+}
+
+/// the columns of a table which refer to the columns of another table
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    pub table: String,
+    pub references: Vec<String>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+impl Table {
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+}
+
+impl Schema {
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    /// the columns a join of two tables is on, from a foreign key of either
+    /// table to the other, qualified with their table: `users.dept_id`, `depts.id`
+    pub fn join_columns(&self, left: &str, right: &str) -> Option<(Vec<String>, Vec<String>)> {
+        let qualify = |table: &str, columns: &[String]| -> Vec<String> {
+            columns.iter().map(|column| format!("{}.{}", table, column)).collect()
+        };
+        let key = |from: &str, to: &str| {
+            self.table(from)?.foreign_keys.iter().find(|key| key.table == to).cloned()
+        };
+        if let Some(key) = key(left, right) {
+            return Some((qualify(left, &key.columns), qualify(right, &key.references)));
+        }
+        key(right, left).map(|key| (qualify(left, &key.references), qualify(right, &key.columns)))
+    }
+
+    /// fill in the columns of the joins which have none, each joined table is
+    /// joined on the first table before it which it has a foreign key with
+    pub fn resolve_joins(&self, query: &mut Query) -> Result<(), Error> {
+        let mut tables: Vec<String> = query.from
+            .iter()
+            .filter_map(|operand| match *operand {
+                Operand::Column(ref table) => Some(table.to_string()),
+                _ => None,
+            })
+            .collect();
+        for join in &mut query.join {
+            let table = match join.table {
+                Operand::Column(ref table) => table.to_string(),
+                ref operand => return Err(Error::UnknownTable(format!("{:?}", operand))),
+            };
+            if join.column1.is_empty() && join.column2.is_empty() {
+                let columns = tables.iter().filter_map(|left| self.join_columns(left, &table)).next();
+                let (column1, column2) = columns.ok_or_else(|| Error::UnresolvedJoin(table.to_string()))?;
+                join.column1 = column1;
+                join.column2 = column2;
+            }
+            tables.push(table);
+        }
+        Ok(())
+    }
+}
+
+/// a schema file which can not be read
+#[derive(Debug)]
+pub enum LoadError {
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    /// the file is valid but not a schema, the message says where
+    Invalid(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Json(ref e) => write!(f, "{}", e),
+            LoadError::Toml(ref e) => write!(f, "{}", e),
+            LoadError::Invalid(ref reason) => write!(f, "invalid schema: {}", reason),
+        }
+    }
+}
+
+impl ::std::error::Error for LoadError {}
+
+/// the schema of every table and view of a sqlite database
+pub fn from_sqlite(connection: &Connection) -> rusqlite::Result<Schema> {
+    let mut statement = connection.prepare("SELECT name FROM sqlite_master WHERE type IN ('table', 'view') \
+                                            AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' ORDER BY name")?;
+    let names = statement.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+    let mut tables = vec![];
+    for name in names {
+        tables.push(sqlite_table(connection, &name)?);
+    }
+    Ok(Schema { tables })
+}
+
+fn sqlite_table(connection: &Connection, name: &str) -> rusqlite::Result<Table> {
+    let mut statement = connection.prepare("SELECT name, type, \"notnull\", pk FROM pragma_table_info(?)")?;
+    let columns = statement.query_map([name], |row| {
+            let primary_key = row.get::<_, i64>(3)? > 0;
+            Ok(Column {
+                name: row.get(0)?,
+                column_type: Type::from_sqlite(&row.get::<_, String>(1)?),
+                nullable: row.get::<_, i64>(2)? == 0 && !primary_key,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut statement = connection.prepare("SELECT id, \"table\", \"from\", \"to\" FROM pragma_foreign_key_list(?) \
+                                            ORDER BY id, seq")?;
+    let rows = statement.query_map([name], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut foreign_keys: Vec<(i64, ForeignKey)> = vec![];
+    for (id, table, from, to) in rows {
+        // a key without the referenced columns refers to the primary key
+        let to = match to {
+            Some(to) => to,
+            None => {
+                let position = foreign_keys.iter().find(|&&(i, _)| i == id).map_or(0, |(_, key)| key.columns.len());
+                primary_key(connection, &table)?.get(position).cloned().unwrap_or_default()
+            }
+        };
+        match foreign_keys.iter_mut().find(|&&mut (i, _)| i == id) {
+            Some(&mut (_, ref mut key)) => {
+                key.columns.push(from);
+                key.references.push(to);
+            }
+            None => {
+                foreign_keys.push((id, ForeignKey {
+                    columns: vec![from],
+                    table,
+                    references: vec![to],
+                }))
+            }
+        }
+    }
+    Ok(Table {
+        name: name.to_string(),
+        columns,
+        foreign_keys: foreign_keys.into_iter().map(|(_, key)| key).collect(),
+    })
+}
+
+fn primary_key(connection: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut statement = connection.prepare("SELECT name FROM pragma_table_info(?) WHERE pk > 0 ORDER BY pk")?;
+    let columns = statement.query_map([table], |row| row.get(0))?.collect();
+    columns
+}
+
+/// the schema described in a toml file
+pub fn from_toml(input: &str) -> Result<Schema, LoadError> {
+    let value: toml::Value = toml::from_str(input).map_err(LoadError::Toml)?;
+    let json = serde_json::to_value(&value).map_err(LoadError::Json)?;
+    from_json_value(&json)
+}
+
+/// the schema described in a json file, the same as the toml one
+pub fn from_json(input: &str) -> Result<Schema, LoadError> {
+    let json: serde_json::Value = serde_json::from_str(input).map_err(LoadError::Json)?;
+    from_json_value(&json)
+}
+
+/// `{"tables": {"users": {"columns": {...}, "foreign_keys": [...]}}}`, a column is
+/// its type, and is then nullable, or `{"type": "integer", "nullable": false}`
+fn from_json_value(json: &serde_json::Value) -> Result<Schema, LoadError> {
+    let invalid = |reason: String| LoadError::Invalid(reason);
+    let tables = json.get("tables")
+        .and_then(|tables| tables.as_object())
+        .ok_or_else(|| invalid("no tables".to_string()))?;
+    let mut schema = Schema::default();
+    for (name, table) in tables {
+        let mut columns = vec![];
+        let declared = table.get("columns")
+            .and_then(|columns| columns.as_object())
+            .ok_or_else(|| invalid(format!("{}: no columns", name)))?;
+        for (column, declaration) in declared {
+            let (column_type, nullable) = match *declaration {
+                serde_json::Value::String(ref column_type) => (column_type.as_str(), true),
+                serde_json::Value::Object(ref object) => {
+                    let column_type = object.get("type").and_then(|t| t.as_str());
+                    let nullable = object.get("nullable").map(|n| n.as_bool());
+                    match (column_type, nullable) {
+                        (Some(column_type), None) => (column_type, true),
+                        (Some(column_type), Some(Some(nullable))) => (column_type, nullable),
+                        _ => return Err(invalid(format!("{}.{}: expecting a type and nullable", name, column))),
+                    }
+                }
+                _ => return Err(invalid(format!("{}.{}: expecting a type", name, column))),
+            };
+            columns.push(Column {
+                name: column.to_string(),
+                column_type: column_type.parse().map_err(|e| invalid(format!("{}.{}: {}", name, column, e)))?,
+                nullable,
+            });
+        }
+        let mut foreign_keys = vec![];
+        let keys = table.get("foreign_keys").and_then(|keys| keys.as_array()).cloned().unwrap_or_default();
+        for key in keys {
+            let names = |field: &str| -> Option<Vec<String>> {
+                key.get(field)?.as_array()?.iter().map(|c| c.as_str().map(str::to_string)).collect()
+            };
+            let table = key.get("table").and_then(|t| t.as_str());
+            match (names("columns"), table, names("references")) {
+                (Some(columns), Some(table), Some(references)) if columns.len() == references.len() => {
+                    foreign_keys.push(ForeignKey {
+                        columns,
+                        table: table.to_string(),
+                        references,
+                    })
+                }
+                _ => {
+                    return Err(invalid(format!("{}: a foreign key has columns, a table and as many references",
+                                               name)))
+                }
+            }
+        }
+        schema.tables.push(Table {
+            name: name.to_string(),
+            columns,
+            foreign_keys,
+        });
+    }
+    Ok(schema)
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Error {
+    /// the query has no `from`, so there is nothing to look the columns up in
+    MissingTable,
+    UnknownTable(String),
+    UnknownColumn(String),
+    /// the column is in more than one of the tables of the query
+    AmbiguousColumn(String),
+    /// a function which is not one of the aggregates: count, sum, avg, min, max
+    UnknownFunction(String),
+    /// the operand is not a value of the type of the column, `price=lt.banana`
+    TypeMismatch {
+        column: String,
+        column_type: Type,
+        operand: Operand,
+    },
+    /// the operator makes no sense for the type of the column, `active=like.t*`
+    InvalidOperator {
+        column: String,
+        column_type: Type,
+        equality: Equality,
+    },
+    /// the column can not be NULL, `id=is.null` is always false
+    NotNullable(String),
+    /// a join without columns to a table which has no foreign key with the tables before it
+    UnresolvedJoin(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::MissingTable => write!(f, "the query has no table"),
+            Error::UnknownTable(ref table) => write!(f, "unknown table: {}", table),
+            Error::UnknownColumn(ref column) => write!(f, "unknown column: {}", column),
+            Error::AmbiguousColumn(ref column) => write!(f, "ambiguous column: {}", column),
+            Error::UnknownFunction(ref function) => write!(f, "unknown function: {}", function),
+            Error::TypeMismatch { ref column, column_type, ref operand } => {
+                write!(f, "{} is a {} column, it can not be compared to {:?}", column, column_type, operand)
+            }
+            Error::InvalidOperator { ref column, column_type, ref equality } => {
+                write!(f, "{:?} can not be used on the {} column {}", equality, column_type, column)
+            }
+            Error::NotNullable(ref column) => write!(f, "{} can not be null", column),
+            Error::UnresolvedJoin(ref table) => write!(f, "no foreign key to join {} on", table),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// check the query against the schema, Err holds every problem found
+pub fn validate(query: &Query, schema: &Schema) -> Result<(), Vec<Error>> {
+    let mut validator = Validator {
+        tables: vec![],
+        errors: vec![],
+    };
+    if query.from.is_empty() {
+        validator.errors.push(Error::MissingTable);
+    }
+    let joined = query.join.iter().map(|join| &join.table);
+    for operand in query.from.iter().chain(joined) {
+        match *operand {
+            Operand::Column(ref name) => {
+                match schema.table(name) {
+                    Some(table) => validator.tables.push(table),
+                    None => validator.errors.push(Error::UnknownTable(name.to_string())),
+                }
+            }
+            _ => validator.errors.push(Error::UnknownTable(format!("{:?}", operand))),
+        }
+    }
+    // columns of an unknown table would all be unknown too
+    if validator.errors.is_empty() {
+        for join in &query.join {
+            for column in join.column1.iter().chain(&join.column2) {
+                validator.column(column);
+            }
+        }
+        validator.filters(&query.filters);
+        for operand in &query.group_by {
+            validator.operand_type(operand);
+        }
+        validator.filters(&query.having);
+        for order in &query.order_by {
+            validator.operand_type(&order.operand);
+        }
+    }
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+struct Validator<'a> {
+    tables: Vec<&'a Table>,
+    errors: Vec<Error>,
+}
+
+impl<'a> Validator<'a> {
+    /// a column of one of the tables, `name` or `table.name`
+    fn column(&mut self, name: &str) -> Option<&'a Column> {
+        if let Some(dot) = name.find('.') {
+            let (table, column) = (&name[..dot], &name[dot + 1..]);
+            if let Some(table) = self.tables.iter().find(|t| t.name == table) {
+                let found = table.column(column);
+                if found.is_none() {
+                    self.errors.push(Error::UnknownColumn(name.to_string()));
+                }
+                return found;
+            }
+        }
+        let found: Vec<&'a Column> = self.tables.iter().filter_map(|table| table.column(name)).collect();
+        match found.len() {
+            0 => self.errors.push(Error::UnknownColumn(name.to_string())),
+            1 => return Some(found[0]),
+            _ => self.errors.push(Error::AmbiguousColumn(name.to_string())),
+        }
+        None
+    }
+
+    /// the type of a column or a function, None for a literal or when it is not known
+    fn operand_type(&mut self, operand: &Operand) -> Option<Type> {
+        match *operand {
+            Operand::Column(ref name) => self.column(name).map(|column| column.column_type),
+            Operand::Function(ref function) => self.function_type(function),
+            _ => None,
+        }
+    }
+
+    fn function_type(&mut self, function: &Function) -> Option<Type> {
+        let mut params = vec![];
+        for param in &function.params {
+            match *param {
+                Operand::Value(ref v) if v == "*" => params.push(None),
+                _ => params.push(self.operand_type(param)),
+            }
+        }
+        let param = params.first().cloned().unwrap_or(None);
+        match function.function.to_lowercase().as_str() {
+            "count" => Some(Type::Integer),
+            "sum" | "avg" => {
+                match param {
+                    Some(t) if !t.is_numeric() => {
+                        self.errors.push(Error::TypeMismatch {
+                            column: function.column_name(),
+                            column_type: t,
+                            operand: function.params[0].clone(),
+                        });
+                        None
+                    }
+                    _ => Some(Type::Number),
+                }
+            }
+            "min" | "max" => param,
+            _ => {
+                self.errors.push(Error::UnknownFunction(function.function.to_string()));
+                None
+            }
+        }
+    }
+
+    fn filters(&mut self, filters: &[Filter]) {
+        if let Some(expr) = Expr::from_filters(filters) {
+            for condition in expr.conditions() {
+                self.condition(condition);
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Condition) {
+        let name = match condition.left {
+            Operand::Column(ref name) => name.to_string(),
+            Operand::Function(ref function) => function.column_name(),
+            _ => format!("{:?}", condition.left),
+        };
+        let left = self.operand_type(&condition.left);
+        let column = self.nullable(&condition.left);
+        let right = match condition.right {
+            Operand::Column(_) | Operand::Function(_) => self.operand_type(&condition.right),
+            _ => None,
+        };
+        let column_type = match left {
+            Some(column_type) => column_type,
+            None => return,
+        };
+        if !column_type.supports(&condition.equality) {
+            self.errors.push(Error::InvalidOperator {
+                column: name,
+                column_type,
+                equality: condition.equality.clone(),
+            });
+            return;
+        }
+        let mismatch = |operand: &Operand| Error::TypeMismatch {
+            column: name.to_string(),
+            column_type,
+            operand: operand.clone(),
+        };
+        match condition.equality {
+            Equality::IS | Equality::IS_NOT => {
+                match condition.right {
+                    Operand::Value(ref v) if v.eq_ignore_ascii_case("null") => {
+                        if column == Some(false) {
+                            self.errors.push(Error::NotNullable(name.to_string()));
+                        }
+                    }
+                    Operand::Boolean(_) if column_type == Type::Bool => (),
+                    ref operand => self.errors.push(mismatch(operand)),
+                }
+            }
+            Equality::IN | Equality::NOT_IN => {
+                for item in condition.right.list_items() {
+                    if !column_type.accepts(&item) {
+                        self.errors.push(mismatch(&item));
+                    }
+                }
+            }
+            // the pattern and the search are text whatever they look like
+            Equality::LIKE | Equality::ILIKE | Equality::ST | Equality::MATCH | Equality::IMATCH => (),
+            Equality::FTS(_) | Equality::PLFTS(_) | Equality::PHFTS(_) | Equality::WFTS(_) => (),
+            _ => {
+                let fits = match right {
+                    Some(right) => column_type.compatible(right),
+                    None => {
+                        match condition.right {
+                            Operand::Column(_) | Operand::Function(_) => true,
+                            ref operand => column_type.accepts(operand),
+                        }
+                    }
+                };
+                if !fits {
+                    self.errors.push(mismatch(&condition.right));
+                }
+            }
+        }
+    }
+
+    /// whether a column can be NULL, None for anything but a known column.
+    /// The column was looked up already, so no error is added here
+    fn nullable(&self, operand: &Operand) -> Option<bool> {
+        let name = match *operand {
+            Operand::Column(ref name) => name,
+            _ => return None,
+        };
+        let qualified = name.find('.').and_then(|dot| {
+            self.tables.iter().find(|t| t.name == name[..dot]).and_then(|t| t.column(&name[dot + 1..]))
+        });
+        let mut found = self.tables.iter().filter_map(|table| table.column(name));
+        qualified.or_else(|| found.next()).map(|column| column.nullable)
+    }
+}
+
+#[cfg(test)]
+fn users() -> Schema {
+    let column = |name: &str, column_type: Type, nullable: bool| Column {
+        name: name.to_string(),
+        column_type,
+        nullable,
+    };
+    Schema {
+        tables: vec![Table {
+                         name: "users".to_string(),
+                         columns: vec![column("id", Type::Integer, false),
+                                       column("name", Type::Text, false),
+                                       column("price", Type::Number, true),
+                                       column("active", Type::Bool, true)],
+                         foreign_keys: vec![],
+                     },
+                     Table {
+                         name: "orders".to_string(),
+                         columns: vec![column("id", Type::Integer, false),
+                                       column("total", Type::Number, false),
+                                       column("user_id", Type::Integer, false)],
+                         foreign_keys: vec![ForeignKey {
+                                                columns: vec!["user_id".to_string()],
+                                                table: "users".to_string(),
+                                                references: vec!["id".to_string()],
+                                            }],
+                     }],
+    }
+}
+
+#[cfg(test)]
+fn errors(input: &str) -> Vec<Error> {
+    let query = ::parse_query(input).unwrap();
+    validate(&query, &users()).err().unwrap_or_default()
+}
+
+#[test]
+fn test_valid() {
+    assert_eq!(errors("from=users&price=lt.10.5&id=in.1,2&name=st.jo&active=is.true&price=is.null"),
+               vec![]);
+    assert_eq!(errors("from=users&name=eq.134&order_by=users.name.desc"), vec![]);
+    assert_eq!(errors("from=users&group_by=active&having=sum(price)=gt.100&order_by=count(*)"),
+               vec![]);
+}
+
+#[test]
+fn test_invalid() {
+    assert_eq!(errors("from=users&price=lt.banana&nonexistent=eq.1&active=like.t*|id=eq.1.5"),
+               vec![Error::TypeMismatch {
+                        column: "price".to_string(),
+                        column_type: Type::Number,
+                        operand: Operand::Value("banana".to_string()),
+                    },
+                    Error::UnknownColumn("nonexistent".to_string()),
+                    Error::InvalidOperator {
+                        column: "active".to_string(),
+                        column_type: Type::Bool,
+                        equality: Equality::LIKE,
+                    },
+                    Error::TypeMismatch {
+                        column: "id".to_string(),
+                        column_type: Type::Integer,
+                        operand: Operand::Number(1.5),
+                    }]);
+    assert_eq!(errors("from=users&id=is.null&id=in.1,x&order_by=missing"),
+               vec![Error::NotNullable("id".to_string()),
+                    Error::TypeMismatch {
+                        column: "id".to_string(),
+                        column_type: Type::Integer,
+                        operand: Operand::Value("x".to_string()),
+                    },
+                    Error::UnknownColumn("missing".to_string())]);
+    assert_eq!(errors("from=users,orders&id=eq.1&orders.total=gt.1&users.nothing=eq.1"),
+               vec![Error::AmbiguousColumn("id".to_string()),
+                    Error::UnknownColumn("users.nothing".to_string())]);
+    assert_eq!(errors("from=users&group_by=name&having=sum(name)=gt.1&order_by=median(price)"),
+               vec![Error::TypeMismatch {
+                        column: "sum(name)".to_string(),
+                        column_type: Type::Text,
+                        operand: Operand::Column("name".to_string()),
+                    },
+                    Error::UnknownFunction("median".to_string())]);
+}
+
+#[test]
+fn test_unknown_table() {
+    assert_eq!(errors("from=nothing&whatever=eq.1"), vec![Error::UnknownTable("nothing".to_string())]);
+    assert_eq!(errors("id=eq.1"), vec![Error::MissingTable]);
+}
+
+#[test]
+fn test_resolve_joins() {
+    use {Join, JoinType};
+    let join = |table: &str| Join {
+        modifier: None,
+        join_type: Some(JoinType::INNER),
+        table: Operand::Column(table.to_string()),
+        column1: vec![],
+        column2: vec![],
+    };
+    let schema = users();
+    let mut query = Query {
+        from: vec![Operand::Column("users".to_string())],
+        join: vec![join("orders")],
+        ..Default::default()
+    };
+    schema.resolve_joins(&mut query).unwrap();
+    assert_eq!(query.join[0].column1, vec!["users.id"]);
+    assert_eq!(query.join[0].column2, vec!["orders.user_id"]);
+    assert_eq!(validate(&query, &schema), Ok(()));
+    assert_eq!(schema.join_columns("orders", "users"),
+               Some((vec!["orders.user_id".to_string()], vec!["users.id".to_string()])));
+    query.join.push(join("users"));
+    query.join.push(join("nothing"));
+    assert_eq!(schema.resolve_joins(&mut query), Err(Error::UnresolvedJoin("nothing".to_string())));
+}
+
+#[test]
+fn test_from_sqlite() {
+    let connection = Connection::open_in_memory().unwrap();
+    connection.execute_batch("CREATE TABLE depts (id INTEGER PRIMARY KEY, name VARCHAR(20) NOT NULL);
+                              CREATE TABLE users (id INTEGER PRIMARY KEY, dept_id INT REFERENCES depts,
+                                                  salary DECIMAL(10, 2), active BOOLEAN, born DATE, data);")
+        .unwrap();
+    let schema = from_sqlite(&connection).unwrap();
+    let users = schema.table("users").unwrap();
+    let types: Vec<Type> = users.columns.iter().map(|c| c.column_type).collect();
+    assert_eq!(types,
+               vec![Type::Integer, Type::Integer, Type::Number, Type::Bool, Type::Text, Type::Text]);
+    assert!(!users.column("id").unwrap().nullable);
+    assert!(users.column("dept_id").unwrap().nullable);
+    assert!(!schema.table("depts").unwrap().column("name").unwrap().nullable);
+    assert_eq!(users.foreign_keys,
+               vec![ForeignKey {
+                        columns: vec!["dept_id".to_string()],
+                        table: "depts".to_string(),
+                        references: vec!["id".to_string()],
+                    }]);
+}
+
+#[test]
+fn test_from_file() {
+    let toml = r#"
+        [tables.users]
+        columns = { id = { type = "integer", nullable = false }, name = "text", dept_id = "integer" }
+        foreign_keys = [{ columns = ["dept_id"], table = "depts", references = ["id"] }]
+
+        [tables.depts]
+        columns = { id = { type = "integer", nullable = false } }
+    "#;
+    let schema = from_toml(toml).unwrap();
+    let json = r#"{"tables": {
+        "users": {"columns": {"id": {"type": "integer", "nullable": false}, "name": "text", "dept_id": "integer"},
+                  "foreign_keys": [{"columns": ["dept_id"], "table": "depts", "references": ["id"]}]},
+        "depts": {"columns": {"id": {"type": "integer", "nullable": false}}}
+    }}"#;
+    let users = schema.table("users").unwrap();
+    assert_eq!(users.column("id").map(|c| (c.column_type, c.nullable)), Some((Type::Integer, false)));
+    assert_eq!(users.column("name").map(|c| (c.column_type, c.nullable)), Some((Type::Text, true)));
+    assert_eq!(schema.join_columns("depts", "users"),
+               Some((vec!["depts.id".to_string()], vec!["users.dept_id".to_string()])));
+    let loaded = from_json(json).unwrap();
+    for table in &schema.tables {
+        assert_eq!(loaded.table(&table.name).map(|t| t.columns.len()), Some(table.columns.len()));
+    }
+    assert_eq!(loaded.table("users").unwrap().foreign_keys, users.foreign_keys);
+    assert_eq!(from_json(r#"{"tables": {"a": {"columns": {"b": "date"}}}}"#).unwrap_err().to_string(),
+               "invalid schema: a.b: unknown type: date, expecting boolean, integer, number or text");
+}
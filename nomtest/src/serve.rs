@@ -0,0 +1,471 @@
+//! A local http server exposing the tables of a sqlite database
+//!
+//! `GET /users?age=lt.20&order_by=name` answers with the json array of the
+//! rows, the `Content-Range` header tells which rows they are out of how many,
+//! `0-9/42`, or `*/42` when there is none. A query which does not parse or is over the
+//! `Limits`, or which the database does not accept, is a 400 and an unknown table a 404,
+//! the body of an error is `{"message": "..."}`. An escaped `&`, `%26`, is part of
+//! a value rather than a separator: `/users?name=eq.R%26D`, and a `+` is a space,
+//! `/users?due=lt.today%2B1w` for a `+`.
+//!
+//! A server with a `Signer` pages by keyset: the `Next-Cursor` and `Prev-Cursor`
+//! headers of an ordered response are the cursors of its last and first rows,
+//! for the `after` and `before` of the next request. The `Content-Range` of such
+//! a page is still its place among all the rows.
+
+use std::error;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use regex::Regex;
+use rusqlite::config::DbConfig;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{self, ValueRef};
+use rusqlite::{self, params_from_iter, Connection, OpenFlags};
+use serde_json::{self, Map};
+use tiny_http;
+
+use json;
+use keyset::{self, Resolved, Signer};
+use limits::Limits;
+use regexp;
+use row::Value;
+use sql::{self, Statement};
+use {Filter, Operand, Query, Range};
+
+#[derive(Debug)]
+pub enum Error {
+    Sqlite(rusqlite::Error),
+    Http(Box<dyn error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Sqlite(ref e) => write!(f, "{}", e),
+            Error::Http(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    fn error(status: u16, message: &str) -> Self {
+        let mut body = Map::new();
+        body.insert("message".to_string(), serde_json::Value::from(message));
+        Response {
+            status,
+            headers: vec![],
+            body: serde_json::Value::Object(body).to_string(),
+        }
+    }
+}
+
+pub struct Server {
+    http: tiny_http::Server,
+    connection: Connection,
+    limits: Limits,
+    signer: Option<Signer>,
+}
+
+impl Server {
+    /// open the database read only and listen on the address, `127.0.0.1:8080`
+    pub fn open<P: AsRef<Path>>(database: P, address: &str) -> Result<Server, Error> {
+        let connection = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        configure(&connection)?;
+        let http = tiny_http::Server::http(address).map_err(Error::Http)?;
+        Ok(Server {
+            http,
+            connection,
+            limits: Limits::default(),
+            signer: None,
+        })
+    }
+
+    /// the limits the queries are parsed with, `Limits::default()` unless told otherwise
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// the signer of the cursors, without one `after` and `before` are refused
+    pub fn with_signer(mut self, signer: Signer) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// the port listened on, which the system picks when the address has port 0
+    pub fn port(&self) -> Option<u16> {
+        self.http.server_addr().to_ip().map(|address| address.port())
+    }
+
+    /// answer the requests, one at a time, until the process ends
+    pub fn run(&self) {
+        for request in self.http.incoming_requests() {
+            let method = request.method().as_str().to_string();
+            let response = handle(&self.connection, &self.limits, self.signer.as_ref(), &method, request.url());
+            let mut http_response = tiny_http::Response::from_string(response.body)
+                .with_status_code(response.status);
+            let headers = Some(("Content-Type".to_string(), "application/json".to_string()))
+                .into_iter()
+                .chain(response.headers);
+            for (name, value) in headers {
+                if let Ok(header) = tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes()) {
+                    http_response.add_header(header);
+                }
+            }
+            // the client is gone, there is no one left to tell
+            let _ = request.respond(http_response);
+        }
+    }
+}
+
+/// LIKE is case sensitive, and a double quoted name is always a column,
+/// sqlite would otherwise take an unknown `"column"` for a string. `REGEXP`
+/// calls the `regexp` function, which sqlite does not have on its own
+pub fn configure(connection: &Connection) -> rusqlite::Result<()> {
+    connection.pragma_update(None, "case_sensitive_like", true)?;
+    connection.set_db_config(DbConfig::SQLITE_DBCONFIG_DQS_DML, false)?;
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    connection.create_scalar_function("regexp", 2, flags, |context| {
+        // `text REGEXP pattern` is `regexp(pattern, text)`, the pattern is compiled once a statement
+        let regex: Arc<Regex> = context.get_or_create_aux(0, |pattern| {
+            let pattern = pattern.as_str().map_err(|e| Box::new(e) as Box<dyn error::Error + Send + Sync>)?;
+            // imatch is rendered with `(?i)` in front of the pattern
+            let compiled = match pattern.strip_prefix("(?i)") {
+                Some(pattern) => regexp::compile(pattern, true),
+                None => regexp::compile(pattern, false),
+            };
+            compiled.map_err(|e| Box::new(e) as Box<dyn error::Error + Send + Sync>)
+        })?;
+        let text = match context.get_raw(1) {
+            ValueRef::Null => return Ok(None),
+            ValueRef::Integer(i) => i.to_string(),
+            ValueRef::Real(f) => f.to_string(),
+            ValueRef::Text(text) | ValueRef::Blob(text) => String::from_utf8_lossy(text).into_owned(),
+        };
+        Ok(Some(regex.is_match(&text)))
+    })?;
+    Ok(())
+}
+
+/// the response to a request for a url, `/users?age=lt.20`
+pub fn handle(connection: &Connection, limits: &Limits, signer: Option<&Signer>, method: &str, url: &str) -> Response {
+    if method != "GET" {
+        let mut response = Response::error(405, &format!("{} is not allowed", method));
+        response.headers.push(("Allow".to_string(), "GET".to_string()));
+        return response;
+    }
+    let (path, query_string) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => (url, ""),
+    };
+    let table = percent_decode(path.trim_matches('/'));
+    if table.is_empty() || table.contains('/') || !table_exists(connection, &table) {
+        return Response::error(404, &format!("no such table: {}", table));
+    }
+    // an `&` which is escaped is part of a value, it is decoded once the parameters are parsed.
+    // A `+` is a space as in a form, `%2B` is a `+`
+    let input: Vec<String> = query_string.split('&')
+        .map(|param| percent_decode_except(&param.replace('+', " "), b"%&"))
+        .collect();
+    let input = input.join("&");
+    let mut query = match ::parse_query_with_limits(&input, limits) {
+        Ok(query) => query,
+        Err(e) => return Response::error(400, &format!("{}: {}", input, e)),
+    };
+    decode_values(&mut query);
+    query.from = vec![Operand::Column(table)];
+    // the cursor is a filter of the select, the count is of all the pages
+    let resolved = match (signer, &query.range) {
+        (Some(signer), _) => keyset::resolve(&query, signer),
+        (None, &Some(Range::Keyset(_))) => return Response::error(400, "this server has no cursors"),
+        (None, _) => Ok(Resolved { query: query.clone(), reversed: false }),
+    };
+    let resolved = match resolved {
+        Ok(resolved) => resolved,
+        Err(e) => return Response::error(400, &e.to_string()),
+    };
+    let (select, count) = match (sql::to_select(&resolved.query), sql::to_count(&query)) {
+        (Ok(select), Ok(count)) => (select, count),
+        (Err(e), _) | (_, Err(e)) => return Response::error(400, &e.to_string()),
+    };
+    let total = match count_rows(connection, &count) {
+        Ok(total) => total,
+        Err(e) => return Response::error(400, &e.to_string()),
+    };
+    let mut rows = match select_rows(connection, &select) {
+        Ok(rows) => rows,
+        Err(e) => return Response::error(400, &e.to_string()),
+    };
+    resolved.restore_order(&mut rows);
+    let offset = match query.range {
+        Some(Range::Keyset(_)) => match keyset_offset(connection, &resolved, total, rows.len()) {
+            Ok(offset) => offset,
+            Err(e) => return Response::error(400, &e.to_string()),
+        },
+        Some(ref range) => range.bounds().map_or(0, |bounds| bounds.0),
+        None => 0,
+    };
+    let content_range = if rows.is_empty() {
+        format!("*/{}", total)
+    } else {
+        format!("{}-{}/{}", offset, offset + rows.len() - 1, total)
+    };
+    let mut headers = vec![("Content-Range".to_string(), content_range)];
+    if let (Some(signer), Some(first), Some(last)) = (signer, rows.first(), rows.last()) {
+        if !query.order_by.is_empty() {
+            headers.push(("Next-Cursor".to_string(), signer.cursor(&query.order_by, last)));
+            headers.push(("Prev-Cursor".to_string(), signer.cursor(&query.order_by, first)));
+        }
+    }
+    Response {
+        status: 200,
+        headers,
+        body: serde_json::Value::Array(rows).to_string(),
+    }
+}
+
+fn table_exists(connection: &Connection, table: &str) -> bool {
+    connection.query_row("SELECT count(*) FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?",
+                   [table],
+                   |row| row.get::<_, i64>(0))
+        .map(|count| count > 0)
+        .unwrap_or(false)
+}
+
+fn count_rows(connection: &Connection, statement: &Statement) -> rusqlite::Result<usize> {
+    connection.query_row(&statement.sql, params_from_iter(params(statement)), |row| row.get::<_, i64>(0))
+        .map(|count| count.max(0) as usize)
+}
+
+/// the number of rows before a keyset page, from the count of the rows on the
+/// other side of its cursor: all but those after it, or those before it but the page
+fn keyset_offset(connection: &Connection, resolved: &Resolved, total: usize, rows: usize) -> Result<usize, String> {
+    let count = sql::to_count(&resolved.query).map_err(|e| e.to_string())?;
+    let beyond = count_rows(connection, &count).map_err(|e| e.to_string())?;
+    Ok(if resolved.reversed {
+        beyond.saturating_sub(rows)
+    } else {
+        total.saturating_sub(beyond)
+    })
+}
+
+fn select_rows(connection: &Connection, statement: &Statement) -> rusqlite::Result<Vec<serde_json::Value>> {
+    let mut prepared = connection.prepare(&statement.sql)?;
+    let columns: Vec<String> = prepared.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = prepared.query(params_from_iter(params(statement)))?;
+    let mut objects = vec![];
+    while let Some(row) = rows.next()? {
+        let mut object = Map::new();
+        for (i, column) in columns.iter().enumerate() {
+            object.insert(column.to_string(), json_value(row.get_ref(i)?));
+        }
+        objects.push(serde_json::Value::Object(object));
+    }
+    Ok(objects)
+}
+
+/// whole numbers are bound as integers, booleans as 0 and 1 like sqlite stores them
+fn params(statement: &Statement) -> Vec<types::Value> {
+    statement.params
+        .iter()
+        .map(|value| match *value {
+            Value::Null => types::Value::Null,
+            Value::Bool(b) => types::Value::Integer(b as i64),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                types::Value::Integer(n as i64)
+            }
+            Value::Number(n) => types::Value::Real(n),
+            Value::Text(ref s) => types::Value::Text(s.to_string()),
+        })
+        .collect()
+}
+
+/// blobs are read as text
+fn json_value(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => serde_json::Value::from(i),
+        ValueRef::Real(f) => json::number(f),
+        ValueRef::Text(s) | ValueRef::Blob(s) => serde_json::Value::from(String::from_utf8_lossy(s).to_string()),
+    }
+}
+
+/// the escaped `&` and `%` left in the values of the query, once it is parsed
+fn decode_values(query: &mut Query) {
+    fn decode(operand: &mut Operand) {
+        match *operand {
+            Operand::Value(ref mut value) if value.contains('%') => *value = percent_decode(value),
+            Operand::List(ref mut items) => items.iter_mut().for_each(decode),
+            _ => (),
+        }
+    }
+    fn decode_filter(filter: &mut Filter) {
+        decode(&mut filter.condition.right);
+        filter.sub_filters.iter_mut().for_each(decode_filter);
+    }
+    query.filters.iter_mut().chain(query.having.iter_mut()).for_each(decode_filter);
+    for equation in &mut query.equations {
+        decode(&mut equation.right);
+    }
+}
+
+/// `%20` is a space, a `%` which is not followed by two hex digits is kept as it is
+fn percent_decode(s: &str) -> String {
+    percent_decode_except(s, b"")
+}
+
+/// decode the escapes but those of the characters kept, which stay escaped
+fn percent_decode_except(s: &str, kept: &[u8]) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok())
+        } else {
+            None
+        };
+        match hex {
+            Some(byte) if !kept.contains(&byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+fn database() -> Connection {
+    let connection = Connection::open_in_memory().unwrap();
+    configure(&connection).unwrap();
+    connection.execute_batch("CREATE TABLE users (name TEXT, dept TEXT, age INTEGER, active BOOLEAN);
+                              INSERT INTO users VALUES ('John', 'sales', 30, 1), ('Jane', 'tech', 25, 0),
+                                                       ('Joe', 'sales', NULL, 1), ('技術通報', 'tech', 41, 1);")
+        .unwrap();
+    connection
+}
+
+#[test]
+fn test_handle() {
+    let connection = database();
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?age=lt.40&order_by=name.desc&limit=1&offset=1");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "1-1/2".to_string())]);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&response.body).unwrap(),
+               serde_json::from_str::<serde_json::Value>(
+                   r#"[{"name": "Jane", "dept": "tech", "age": 25, "active": 0}]"#).unwrap());
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=eq.%E6%8A%80%E8%A1%93%E9%80%9A%E5%A0%B1");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "0-0/1".to_string())]);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=like.j*");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "*/0".to_string())]);
+    assert_eq!(response.body, "[]");
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?group_by=dept&order_by=dept");
+    assert_eq!(response.body, r#"[{"dept":"sales","count(*)":2},{"dept":"tech","count(*)":2}]"#);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=match.^Jo(hn|e)$&order_by=name");
+    assert_eq!(response.body, r#"[{"name":"Joe","dept":"sales","age":null,"active":1},{"name":"John","dept":"sales","age":30,"active":1}]"#);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=imatch.^JA|通");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "0-1/2".to_string())]);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?age=match.^4");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "0-0/1".to_string())]);
+    // an escaped `&` is in the value, an escaped `|` still separates the conditions
+    connection.execute("INSERT INTO users VALUES ('R&D', 'tech', 50, 1), ('100%', 'tech', 60, 1)", []).unwrap();
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=eq.R%26D");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "0-0/1".to_string())]);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=in.R%26D,100%25&dept=eq.tech");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "0-1/2".to_string())]);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=eq.John%7Cage=gt.45");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "0-2/3".to_string())]);
+}
+
+#[test]
+fn test_handle_cursors() {
+    let connection = database();
+    let signer = Signer::new(b"secret");
+    let get = |url: &str| handle(&connection, &Limits::default(), Some(&signer), "GET", url);
+    let header = |response: &Response, name: &str| {
+        response.headers.iter().find(|header| header.0 == name).map(|header| header.1.to_string()).unwrap()
+    };
+    let names = |response: &Response| {
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&response.body).unwrap();
+        rows.iter().map(|row| row["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+    };
+    let first = get("/users?order_by=name&limit=2");
+    assert_eq!(names(&first), vec!["Jane", "Joe"]);
+    let second = get(&format!("/users?order_by=name&after={}&limit=2", header(&first, "Next-Cursor")));
+    assert_eq!(second.status, 200);
+    assert_eq!(names(&second), vec!["John", "技術通報"]);
+    assert_eq!(header(&second, "Content-Range"), "2-3/4");
+    let back = get(&format!("/users?order_by=name&before={}&limit=1", header(&second, "Prev-Cursor")));
+    assert_eq!(names(&back), vec!["Joe"]);
+    assert_eq!(header(&back, "Content-Range"), "1-1/4");
+    let past = get(&format!("/users?order_by=name&after={}&limit=2", header(&second, "Next-Cursor")));
+    assert_eq!(header(&past, "Content-Range"), "*/4");
+    let other = get(&format!("/users?order_by=age&after={}&limit=2", header(&first, "Next-Cursor")));
+    assert_eq!(other.body, r#"{"message":"the cursor is for order_by=name.asc.nulls_last"}"#);
+    // all the rows after the cursor are more than a page
+    let unlimited = get(&format!("/users?order_by=name&after={}", header(&first, "Next-Cursor")));
+    assert_eq!(unlimited.status, 400);
+    assert!(unlimited.body.ends_with(r#"query too large: a page of more than 1000 rows"}"#), "{}", unlimited.body);
+    let unsigned = handle(&connection, &Limits::default(), None, "GET", "/users?order_by=name&after=abc.def&limit=2");
+    assert_eq!(unsigned.body, r#"{"message":"this server has no cursors"}"#);
+}
+
+#[test]
+fn test_handle_errors() {
+    let connection = database();
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?age=lt.20&&");
+    assert_eq!(response.status, 400);
+    assert_eq!(response.body, r#"{"message":"age=lt.20&&: syntax error at position 10"}"#);
+    assert_eq!(handle(&connection, &Limits::default(), None, "GET", "/users?missing=eq.1").status, 400);
+    assert_eq!(handle(&connection, &Limits::default(), None, "GET", "/users?order_by=missing").status, 400);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=fts.ann");
+    assert_eq!(response.status, 400);
+    assert_eq!(response.body, r#"{"message":"the full text search in sqlite is not supported in sql"}"#);
+    assert_eq!(handle(&connection, &Limits::default(), None, "GET", "/nothing").status, 404);
+    assert_eq!(handle(&connection, &Limits::default(), None, "GET", "/").status, 404);
+    assert_eq!(handle(&connection, &Limits::default(), None, "POST", "/users").status, 405);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?limit=5000");
+    assert_eq!(response.status, 400);
+    assert_eq!(response.body, r#"{"message":"limit=5000: query too large: a page of more than 1000 rows"}"#);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=match.(a%2B)%2B");
+    assert_eq!(response.status, 400);
+    assert_eq!(response.body, r#"{"message":"name=match.(a+)+: a repetition can not be repeated"}"#);
+}
+
+#[test]
+fn test_percent_decode() {
+    assert_eq!(percent_decode("a%20b%2"), "a b%2");
+    assert_eq!(percent_decode("%zz%41"), "%zzA");
+    assert_eq!(percent_decode("%E6%8A%80"), "技");
+    assert_eq!(percent_decode_except("a%26b%25%20", b"%&"), "a%26b%25 ");
+    let connection = database();
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?dept=in.sales,tech+team");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "0-1/2".to_string())]);
+    let response = handle(&connection, &Limits::default(), None, "GET", "/users?name=like.J*%2Bx");
+    assert_eq!(response.headers, vec![("Content-Range".to_string(), "*/0".to_string())]);
+}
@@ -0,0 +1,364 @@
+//! Simplification of the boolean expression of the filters, and its normal forms
+//!
+//! The nested ANDs in an AND and ORs in an OR are flattened, duplicates and
+//! absorbed terms are removed, `a=eq.1&(a=eq.1|b=eq.2)` is `a=eq.1`, and the
+//! bounds on a column are merged: `a=gt.5&a=gt.3` is `a=gt.5`, `a=gt.5|a=gt.3`
+//! is `a=gt.3`. Only the bounds of numbers are merged, the order and the
+//! equality of texts depend on the collation of the database: under a case
+//! insensitive one `name=eq.a&name=eq.A` matches `A`. An AND which no row can
+//! satisfy, `a=eq.1&a=eq.2`, is a contradiction, an OR which every row
+//! satisfies, `a=is.null|a=is_not.null`, is a tautology.
+//!
+//! A row matches a filter only when it is true, so a condition which is unknown
+//! for a row counts as false. `a=lt.5|a=gte.5` is not a tautology, a NULL or a
+//! text which does not read as a number is neither.
+
+use std::cmp::Ordering;
+
+use expr::Expr;
+use row::Value;
+use {Condition, Equality, Filter, Operand};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Simplified {
+    /// every row matches, the filter can be left out
+    Tautology,
+    /// no row can match
+    Contradiction,
+    Expr(Expr),
+}
+
+/// the simplified expression of all the filters of a query
+pub fn simplify_filters(filters: &[Filter]) -> Simplified {
+    match Expr::from_filters(filters) {
+        Some(expr) => simplify(&expr),
+        None => Simplified::Tautology,
+    }
+}
+
+pub fn simplify(expr: &Expr) -> Simplified {
+    match *expr {
+        Expr::Condition(ref condition) => Simplified::Expr(Expr::Condition(condition.clone())),
+        Expr::And(ref exprs) => {
+            let mut children = vec![];
+            for expr in exprs {
+                match simplify(expr) {
+                    Simplified::Contradiction => return Simplified::Contradiction,
+                    Simplified::Tautology => (),
+                    Simplified::Expr(Expr::And(inner)) => children.extend(inner),
+                    Simplified::Expr(expr) => children.push(expr),
+                }
+            }
+            let children = match merge_bounds(absorb(dedup(children), true), true) {
+                Some(children) => children,
+                None => return Simplified::Contradiction,
+            };
+            match children.len() {
+                0 => Simplified::Tautology,
+                1 => Simplified::Expr(children.into_iter().next().unwrap()),
+                _ => Simplified::Expr(Expr::And(children)),
+            }
+        }
+        Expr::Or(ref exprs) => {
+            let mut children = vec![];
+            for expr in exprs {
+                match simplify(expr) {
+                    Simplified::Tautology => return Simplified::Tautology,
+                    Simplified::Contradiction => (),
+                    Simplified::Expr(Expr::Or(inner)) => children.extend(inner),
+                    Simplified::Expr(expr) => children.push(expr),
+                }
+            }
+            let children = absorb(dedup(children), false);
+            if has_complements(&children) {
+                return Simplified::Tautology;
+            }
+            let children = merge_bounds(children, false).unwrap_or_default();
+            match children.len() {
+                0 => Simplified::Contradiction,
+                1 => Simplified::Expr(children.into_iter().next().unwrap()),
+                _ => Simplified::Expr(Expr::Or(children)),
+            }
+        }
+    }
+}
+
+/// the expression as an OR of ANDs of conditions, None when it would have more
+/// than `max_terms` ANDs. Every OR inside an AND multiplies the number of terms
+pub fn to_dnf(expr: &Expr, max_terms: usize) -> Option<Expr> {
+    let terms = normal_terms(expr, true, max_terms)?;
+    Some(build(terms, Expr::And, Expr::Or))
+}
+
+/// the expression as an AND of ORs of conditions, None when it would have more
+/// than `max_terms` ORs. Every AND inside an OR multiplies the number of terms
+pub fn to_cnf(expr: &Expr, max_terms: usize) -> Option<Expr> {
+    let terms = normal_terms(expr, false, max_terms)?;
+    Some(build(terms, Expr::Or, Expr::And))
+}
+
+/// the terms of the normal form, each a list of conditions. For the disjunctive
+/// form an OR joins the terms of its children and an AND distributes over them,
+/// for the conjunctive form it is the other way around
+fn normal_terms(expr: &Expr, dnf: bool, max_terms: usize) -> Option<Vec<Vec<Condition>>> {
+    let (joined, distributed) = match *expr {
+        Expr::Condition(ref condition) => return Some(vec![vec![condition.clone()]]),
+        Expr::Or(ref exprs) if dnf => (Some(exprs), None),
+        Expr::And(ref exprs) if !dnf => (Some(exprs), None),
+        Expr::And(ref exprs) | Expr::Or(ref exprs) => (None, Some(exprs)),
+    };
+    let mut terms: Vec<Vec<Condition>> = vec![];
+    if let Some(exprs) = joined {
+        for expr in exprs {
+            terms.extend(normal_terms(expr, dnf, max_terms)?);
+            if terms.len() > max_terms {
+                return None;
+            }
+        }
+    }
+    if let Some(exprs) = distributed {
+        terms.push(vec![]);
+        for expr in exprs {
+            let child = normal_terms(expr, dnf, max_terms)?;
+            if terms.len().saturating_mul(child.len()) > max_terms {
+                return None;
+            }
+            terms = terms.iter()
+                .flat_map(|term| {
+                    child.iter().map(move |other| {
+                        let mut term = term.clone();
+                        term.extend(other.iter().cloned());
+                        term
+                    })
+                })
+                .collect();
+        }
+    }
+    Some(dedup(terms.into_iter().map(dedup).collect()))
+}
+
+fn build<F, G>(terms: Vec<Vec<Condition>>, inner: F, outer: G) -> Expr
+    where F: Fn(Vec<Expr>) -> Expr,
+          G: Fn(Vec<Expr>) -> Expr
+{
+    let mut exprs: Vec<Expr> = terms.into_iter()
+        .map(|term| {
+            let mut conditions: Vec<Expr> = term.into_iter().map(Expr::Condition).collect();
+            if conditions.len() == 1 { conditions.remove(0) } else { inner(conditions) }
+        })
+        .collect();
+    if exprs.len() == 1 { exprs.remove(0) } else { outer(exprs) }
+}
+
+/// the items without the later duplicates, in the same order
+fn dedup<T: PartialEq>(items: Vec<T>) -> Vec<T> {
+    let mut unique: Vec<T> = vec![];
+    for item in items {
+        if !unique.contains(&item) {
+            unique.push(item);
+        }
+    }
+    unique
+}
+
+/// `a&(a|b)` is `a` and `a|(a&b)` is `a`, a nested term goes when one of its
+/// children is also a child of the outer one
+fn absorb(children: Vec<Expr>, and: bool) -> Vec<Expr> {
+    let absorbed: Vec<bool> = children.iter()
+        .map(|child| match (child, and) {
+            (Expr::Or(inner), true) | (Expr::And(inner), false) => {
+                inner.iter().any(|expr| children.contains(expr))
+            }
+            _ => false,
+        })
+        .collect();
+    children.into_iter().zip(absorbed).filter(|&(_, absorbed)| !absorbed).map(|(child, _)| child).collect()
+}
+
+/// `x IS NULL` and `x IS NOT NULL` in the same OR, `is` with NULL or a boolean is never unknown
+fn has_complements(children: &[Expr]) -> bool {
+    let definite = |right: &Operand| match *right {
+        Operand::Value(ref v) => v.eq_ignore_ascii_case("null"),
+        Operand::Boolean(_) => true,
+        _ => false,
+    };
+    children.iter().any(|child| match *child {
+        Expr::Condition(ref condition) if condition.equality == Equality::IS && definite(&condition.right) => {
+            children.contains(&Expr::Condition(Condition {
+                left: condition.left.clone(),
+                equality: Equality::IS_NOT,
+                right: condition.right.clone(),
+            }))
+        }
+        _ => false,
+    })
+}
+
+/// which side of a value a bound keeps
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+enum Side {
+    Lower,
+    Upper,
+    Equal,
+}
+
+/// a comparison of a column with a number
+struct Bound {
+    column: String,
+    side: Side,
+    strict: bool,
+    value: Value,
+}
+
+impl Bound {
+    fn of(expr: &Expr) -> Option<Bound> {
+        let condition = match *expr {
+            Expr::Condition(ref condition) => condition,
+            _ => return None,
+        };
+        let column = match condition.left {
+            Operand::Column(ref column) => column.to_string(),
+            _ => return None,
+        };
+        let value = match condition.right {
+            Operand::Number(n) if !n.is_nan() => Value::Number(n),
+            _ => return None,
+        };
+        let (side, strict) = match condition.equality {
+            Equality::EQ => (Side::Equal, false),
+            Equality::GT => (Side::Lower, true),
+            Equality::GTE => (Side::Lower, false),
+            Equality::LT => (Side::Upper, true),
+            Equality::LTE => (Side::Upper, false),
+            _ => return None,
+        };
+        Some(Bound { column, side, strict, value })
+    }
+
+    fn same_column(&self, other: &Bound) -> bool {
+        self.column == other.column
+    }
+
+    /// whether the value is within the bound
+    fn admits(&self, value: &Value) -> bool {
+        match (value.compare(&self.value), self.side) {
+            (Some(Ordering::Equal), Side::Equal) => true,
+            (Some(Ordering::Equal), _) => !self.strict,
+            (Some(Ordering::Greater), Side::Lower) | (Some(Ordering::Less), Side::Upper) => true,
+            _ => false,
+        }
+    }
+
+    /// whether every value within this bound is within the other, of the same side
+    fn implies(&self, other: &Bound) -> bool {
+        match self.value.compare(&other.value) {
+            Some(Ordering::Equal) => self.strict || !other.strict,
+            Some(Ordering::Greater) => self.side == Side::Lower,
+            Some(Ordering::Less) => self.side == Side::Upper,
+            None => false,
+        }
+    }
+}
+
+/// the children without the bounds implied by the others, in an AND the
+/// tightest bounds are kept and in an OR the loosest. None when the bounds of
+/// an AND leave no value
+fn merge_bounds(children: Vec<Expr>, and: bool) -> Option<Vec<Expr>> {
+    let bounds: Vec<Option<Bound>> = children.iter().map(Bound::of).collect();
+    let mut dropped = vec![false; children.len()];
+    for (i, bound) in bounds.iter().enumerate() {
+        let bound = match *bound {
+            Some(ref bound) => bound,
+            None => continue,
+        };
+        for (j, other) in bounds.iter().enumerate() {
+            let other = match *other {
+                Some(ref other) if i != j && !dropped[i] && !dropped[j] && other.same_column(bound) => other,
+                _ => continue,
+            };
+            match (bound.side, other.side) {
+                (Side::Equal, Side::Equal) if and && bound.value.compare(&other.value) != Some(Ordering::Equal) => {
+                    return None;
+                }
+                // an equality within a range: the range goes in an AND, the equality in an OR
+                (Side::Equal, _) => match (other.admits(&bound.value), and) {
+                    (false, true) => return None,
+                    (false, false) => (),
+                    (true, true) => dropped[j] = true,
+                    (true, false) => dropped[i] = true,
+                },
+                (Side::Lower, Side::Upper) if and => match bound.value.compare(&other.value) {
+                    Some(Ordering::Greater) => return None,
+                    Some(Ordering::Equal) if bound.strict || other.strict => return None,
+                    _ => (),
+                },
+                (Side::Lower, Side::Lower) | (Side::Upper, Side::Upper)
+                    if (and && bound.implies(other)) || (!and && other.implies(bound)) => dropped[j] = true,
+                _ => (),
+            }
+        }
+    }
+    Some(children.into_iter().zip(dropped).filter(|&(_, dropped)| !dropped).map(|(child, _)| child).collect())
+}
+
+#[cfg(test)]
+fn simplified(input: &str) -> String {
+    match simplify(&Expr::from(&::parse_filter(input))) {
+        Simplified::Expr(expr) => expr.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_simplify() {
+    assert_eq!(simplified("a=eq.1&b=eq.2&(c=eq.3&a=eq.1)"), "a=eq.1&b=eq.2&c=eq.3");
+    assert_eq!(simplified("a=eq.1|b=eq.2|(b=eq.2|c=eq.3)"), "a=eq.1|b=eq.2|c=eq.3");
+    assert_eq!(simplified("a=eq.1&(a=eq.1|b=eq.2)"), "a=eq.1");
+    assert_eq!(simplified("a=eq.1|a=eq.1&b=eq.2"), "a=eq.1");
+    assert_eq!(simplified("a=gt.5&a=gt.3"), "a=gt.5");
+    assert_eq!(simplified("a=gte.5&a=gt.5&a=lt.9"), "a=gt.5&a=lt.9");
+    assert_eq!(simplified("a=gt.5|a=gt.3"), "a=gt.3");
+    assert_eq!(simplified("a=eq.7&a=gt.5"), "a=eq.7");
+    assert_eq!(simplified("a=eq.7|a=gt.5"), "a=gt.5");
+    // the order of the texts is the one of the collation of the database
+    assert_eq!(simplified("name=gt.b&name=gt.a"), "name=gt.b&name=gt.a");
+    assert_eq!(simplified("a=gt.5&a=gt.x"), "a=gt.5&a=gt.x");
+}
+
+#[test]
+fn test_contradictions() {
+    assert_eq!(simplified("a=eq.1&a=eq.2"), "Contradiction");
+    assert_eq!(simplified("a=eq.1&a=gt.2"), "Contradiction");
+    assert_eq!(simplified("a=gt.5&a=lt.3"), "Contradiction");
+    assert_eq!(simplified("a=gt.5&a=lte.5"), "Contradiction");
+    assert_eq!(simplified("a=gte.5&a=lte.5"), "a=gte.5&a=lte.5");
+    assert_eq!(simplified("name=eq.a&name=eq.A"), "name=eq.a&name=eq.A");
+    assert_eq!(simplified("name=gt.b&name=lt.B"), "name=gt.b&name=lt.B");
+    assert_eq!(simplified("b=eq.1|a=eq.1&a=eq.2"), "b=eq.1");
+    assert_eq!(simplified("b=eq.1&(a=is.null|a=is_not.null)"), "b=eq.1");
+    assert_eq!(simplified("a=is.null|a=is_not.null"), "Tautology");
+    assert_eq!(simplified("a=is.true|a=is_not.true"), "Tautology");
+    assert_eq!(simplified("a=is.x|a=is_not.x"), "a=is.x|a=is_not.x");
+    // unknown for a NULL, which is not a match
+    assert_eq!(simplified("a=lt.5|a=gte.5"), "a=lt.5|a=gte.5");
+    assert_eq!(simplify_filters(&[]), Simplified::Tautology);
+}
+
+#[test]
+fn test_normal_forms() {
+    let expr = Expr::from(&::parse_filter("a=eq.1&(b=eq.2|c=eq.3)"));
+    assert_eq!(to_dnf(&expr, 16).unwrap().to_string(), "a=eq.1&b=eq.2|a=eq.1&c=eq.3");
+    assert_eq!(to_cnf(&expr, 16).unwrap().to_string(), "a=eq.1&(b=eq.2|c=eq.3)");
+    let expr = Expr::from(&::parse_filter("a=eq.1|b=eq.2&c=eq.3"));
+    assert_eq!(to_dnf(&expr, 16).unwrap().to_string(), "a=eq.1|b=eq.2&c=eq.3");
+    assert_eq!(to_cnf(&expr, 16).unwrap().to_string(), "(a=eq.1|b=eq.2)&(a=eq.1|c=eq.3)");
+    let expr = Expr::from(&::parse_filter("a=eq.1&(b=eq.1|b=eq.2)&(c=eq.1|c=eq.2)&(d=eq.1|d=eq.2)"));
+    assert_eq!(to_dnf(&expr, 8).map(|dnf| dnf.conditions().len()), Some(32));
+    assert_eq!(to_dnf(&expr, 7), None);
+    let condition = Expr::from(&::parse_filter("a=eq.1"));
+    assert_eq!(to_cnf(&condition, 1), Some(condition));
+}
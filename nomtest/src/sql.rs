@@ -0,0 +1,511 @@
+//! Translate a `Query` into a sql SELECT statement
+//!
+//! ```sql
+//! SELECT * FROM "users" WHERE "age" < ? ORDER BY "name" DESC LIMIT 10
+//! ```
+//! The values are never written into the sql, they are the parameters of the
+//! statement. The sql is the one sqlite understands, LIKE is expected to be
+//! case sensitive (`PRAGMA case_sensitive_like = ON`), `ilike` and `st` compare
+//! the lowercase of both sides. Joins are reported as unsupported. The full text
+//! searches are those of postgresql, `"body" @@ to_tsquery(?)`, and are unsupported
+//! in the other dialects.
+//! The regular expressions are written for a `Dialect`: `~` in postgresql,
+//! `REGEXP_LIKE` in mysql and `REGEXP` in sqlite, which needs a `regexp` function
+//! such as the one `serve::configure` adds. So are the parameters, `$1`, `$2`...
+//! in postgresql, and the identifiers, `` `name` `` in mysql.
+
+use std::fmt;
+
+use execute;
+use expr::Expr;
+use like::{self, LikePattern};
+use row::Value;
+use {Condition, Direction, Equality, Function, NullsWhere, Operand, Order, Query};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Error {
+    /// the part of the query which has no sql equivalent here
+    Unsupported(String),
+    /// an operand which can not be used at this position, ie: a column on the right of `is`
+    InvalidOperand(Operand),
+    /// the query has no `from`
+    MissingTable,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Unsupported(ref what) => write!(f, "{} is not supported in sql", what),
+            Error::InvalidOperand(ref op) => write!(f, "invalid operand: {:?}", op),
+            Error::MissingTable => write!(f, "the query has no table"),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
+/// the sql of a statement and the values of its parameters, in order
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Statement {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+/// the database the sql is for: the parameters, the quotes, the regular
+/// expressions and the order of the nulls are written the way it takes them
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
+    MySql,
+}
+
+/// the functions which can be called, all aggregates
+const FUNCTIONS: [&str; 5] = ["count", "sum", "avg", "min", "max"];
+
+/// the SELECT of a query, with its range
+pub fn to_select(query: &Query) -> Result<Statement, Error> {
+    to_select_for(query, Dialect::Sqlite)
+}
+
+pub fn to_select_for(query: &Query, dialect: Dialect) -> Result<Statement, Error> {
+    let mut statement = unranged_select(query, dialect)?;
+    if let Some(ref range) = query.range {
+        let (offset, limit) = range.bounds().ok_or_else(|| Error::Unsupported("an unresolved cursor".to_string()))?;
+        statement.sql.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
+    }
+    Ok(statement)
+}
+
+/// the count of the rows, or groups, the query selects without its range
+pub fn to_count(query: &Query) -> Result<Statement, Error> {
+    to_count_for(query, Dialect::Sqlite)
+}
+
+pub fn to_count_for(query: &Query, dialect: Dialect) -> Result<Statement, Error> {
+    let select = unranged_select(query, dialect)?;
+    Ok(Statement {
+        sql: format!("SELECT count(*) FROM ({}) AS selected", select.sql),
+        params: select.params,
+    })
+}
+
+fn unranged_select(query: &Query, dialect: Dialect) -> Result<Statement, Error> {
+    if !query.join.is_empty() {
+        return Err(Error::Unsupported("join".to_string()));
+    }
+    if query.from.is_empty() {
+        return Err(Error::MissingTable);
+    }
+    let mut params = vec![];
+    let columns = if query.group_by.is_empty() {
+        "*".to_string()
+    } else {
+        let mut columns = vec![];
+        for operand in &query.group_by {
+            columns.push(operand_sql(operand, dialect, &mut params)?);
+        }
+        columns.push("count(*)".to_string());
+        for function in execute::aggregates(query) {
+            let call = function_sql(&function, dialect, &mut params)?;
+            columns.push(format!("{} AS {}", call, quote_in(&function.column_name(), dialect)));
+        }
+        columns.join(", ")
+    };
+    let mut tables = vec![];
+    for operand in &query.from {
+        match *operand {
+            Operand::Column(ref table) => tables.push(identifier(table, dialect)),
+            _ => return Err(Error::InvalidOperand(operand.clone())),
+        }
+    }
+    let mut sql = format!("SELECT {} FROM {}", columns, tables.join(", "));
+    if let Some(expr) = Expr::from_filters(&query.filters) {
+        sql.push_str(" WHERE ");
+        sql.push_str(&expr_sql(&expr, dialect, &mut params)?);
+    }
+    if !query.group_by.is_empty() {
+        let mut group_by = vec![];
+        for operand in &query.group_by {
+            group_by.push(operand_sql(operand, dialect, &mut params)?);
+        }
+        sql.push_str(" GROUP BY ");
+        sql.push_str(&group_by.join(", "));
+    }
+    if let Some(expr) = Expr::from_filters(&query.having) {
+        sql.push_str(" HAVING ");
+        sql.push_str(&expr_sql(&expr, dialect, &mut params)?);
+    }
+    if !query.order_by.is_empty() {
+        let mut order_by = vec![];
+        for order in &query.order_by {
+            order_by.push(order_sql(order, dialect, &mut params)?);
+        }
+        sql.push_str(" ORDER BY ");
+        sql.push_str(&order_by.join(", "));
+    }
+    Ok(Statement { sql, params })
+}
+
+/// a quoted identifier, `"name"`
+pub fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// a quoted identifier in the dialect, `` `name` `` in mysql
+fn quote_in(name: &str, dialect: Dialect) -> String {
+    match dialect {
+        Dialect::MySql => format!("`{}`", name.replace('`', "``")),
+        Dialect::Sqlite | Dialect::Postgres => quote(name),
+    }
+}
+
+/// a dotted column is a column of a table, `users.name` is `"users"."name"`
+fn identifier(column: &str, dialect: Dialect) -> String {
+    let parts: Vec<String> = column.split('.').map(|part| quote_in(part, dialect)).collect();
+    parts.join(".")
+}
+
+/// the placeholder of a value added to the parameters, `?` or `$1` in postgresql
+fn param(value: Value, dialect: Dialect, params: &mut Vec<Value>) -> String {
+    params.push(value);
+    match dialect {
+        Dialect::Postgres => format!("${}", params.len()),
+        Dialect::Sqlite | Dialect::MySql => "?".to_string(),
+    }
+}
+
+/// mysql has no NULLS FIRST and NULLS LAST, the nulls are ordered by a key of their own
+fn order_sql(order: &Order, dialect: Dialect, params: &mut Vec<Value>) -> Result<String, Error> {
+    if let (Dialect::MySql, Some(ref nulls_where)) = (dialect, &order.nulls_where) {
+        let nulls = operand_sql(&order.operand, dialect, params)?;
+        let first = if *nulls_where == NullsWhere::FIRST { "DESC" } else { "ASC" };
+        let order = Order { nulls_where: None, ..order.clone() };
+        return Ok(format!("{} IS NULL {}, {}", nulls, first, order_sql(&order, dialect, params)?));
+    }
+    let mut sql = operand_sql(&order.operand, dialect, params)?;
+    match order.direction {
+        Some(Direction::ASC) => sql.push_str(" ASC"),
+        Some(Direction::DESC) => sql.push_str(" DESC"),
+        None => (),
+    }
+    match order.nulls_where {
+        Some(NullsWhere::FIRST) => sql.push_str(" NULLS FIRST"),
+        Some(NullsWhere::LAST) => sql.push_str(" NULLS LAST"),
+        None => (),
+    }
+    Ok(sql)
+}
+
+/// the nested AND and OR are in parentheses
+fn expr_sql(expr: &Expr, dialect: Dialect, params: &mut Vec<Value>) -> Result<String, Error> {
+    let (exprs, connector) = match *expr {
+        Expr::Condition(ref condition) => return condition_sql(condition, dialect, params),
+        Expr::And(ref exprs) => (exprs, " AND "),
+        Expr::Or(ref exprs) => (exprs, " OR "),
+    };
+    let mut parts = vec![];
+    for expr in exprs {
+        let sql = expr_sql(expr, dialect, params)?;
+        match *expr {
+            Expr::Condition(_) => parts.push(sql),
+            Expr::And(_) | Expr::Or(_) => parts.push(format!("({})", sql)),
+        }
+    }
+    Ok(parts.join(connector))
+}
+
+/// `"body" @@ to_tsquery(?, ?)`, the language is the first parameter when there is one.
+/// Only postgresql has these
+fn search_sql(left: &str, function: &str, condition: &Condition, dialect: Dialect, params: &mut Vec<Value>)
+              -> Result<String, Error> {
+    match dialect {
+        Dialect::Postgres => (),
+        Dialect::Sqlite => return Err(Error::Unsupported("the full text search in sqlite".to_string())),
+        Dialect::MySql => return Err(Error::Unsupported("the full text search in mysql".to_string())),
+    }
+    let text = Value::from_operand(&condition.right)
+        .and_then(|value| value.to_text())
+        .ok_or_else(|| Error::InvalidOperand(condition.right.clone()))?;
+    let mut arguments = vec![];
+    if let Some(language) = condition.equality.language() {
+        arguments.push(param(Value::Text(language.to_string()), dialect, params));
+    }
+    arguments.push(param(Value::Text(text), dialect, params));
+    Ok(format!("{} @@ {}({})", left, function, arguments.join(", ")))
+}
+
+/// `"name" ~ ?` in postgresql, `REGEXP_LIKE("name", ?, 'c')` in mysql whatever the
+/// collation, and `"name" REGEXP ?` in sqlite with a `(?i)` flag for `imatch`
+fn pattern_sql(left: &str, condition: &Condition, dialect: Dialect, params: &mut Vec<Value>) -> Result<String, Error> {
+    let pattern = Value::from_operand(&condition.right)
+        .and_then(|value| value.to_text())
+        .ok_or_else(|| Error::InvalidOperand(condition.right.clone()))?;
+    let case_insensitive = condition.equality == Equality::IMATCH;
+    let sql = match dialect {
+        Dialect::Postgres => {
+            let pattern = param(Value::Text(pattern), dialect, params);
+            format!("{} {} {}", left, if case_insensitive { "~*" } else { "~" }, pattern)
+        }
+        Dialect::MySql => {
+            let pattern = param(Value::Text(pattern), dialect, params);
+            format!("REGEXP_LIKE({}, {}, '{}')", left, pattern, if case_insensitive { "i" } else { "c" })
+        }
+        Dialect::Sqlite => {
+            let pattern = if case_insensitive { format!("(?i){}", pattern) } else { pattern };
+            format!("{} REGEXP {}", left, param(Value::Text(pattern), dialect, params))
+        }
+    };
+    Ok(sql)
+}
+
+fn condition_sql(condition: &Condition, dialect: Dialect, params: &mut Vec<Value>) -> Result<String, Error> {
+    let left = operand_sql(&condition.left, dialect, params)?;
+    let right = &condition.right;
+    let sql = match condition.equality {
+        Equality::EQ => format!("{} = {}", left, operand_sql(right, dialect, params)?),
+        Equality::NEQ => format!("{} <> {}", left, operand_sql(right, dialect, params)?),
+        Equality::LT => format!("{} < {}", left, operand_sql(right, dialect, params)?),
+        Equality::LTE => format!("{} <= {}", left, operand_sql(right, dialect, params)?),
+        Equality::GT => format!("{} > {}", left, operand_sql(right, dialect, params)?),
+        Equality::GTE => format!("{} >= {}", left, operand_sql(right, dialect, params)?),
+        Equality::IN => format!("{} IN ({})", left, list_sql(right, dialect, params)?),
+        Equality::NOT_IN => format!("{} NOT IN ({})", left, list_sql(right, dialect, params)?),
+        Equality::IS => format!("{} IS {}", left, is_sql(right)?),
+        Equality::IS_NOT => format!("{} IS NOT {}", left, is_sql(right)?),
+        Equality::LIKE | Equality::ILIKE | Equality::ST => {
+            let pattern = LikePattern::from_condition(&condition.equality, right)
+                .ok_or_else(|| Error::InvalidOperand(right.clone()))?;
+            let placeholder = param(Value::Text(pattern.to_like()), dialect, params);
+            // a backslash escapes in the strings of mysql
+            let escape = match dialect {
+                Dialect::MySql => format!("{}{}", like::ESCAPE, like::ESCAPE),
+                Dialect::Sqlite | Dialect::Postgres => like::ESCAPE.to_string(),
+            };
+            if pattern.case_insensitive {
+                format!("lower({}) LIKE lower({}) ESCAPE '{}'", left, placeholder, escape)
+            } else {
+                format!("{} LIKE {} ESCAPE '{}'", left, placeholder, escape)
+            }
+        }
+        Equality::FTS(_) => search_sql(&left, "to_tsquery", condition, dialect, params)?,
+        Equality::PLFTS(_) => search_sql(&left, "plainto_tsquery", condition, dialect, params)?,
+        Equality::PHFTS(_) => search_sql(&left, "phraseto_tsquery", condition, dialect, params)?,
+        Equality::WFTS(_) => search_sql(&left, "websearch_to_tsquery", condition, dialect, params)?,
+        Equality::MATCH | Equality::IMATCH => pattern_sql(&left, condition, dialect, params)?,
+    };
+    Ok(sql)
+}
+
+fn operand_sql(operand: &Operand, dialect: Dialect, params: &mut Vec<Value>) -> Result<String, Error> {
+    match *operand {
+        Operand::Column(ref column) => Ok(identifier(column, dialect)),
+        Operand::Function(ref function) => function_sql(function, dialect, params),
+        _ => {
+            let value = Value::from_operand(operand).ok_or_else(|| Error::InvalidOperand(operand.clone()))?;
+            Ok(param(value, dialect, params))
+        }
+    }
+}
+
+/// only the aggregates can be called, `*` is the only parameter which is not a value
+fn function_sql(function: &Function, dialect: Dialect, params: &mut Vec<Value>) -> Result<String, Error> {
+    let name = function.function.to_lowercase();
+    if !FUNCTIONS.contains(&name.as_str()) {
+        return Err(Error::Unsupported(format!("the function {}", function.function)));
+    }
+    let mut args = vec![];
+    for param in &function.params {
+        match *param {
+            Operand::Value(ref v) if v == "*" => args.push("*".to_string()),
+            _ => args.push(operand_sql(param, dialect, params)?),
+        }
+    }
+    if args.is_empty() {
+        args.push("*".to_string());
+    }
+    Ok(format!("{}({})", name, args.join(", ")))
+}
+
+fn list_sql(operand: &Operand, dialect: Dialect, params: &mut Vec<Value>) -> Result<String, Error> {
+    let mut items = vec![];
+    for item in operand.list_items() {
+        items.push(operand_sql(&item, dialect, params)?);
+    }
+    Ok(items.join(", "))
+}
+
+/// the right side of `is` and `is_not`: NULL, TRUE or FALSE
+fn is_sql(operand: &Operand) -> Result<&'static str, Error> {
+    match *operand {
+        Operand::Value(ref v) if v.eq_ignore_ascii_case("null") => Ok("NULL"),
+        Operand::Boolean(true) => Ok("TRUE"),
+        Operand::Boolean(false) => Ok("FALSE"),
+        _ => Err(Error::InvalidOperand(operand.clone())),
+    }
+}
+
+#[cfg(test)]
+fn select(input: &str) -> Statement {
+    let mut query = ::parse_query(input).unwrap();
+    query.from = vec![Operand::Column("users".to_string())];
+    to_select(&query).unwrap()
+}
+
+#[test]
+fn test_where() {
+    let statement = select("age=lt.20&(product=eq.134|price=lte.100.5)&name=neq.技術通報");
+    assert_eq!(statement.sql,
+               "SELECT * FROM \"users\" WHERE \"age\" < ? AND (\"product\" = ? OR \"price\" <= ?) \
+                AND \"name\" <> ?");
+    assert_eq!(statement.params,
+               vec![Value::Number(20.0),
+                    Value::Number(134.0),
+                    Value::Number(100.5),
+                    Value::Text("技術通報".to_string())]);
+}
+
+#[test]
+fn test_in_is_and_patterns() {
+    let statement = select("tag=in.red,green&id=not_in.1,2&deleted_at=is.null&active=is_not.true");
+    assert_eq!(statement.sql,
+               "SELECT * FROM \"users\" WHERE \"tag\" IN (?, ?) AND \"id\" NOT IN (?, ?) \
+                AND \"deleted_at\" IS NULL AND \"active\" IS NOT TRUE");
+    assert_eq!(statement.params.len(), 4);
+    let statement = select("title=like.*50%_off*&name=st.jo&nick=ilike.*Bob");
+    assert_eq!(statement.sql,
+               "SELECT * FROM \"users\" WHERE \"title\" LIKE ? ESCAPE '\\' \
+                AND lower(\"name\") LIKE lower(?) ESCAPE '\\' AND lower(\"nick\") LIKE lower(?) ESCAPE '\\'");
+    assert_eq!(statement.params,
+               vec![Value::Text("%50\\%\\_off%".to_string()),
+                    Value::Text("jo%".to_string()),
+                    Value::Text("%Bob".to_string())]);
+    assert_eq!(to_select(&::parse_query("a=is.1&from=t").unwrap()),
+               Err(Error::InvalidOperand(Operand::Number(1.0))));
+}
+
+#[test]
+fn test_full_text() {
+    let select = |input: &str| {
+        let mut query = ::parse_query(input).unwrap();
+        query.from = vec![Operand::Column("users".to_string())];
+        to_select_for(&query, Dialect::Postgres).unwrap()
+    };
+    let statement = select("body=fts(english).fat&cat&title=wfts.\"fat cat\" -dog");
+    assert_eq!(statement.sql,
+               "SELECT * FROM \"users\" WHERE \"body\" @@ to_tsquery($1, $2) \
+                AND \"title\" @@ websearch_to_tsquery($3)");
+    assert_eq!(statement.params,
+               vec![Value::Text("english".to_string()),
+                    Value::Text("fat&cat".to_string()),
+                    Value::Text("\"fat cat\" -dog".to_string())]);
+    assert_eq!(select("a=plfts.x|b=phfts(simple).x y").sql,
+               "SELECT * FROM \"users\" WHERE \"a\" @@ plainto_tsquery($1) OR \"b\" @@ phraseto_tsquery($2, $3)");
+    let query = ::parse_query("body=fts.fat&from=users").unwrap();
+    assert_eq!(to_select(&query), Err(Error::Unsupported("the full text search in sqlite".to_string())));
+    assert_eq!(to_select_for(&query, Dialect::MySql),
+               Err(Error::Unsupported("the full text search in mysql".to_string())));
+}
+
+#[test]
+fn test_dialects() {
+    let query = ::parse_query("a.b=eq.1&name=like.jo*&group_by=dept&having=max(age)=gt.2\
+                               &order_by=dept.desc.nulls_last,max(age)&from=users").unwrap();
+    assert_eq!(to_select_for(&query, Dialect::Postgres).unwrap().sql,
+               "SELECT \"dept\", count(*), max(\"age\") AS \"max(age)\" FROM \"users\" \
+                WHERE \"a\".\"b\" = $1 AND \"name\" LIKE $2 ESCAPE '\\' GROUP BY \"dept\" \
+                HAVING max(\"age\") > $3 ORDER BY \"dept\" DESC NULLS LAST, max(\"age\")");
+    let statement = to_select_for(&query, Dialect::MySql).unwrap();
+    assert_eq!(statement.sql,
+               "SELECT `dept`, count(*), max(`age`) AS `max(age)` FROM `users` \
+                WHERE `a`.`b` = ? AND `name` LIKE ? ESCAPE '\\\\' GROUP BY `dept` \
+                HAVING max(`age`) > ? ORDER BY `dept` IS NULL ASC, `dept` DESC, max(`age`)");
+    assert_eq!(statement.params.len(), 3);
+    let query = ::parse_query("a=eq.1&limit=2&from=users").unwrap();
+    assert_eq!(to_count_for(&query, Dialect::Postgres).unwrap().sql,
+               "SELECT count(*) FROM (SELECT * FROM \"users\" WHERE \"a\" = $1) AS selected");
+    assert_eq!(to_select_for(&query, Dialect::MySql).unwrap().sql,
+               "SELECT * FROM `users` WHERE `a` = ? LIMIT 2 OFFSET 0");
+}
+
+#[test]
+fn test_regular_expressions() {
+    let mut query = ::parse_query("name=match.^Jo(hn|e)$|nick=imatch.bob").unwrap();
+    query.from = vec![Operand::Column("users".to_string())];
+    let statement = to_select_for(&query, Dialect::Postgres).unwrap();
+    assert_eq!(statement.sql, "SELECT * FROM \"users\" WHERE \"name\" ~ $1 OR \"nick\" ~* $2");
+    assert_eq!(statement.params,
+               vec![Value::Text("^Jo(hn|e)$".to_string()), Value::Text("bob".to_string())]);
+    assert_eq!(to_select_for(&query, Dialect::MySql).unwrap().sql,
+               "SELECT * FROM `users` WHERE REGEXP_LIKE(`name`, ?, 'c') OR REGEXP_LIKE(`nick`, ?, 'i')");
+    let statement = to_select(&query).unwrap();
+    assert_eq!(statement.sql, "SELECT * FROM \"users\" WHERE \"name\" REGEXP ? OR \"nick\" REGEXP ?");
+    assert_eq!(statement.params,
+               vec![Value::Text("^Jo(hn|e)$".to_string()), Value::Text("(?i)bob".to_string())]);
+}
+
+#[test]
+fn test_relative_times() {
+    use relative::{self, FixedClock};
+    let mut query = ::parse_query("created_at=gt.now-7d&due=in.@today,@today+1w").unwrap();
+    query.from = vec![Operand::Column("tasks".to_string())];
+    // 2024-01-31T13:45:00Z
+    let statement = to_select(&relative::resolve(&query, &FixedClock(1706708700))).unwrap();
+    assert_eq!(statement.sql, "SELECT * FROM \"tasks\" WHERE \"created_at\" > ? AND \"due\" IN (?, ?)");
+    assert_eq!(statement.params,
+               vec![Value::Text("2024-01-24T13:45:00Z".to_string()),
+                    Value::Text("2024-01-31".to_string()),
+                    Value::Text("2024-02-07".to_string())]);
+    // the clock of the system when the query is not resolved
+    assert_eq!(to_select(&query).unwrap().params.len(), 3);
+}
+
+#[test]
+fn test_order_and_range() {
+    assert_eq!(select("order_by=name.desc.nulls_last,users.age&page=3&page_size=20").sql,
+               "SELECT * FROM \"users\" ORDER BY \"name\" DESC NULLS LAST, \"users\".\"age\" \
+                LIMIT 20 OFFSET 40");
+    let mut query = ::parse_query("age=gt.1&limit=10").unwrap();
+    query.from = vec![Operand::Column("users".to_string())];
+    assert_eq!(to_count(&query).unwrap().sql,
+               "SELECT count(*) FROM (SELECT * FROM \"users\" WHERE \"age\" > ?) AS selected");
+}
+
+#[test]
+fn test_group_by() {
+    let statement = select("group_by=dept&having=sum(salary)=gt.100&order_by=count(*).desc");
+    assert_eq!(statement.sql,
+               "SELECT \"dept\", count(*), sum(\"salary\") AS \"sum(salary)\" FROM \"users\" \
+                GROUP BY \"dept\" HAVING sum(\"salary\") > ? ORDER BY count(*) DESC");
+    let mut query = ::parse_query("having=drop(x)=eq.1&group_by=a").unwrap();
+    query.from = vec![Operand::Column("users".to_string())];
+    assert_eq!(to_select(&query), Err(Error::Unsupported("the function drop".to_string())));
+    assert_eq!(to_select(&Query::default()), Err(Error::MissingTable));
+}
+
+#[test]
+fn test_keyset() {
+    use keyset::{self, Signer};
+    use row::Value as RowValue;
+
+    let signer = Signer::new(b"secret");
+    let order_by = ::parse_query("order_by=name.desc,id").unwrap().order_by;
+    let cursor = signer.sign(&order_by, &[RowValue::Text("John".to_string()), RowValue::Number(7.0)]);
+    let mut query = ::parse_query(&format!("age=lt.20&order_by=name.desc,id&after={}&limit=10", cursor)).unwrap();
+    query.from = vec![Operand::Column("users".to_string())];
+    assert_eq!(to_select(&query), Err(Error::Unsupported("an unresolved cursor".to_string())));
+    let statement = to_select(&keyset::resolve(&query, &signer).unwrap().query).unwrap();
+    assert_eq!(statement.sql,
+               "SELECT * FROM \"users\" WHERE \"age\" < ? AND (\"name\" < ? OR (\"name\" = ? AND \
+                (\"id\" > ? OR \"id\" IS NULL))) ORDER BY \"name\" DESC NULLS FIRST, \"id\" ASC NULLS LAST \
+                LIMIT 10 OFFSET 0");
+    assert_eq!(statement.params, vec![RowValue::Number(20.0), RowValue::Text("John".to_string()),
+                                      RowValue::Text("John".to_string()), RowValue::Number(7.0)]);
+}
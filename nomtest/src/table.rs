@@ -0,0 +1,164 @@
+//! Tables read from csv files
+//!
+//! The csv has a header line with the column names, the type of each column
+//! is inferred from a sample of the rows: a column is a number or a boolean
+//! if all of its non-empty values in the sample are, otherwise it is text.
+//! Empty values are NULL.
+
+use std::io;
+use std::rc::Rc;
+
+use csv;
+
+use row::{Row, Value};
+
+/// the number of rows looked at to infer the column types
+pub const SAMPLE_SIZE: usize = 100;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum ColumnType {
+    Bool,
+    Number,
+    Text,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Table {
+    pub columns: Rc<Vec<String>>,
+    pub types: Vec<ColumnType>,
+    pub rows: Vec<TableRow>,
+}
+
+/// a row of a table, the column names are shared with the table
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct TableRow {
+    columns: Rc<Vec<String>>,
+    values: Vec<Value>,
+}
+
+impl TableRow {
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+impl Row for TableRow {
+    fn get(&self, column: &str) -> Option<Value> {
+        self.columns.iter().position(|c| c == column).and_then(|i| self.values.get(i).cloned())
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.columns.to_vec()
+    }
+}
+
+/// read a whole csv into a table
+pub fn read_csv<R: io::Read>(reader: R) -> Result<Table, csv::Error> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let columns: Vec<String> = reader.headers()?.iter().map(|h| h.trim().to_string()).collect();
+    let mut records = vec![];
+    for record in reader.records() {
+        records.push(record?);
+    }
+    let types: Vec<ColumnType> = (0..columns.len())
+        .map(|i| infer_type(records.iter().take(SAMPLE_SIZE).filter_map(|r| r.get(i))))
+        .collect();
+    let columns = Rc::new(columns);
+    let rows = records.iter()
+        .map(|record| {
+            let values = types.iter()
+                .enumerate()
+                .map(|(i, column_type)| typed_value(record.get(i).unwrap_or(""), *column_type))
+                .collect();
+            TableRow {
+                columns: columns.clone(),
+                values,
+            }
+        })
+        .collect();
+    Ok(Table {
+        columns,
+        types,
+        rows,
+    })
+}
+
+fn infer_type<'a, I: Iterator<Item = &'a str>>(values: I) -> ColumnType {
+    let mut column_type = None;
+    for value in values.filter(|v| !v.is_empty()) {
+        let value_type = match typed_value(value, ColumnType::Number) {
+            Value::Number(_) => ColumnType::Number,
+            _ if value == "true" || value == "false" => ColumnType::Bool,
+            _ => return ColumnType::Text,
+        };
+        match column_type {
+            None => column_type = Some(value_type),
+            Some(t) if t != value_type => return ColumnType::Text,
+            Some(_) => (),
+        }
+    }
+    column_type.unwrap_or(ColumnType::Text)
+}
+
+/// the value of a csv field, a value past the sample which does not read as
+/// the column type is kept as text
+fn typed_value(field: &str, column_type: ColumnType) -> Value {
+    if field.is_empty() {
+        return Value::Null;
+    }
+    match column_type {
+        ColumnType::Number => {
+            match field.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => Value::Number(n),
+                _ => Value::Text(field.to_string()),
+            }
+        }
+        ColumnType::Bool => {
+            match field {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::Text(field.to_string()),
+            }
+        }
+        ColumnType::Text => Value::Text(field.to_string()),
+    }
+}
+
+#[test]
+fn test_read_csv() {
+    let csv = "name,age,active,zip\nJohn,30,true,01234\n技術通報,,false,A-1\n";
+    let table = read_csv(csv.as_bytes()).unwrap();
+    assert_eq!(*table.columns, vec!["name", "age", "active", "zip"]);
+    assert_eq!(table.types,
+               vec![ColumnType::Text, ColumnType::Number, ColumnType::Bool, ColumnType::Text]);
+    assert_eq!(table.rows[0].get("age"), Some(Value::Number(30.0)));
+    assert_eq!(table.rows[0].get("zip"), Some(Value::Text("01234".to_string())));
+    assert_eq!(table.rows[1].get("age"), Some(Value::Null));
+    assert_eq!(table.rows[1].get("name"), Some(Value::Text("技術通報".to_string())));
+    assert_eq!(table.rows[1].get("active"), Some(Value::Bool(false)));
+    assert_eq!(table.rows[1].get("missing"), None);
+}
+
+#[test]
+fn test_query_csv() {
+    use execute::{execute, Record};
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/people.csv");
+    let table = read_csv(::std::fs::File::open(path).unwrap()).unwrap();
+    let query = ::parse_query("age=lt.40&order_by=name.desc&limit=2").unwrap();
+    let result = execute(&query, table.rows).unwrap();
+    assert_eq!(result.total, 3);
+    let names: Vec<Option<Value>> = result.rows.iter().map(|r| r.get("name")).collect();
+    assert_eq!(names,
+               vec![Some(Value::Text("John".to_string())), Some(Value::Text("Jane".to_string()))]);
+    assert!(result.rows.iter().all(|r| match *r {
+        Record::Row(_) => true,
+        Record::Group(_) => false,
+    }));
+}
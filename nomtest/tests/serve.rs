@@ -0,0 +1,54 @@
+extern crate nomtest;
+extern crate rusqlite;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+
+use nomtest::serve::Server;
+
+/// a server over a fresh database, running in its own thread, and its port
+fn start(name: &str) -> u16 {
+    let path = format!("{}/{}.db", env!("CARGO_TARGET_TMPDIR"), name);
+    let _ = std::fs::remove_file(&path);
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    connection.execute_batch("CREATE TABLE users (name TEXT, age INTEGER);
+                              INSERT INTO users VALUES ('John', 30), ('Jane', 17), ('Joe', 12);")
+        .unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let server = Server::open(&path, "127.0.0.1:0").unwrap();
+        sender.send(server.port().unwrap()).unwrap();
+        server.run();
+    });
+    receiver.recv().unwrap()
+}
+
+/// the status line, the headers and the body of the response
+fn get(port: u16, target: &str) -> (String, String, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", target).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_at(response.find("\r\n\r\n").unwrap());
+    let (status, headers) = head.split_at(head.find("\r\n").unwrap());
+    (status.to_string(), headers.to_lowercase(), body[4..].to_string())
+}
+
+#[test]
+fn test_serve() {
+    let port = start("serve");
+    let (status, headers, body) = get(port, "/users?age=lt.20&order_by=name&limit=1");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(headers.contains("content-range: 0-0/2"), "{}", headers);
+    assert!(headers.contains("content-type: application/json"), "{}", headers);
+    assert_eq!(body, r#"[{"name":"Jane","age":17}]"#);
+
+    let (status, _, body) = get(port, "/users?age=lt.20&&");
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    assert!(body.contains("syntax error"), "{}", body);
+
+    let (status, _, _) = get(port, "/nothing");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+}